@@ -31,7 +31,7 @@ pub struct Program {
 #[derive(Debug)]
 pub enum Item {
     ConstantDef(ConstantDef),
-    Directive(Directive),
+    Directive(Spanned<Directive>),
     Label(String),
     Instruction(Instruction),
 }
@@ -96,12 +96,15 @@ pub struct ConstantDef {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Directive {
     Text,
+    KText,
     Global {
         label: String,
     },
     Data,
+    KData,
     RData,
     SData,
     Align {
@@ -114,6 +117,10 @@ pub enum Directive {
         ty: NumberDirective,
         values: Vec<RepeatedExpr>,
     },
+    FloatDirective {
+        ty: FloatDirective,
+        values: Vec<f64>,
+    },
     Ascii {
         string: String,
         zero_pad: bool,
@@ -127,6 +134,12 @@ pub enum NumberDirective {
     Word,
 }
 
+#[derive(Debug)]
+pub enum FloatDirective {
+    Float,
+    Double,
+}
+
 #[derive(Debug)]
 pub enum Instruction {
     RType {
@@ -146,6 +159,27 @@ pub enum Instruction {
         op_code: JTypeOp,
         label: Expr,
     },
+    /// A coprocessor 0 instruction. rd is the coprocessor register.
+    Cop0 {
+        op_code: Cop0Op,
+        rt: Register,
+        rd: Register,
+    },
+    /// A coprocessor 1 (floating point) operation. Registers which are not
+    /// used by the operation are $f0.
+    Cop1 {
+        op_code: Cop1Op,
+        format: FloatFormat,
+        fd: Register,
+        fs: Register,
+        ft: Register,
+    },
+    /// A move between a general purpose register and a coprocessor 1 register
+    Cop1Move {
+        op_code: Cop1MoveOp,
+        rt: Register,
+        fs: Register,
+    },
     Pseudo(PseudoInstruction),
 }
 
@@ -261,6 +295,10 @@ pub enum ITypeOp {
     Swl,
     Swr,
     Xori,
+    /// Branch on the floating point condition flag (bc1f/bc1t)
+    Bc1,
+    Lwc1,
+    Swc1,
 }
 
 impl ITypeOp {
@@ -268,9 +306,23 @@ impl ITypeOp {
     pub fn needs_offset(&self) -> bool {
         matches!(
             self,
-            ITypeOp::Bcond | ITypeOp::Beq | ITypeOp::Bne | ITypeOp::Bgtz | ITypeOp::Blez
+            ITypeOp::Bcond
+                | ITypeOp::Beq
+                | ITypeOp::Bne
+                | ITypeOp::Bgtz
+                | ITypeOp::Blez
+                | ITypeOp::Bc1
         )
     }
+
+    /// Get the index of the instruction's rt register. The coprocessor 1 loads
+    /// and stores use a floating point register.
+    pub fn rt_index(&self, rt: &Register) -> Option<u8> {
+        match self {
+            ITypeOp::Lwc1 | ITypeOp::Swc1 => rt.float_index(),
+            _ => rt.index(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -279,6 +331,45 @@ pub enum JTypeOp {
     Jal,
 }
 
+#[derive(Debug)]
+pub enum Cop0Op {
+    Mfc0,
+    Mtc0,
+    Rfe,
+}
+
+#[derive(Debug)]
+pub enum Cop1Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Abs,
+    Mov,
+    Neg,
+    CvtS,
+    CvtD,
+    CvtW,
+    CEq,
+    CLt,
+    CLe,
+}
+
+/// The format of a coprocessor 1 operation's operands. For conversions, this
+/// is the format being converted from.
+#[derive(Debug, Eq, PartialEq)]
+pub enum FloatFormat {
+    Single,
+    Double,
+    Word,
+}
+
+#[derive(Debug)]
+pub enum Cop1MoveOp {
+    Mfc1,
+    Mtc1,
+}
+
 #[derive(Debug)]
 pub enum Register {
     Number(u8),
@@ -286,6 +377,24 @@ pub enum Register {
 }
 
 impl Register {
+    /// Create a floating point register ($fN)
+    pub fn float(index: u8) -> Self {
+        Register::Name(format!("f{}", index))
+    }
+
+    /// Get the floating point register index ($f0 to $f31). If the register
+    /// is not a floating point register, None is returned.
+    pub fn float_index(&self) -> Option<u8> {
+        match self {
+            Register::Name(name) => name
+                .strip_prefix('f')
+                .filter(|index| index.bytes().all(|byte| byte.is_ascii_digit()))
+                .and_then(|index| index.parse().ok())
+                .filter(|index| *index < 32),
+            Register::Number(_) => None,
+        }
+    }
+
     /// Get the register index. If the register is invalid, None is returned.
     pub fn index(&self) -> Option<u8> {
         match self {
@@ -1,6 +1,6 @@
 //! Intermediate Representation
 
-use crate::ast::{ITypeOp, JTypeOp, RTypeOp};
+use crate::ast::{Cop0Op, Cop1MoveOp, Cop1Op, FloatFormat, ITypeOp, JTypeOp, RTypeOp};
 use mips_types::string_table::StringTable;
 use std::collections::HashMap;
 
@@ -14,6 +14,8 @@ pub struct IrProgram {
     pub relocation: Vec<RelocationEntry>,
     pub references: Vec<ReferenceEntry>,
     pub string_table: StringTable,
+    /// The text and data are kernel sections (.ktext/.kdata)
+    pub kernel: bool,
 }
 
 #[derive(Debug)]
@@ -35,6 +37,24 @@ pub enum IrInstruction {
         op_code: JTypeOp,
         pseudo_address: u32,
     },
+    Cop0 {
+        op_code: Cop0Op,
+        rt: u8,
+        rd: u8,
+    },
+    /// A coprocessor 1 operation. fd, fs, and ft are floating point registers.
+    Cop1 {
+        op_code: Cop1Op,
+        format: FloatFormat,
+        fd: u8,
+        fs: u8,
+        ft: u8,
+    },
+    Cop1Move {
+        op_code: Cop1MoveOp,
+        rt: u8,
+        fs: u8,
+    },
     Word(u32),
 }
 
@@ -1,8 +1,9 @@
 //! Lower the AST to IR
 
 use crate::ast::{
-    ConstantDef, Directive, Expr, ExprData, ITypeOp, Instruction, Item, NumberDirective, Operation,
-    Program, PseudoInstruction, RTypeOp, Register, RepeatedExpr, Span,
+    ConstantDef, Cop1Op, Directive, Expr, ExprData, FloatDirective, FloatFormat, ITypeOp,
+    Instruction, Item, NumberDirective, Operation, Program, PseudoInstruction, RTypeOp, Register,
+    RepeatedExpr, Span, Spanned,
 };
 use crate::ir::{
     IrInstruction, IrProgram, ReferenceEntry, ReferenceMethod, ReferenceTarget, ReferenceType,
@@ -11,7 +12,6 @@ use crate::ir::{
 use crate::string_unescape::unescape_str;
 use either::Either;
 use mips_types::string_table::StringTable;
-use std::array::IntoIter;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt::{Display, LowerHex};
@@ -33,12 +33,14 @@ pub enum IrBuildError {
     // UnexpectedForwardReference,
     #[error("Unknown constant")]
     UnknownConstant(Span),
+    #[error("Cannot mix kernel (.ktext/.kdata) and user sections in one file")]
+    MixedKernelSections(Span),
 }
 
 impl IrBuildError {
     pub fn span(&self) -> Span {
         match self {
-            IrBuildError::UnknownConstant(span) => *span,
+            IrBuildError::UnknownConstant(span) | IrBuildError::MixedKernelSections(span) => *span,
         }
     }
 }
@@ -92,6 +94,8 @@ struct IrBuilder {
     auto_align: bool,
     current_label: Option<String>,
     seen_globals: HashSet<String>,
+    /// Set once a section directive decides if this is a kernel module
+    kernel: Option<bool>,
 }
 
 impl Default for IrBuilder {
@@ -112,6 +116,7 @@ impl Default for IrBuilder {
             auto_align: true,
             current_label: None,
             seen_globals: HashSet::new(),
+            kernel: None,
         }
     }
 }
@@ -136,6 +141,7 @@ impl IrBuilder {
             relocation: self.relocation,
             references: self.references,
             string_table: self.string_table,
+            kernel: self.kernel.unwrap_or(false),
         })
     }
 
@@ -161,7 +167,7 @@ impl IrBuilder {
                     label_buffer = Some(label.clone());
                     self.visit_label(label);
                 }
-                Item::Directive(directive) => self.visit_directive(directive),
+                Item::Directive(directive) => self.visit_directive(directive)?,
                 Item::Instruction(instruction) => {
                     self.text_offset += 4 * instruction.expanded_size(&self.constants);
                 }
@@ -179,7 +185,7 @@ impl IrBuilder {
 
         for item in program.items {
             // Add in the text words we found in the first pass
-            while let Some(word) = self.text_words.remove(&self.instructions.len()) {
+            while let Some(word) = self.text_words.remove(&self.text_offset) {
                 self.instructions.push(IrInstruction::Word(word));
                 self.text_offset += 4;
             }
@@ -241,12 +247,15 @@ impl IrBuilder {
         }
     }
 
-    fn visit_directive(&mut self, directive: &Directive) {
-        match directive {
-            Directive::Text => self.set_section(BuilderLocation::Text),
-            Directive::Data => self.set_section(BuilderLocation::Data),
-            Directive::RData => self.set_section(BuilderLocation::RData),
-            Directive::SData => self.set_section(BuilderLocation::SData),
+    fn visit_directive(&mut self, directive: &Spanned<Directive>) -> Result<(), IrBuildError> {
+        let span = directive.span;
+        match &directive.data {
+            Directive::Text => self.set_section(BuilderLocation::Text, false, span)?,
+            Directive::KText => self.set_section(BuilderLocation::Text, true, span)?,
+            Directive::Data => self.set_section(BuilderLocation::Data, false, span)?,
+            Directive::KData => self.set_section(BuilderLocation::Data, true, span)?,
+            Directive::RData => self.set_section(BuilderLocation::RData, false, span)?,
+            Directive::SData => self.set_section(BuilderLocation::SData, false, span)?,
             Directive::Global { label } => self.visit_global(label),
             Directive::Align { boundary } => self.visit_align(boundary),
             Directive::Space { size } => self.visit_space(size),
@@ -255,8 +264,14 @@ impl IrBuilder {
                 NumberDirective::Half => self.visit_half(values),
                 NumberDirective::Byte => self.visit_byte(values),
             },
+            Directive::FloatDirective { ty, values } => match ty {
+                FloatDirective::Float => self.visit_float(values),
+                FloatDirective::Double => self.visit_double(values),
+            },
             Directive::Ascii { string, zero_pad } => self.visit_ascii(string, *zero_pad),
         }
+
+        Ok(())
     }
 
     fn visit_global(&mut self, label: &str) {
@@ -323,7 +338,7 @@ impl IrBuilder {
             .evaluate(&self.constants)
             .expect(".space cannot have forward references") as usize;
 
-        section_data.extend(iter::repeat(0).take(size));
+        section_data.extend(iter::repeat_n(0, size));
     }
 
     fn visit_byte(&mut self, values: &[RepeatedExpr]) {
@@ -350,7 +365,7 @@ impl IrBuilder {
         self.extend_with_numbers(
             numbers,
             |_, _| panic!("Cannot use .half in the text segment"),
-            |half| IntoIter::new(half.to_be_bytes()),
+            |half| half.to_be_bytes(),
         )
     }
 
@@ -434,10 +449,30 @@ impl IrBuilder {
                     builder.text_offset += 4;
                 }
             },
-            |word| IntoIter::new(word.to_be_bytes()),
+            |word| word.to_be_bytes(),
         );
     }
 
+    fn visit_float(&mut self, values: &[f64]) {
+        self.auto_align(2);
+
+        self.extend_with_numbers(
+            values.iter().map(|&value| value as f32).collect(),
+            |_, _| panic!("Cannot use .float in the text segment"),
+            |value| value.to_be_bytes(),
+        )
+    }
+
+    fn visit_double(&mut self, values: &[f64]) {
+        self.auto_align(3);
+
+        self.extend_with_numbers(
+            values.to_vec(),
+            |_, _| panic!("Cannot use .double in the text segment"),
+            |value| value.to_be_bytes(),
+        )
+    }
+
     fn auto_align(&mut self, alignment: usize) {
         if self.auto_align && self.current_section != SymbolLocation::Text {
             self.align_section(alignment);
@@ -491,9 +526,20 @@ impl IrBuilder {
         }
     }
 
-    fn set_section(&mut self, location: BuilderLocation) {
+    fn set_section(
+        &mut self,
+        location: BuilderLocation,
+        kernel: bool,
+        span: Span,
+    ) -> Result<(), IrBuildError> {
+        if self.kernel.is_some_and(|current| current != kernel) {
+            return Err(IrBuildError::MixedKernelSections(span));
+        }
+
+        self.kernel = Some(kernel);
         self.auto_align = true;
         self.current_section = location;
+        Ok(())
     }
 
     /// Aligns the current section according to the alignment value. If there
@@ -509,7 +555,7 @@ impl IrBuilder {
 
         if section.len() % step_size != 0 {
             let alignment_amount = step_size - (section.len() % step_size);
-            section.extend(iter::repeat(0).take(alignment_amount));
+            section.extend(iter::repeat_n(0, alignment_amount));
 
             // If there was a label pointing at this directive, realign it
             if let Some(label) = &self.current_label {
@@ -588,7 +634,7 @@ impl RepeatedExpr {
 
     fn get_const_value(&self, constants: &Constants, directive: &'static str) -> i64 {
         self.expr
-            .evaluate(&constants)
+            .evaluate(constants)
             .unwrap_or_else(|_| panic!("{} cannot have forward references", directive))
     }
 
@@ -603,7 +649,7 @@ impl RepeatedExpr {
     ) -> impl Iterator<Item = T> {
         let times = self
             .times
-            .evaluate(&constants)
+            .evaluate(constants)
             .unwrap_or_else(|_| panic!("{} cannot have forward references", directive))
             as usize; // FIXME: check for negative repeat value
 
@@ -620,7 +666,7 @@ impl RepeatedExpr {
             );
         }
 
-        iter::repeat(truncated).take(times)
+        iter::repeat_n(truncated, times)
     }
 }
 
@@ -628,7 +674,12 @@ impl Instruction {
     /// Get the number of instructions this instruction expands to
     fn expanded_size(&self, constants: &Constants) -> usize {
         match self {
-            Instruction::RType { .. } | Instruction::IType { .. } | Instruction::JType { .. } => 1,
+            Instruction::RType { .. }
+            | Instruction::IType { .. }
+            | Instruction::JType { .. }
+            | Instruction::Cop0 { .. }
+            | Instruction::Cop1 { .. }
+            | Instruction::Cop1Move { .. } => 1,
             Instruction::Pseudo(pseudo) => pseudo.expanded_size(constants),
         }
     }
@@ -690,9 +741,9 @@ impl Instruction {
                 };
 
                 vec![IrInstruction::IType {
-                    op_code,
                     rs: rs.index().unwrap(),
-                    rt: rt.index().unwrap(),
+                    rt: op_code.rt_index(&rt).unwrap(),
+                    op_code,
                     immediate: offset,
                 }]
             }
@@ -704,9 +755,9 @@ impl Instruction {
             } => {
                 // Non-offset based I-type instructions
                 vec![IrInstruction::IType {
-                    op_code,
                     rs: rs.index().unwrap(),
-                    rt: rt.index().unwrap(),
+                    rt: op_code.rt_index(&rt).unwrap(),
+                    op_code,
                     // FIXME: make sure the constant is not too big
                     immediate: immediate.evaluate(&builder.constants).unwrap() as i16,
                 }]
@@ -736,6 +787,49 @@ impl Instruction {
                     pseudo_address,
                 }]
             }
+            Instruction::Cop0 { op_code, rt, rd } => vec![IrInstruction::Cop0 {
+                op_code,
+                rt: rt.index().unwrap(),
+                rd: rd.index().unwrap(),
+            }],
+            Instruction::Cop1 {
+                op_code,
+                format,
+                fd,
+                fs,
+                ft,
+            } => {
+                let fd = fd.float_index().unwrap();
+                let fs = fs.float_index().unwrap();
+                let ft = ft.float_index().unwrap();
+
+                // Double precision values are held in even/odd register pairs
+                let double_result = match op_code {
+                    Cop1Op::CvtS | Cop1Op::CvtW => false,
+                    Cop1Op::CvtD => true,
+                    _ => format == FloatFormat::Double,
+                };
+                let double_operands = format == FloatFormat::Double;
+                if (double_result && fd % 2 != 0)
+                    || (double_operands && (fs % 2 != 0 || ft % 2 != 0))
+                {
+                    // TODO: return a proper error
+                    panic!("Double precision operations must use even registers");
+                }
+
+                vec![IrInstruction::Cop1 {
+                    op_code,
+                    format,
+                    fd,
+                    fs,
+                    ft,
+                }]
+            }
+            Instruction::Cop1Move { op_code, rt, fs } => vec![IrInstruction::Cop1Move {
+                op_code,
+                rt: rt.index().unwrap(),
+                fs: fs.float_index().unwrap(),
+            }],
             Instruction::Pseudo(pseudo_instruction) => pseudo_instruction.lower(builder),
         }
     }
@@ -825,9 +919,9 @@ impl PseudoInstruction {
             PseudoInstruction::LabelStorage { op_code, rt, label } => {
                 let mut instructions = Self::load_symbol_into_register(builder, 1, &label);
                 instructions.push(IrInstruction::IType {
-                    op_code,
                     rs: 1,
-                    rt: rt.index().unwrap(),
+                    rt: op_code.rt_index(&rt).unwrap(),
+                    op_code,
                     immediate: 0,
                 });
                 instructions
@@ -846,7 +940,7 @@ impl PseudoInstruction {
         let (mut instructions, rt) = match rt {
             Either::Left(rt) => (Vec::new(), rt.index().unwrap()),
             Either::Right(value) => {
-                let value = value.evaluate(&constants).unwrap() as u32;
+                let value = value.evaluate(constants).unwrap() as u32;
                 (Self::load_num_into_register(1, value), 1)
             }
         };
@@ -5,16 +5,15 @@ use crate::ir::{
     RelocationType, Symbol, SymbolLocation, SymbolType,
 };
 use mips_types::constants::{
-    REF_METHOD_ADD, REF_METHOD_REPLACE, REF_METHOD_SUBTRACT, REF_TARGET_HALF_WORD, REF_TARGET_IMM,
-    REF_TARGET_JUMP, REF_TARGET_SPLIT_IMM, REF_TARGET_WORD, REL_JUMP, REL_LOWER_IMM, REL_SPLIT_IMM,
-    REL_UPPER_IMM, REL_WORD, SYM_DEF_LABEL, SYM_DEF_SEEN, SYM_GLOBAL,
+    MODULE_KERNEL, REF_METHOD_ADD, REF_METHOD_REPLACE, REF_METHOD_SUBTRACT, REF_TARGET_HALF_WORD,
+    REF_TARGET_IMM, REF_TARGET_JUMP, REF_TARGET_SPLIT_IMM, REF_TARGET_WORD, REL_JUMP,
+    REL_LOWER_IMM, REL_SPLIT_IMM, REL_UPPER_IMM, REL_WORD, SYM_DEF_LABEL, SYM_DEF_SEEN, SYM_GLOBAL,
 };
 use mips_types::module::{
     R2KModule, R2KModuleHeader, R2KReferenceEntry, R2KRelocationEntry, R2KSection, R2KSymbolEntry,
     R2KVersion, DATA_INDEX, R2K_MAGIC, RDATA_INDEX, REFERENCES_INDEX, RELOCATION_INDEX,
     SDATA_INDEX, SECTION_COUNT, STRINGS_INDEX, SYMBOLS_INDEX, TEXT_INDEX,
 };
-use std::array::IntoIter;
 use std::convert::TryFrom;
 
 impl IrProgram {
@@ -22,7 +21,7 @@ impl IrProgram {
         let text: Vec<u8> = self
             .text
             .into_iter()
-            .flat_map(|instruction| IntoIter::new(instruction.lower().to_be_bytes()))
+            .flat_map(|instruction| instruction.lower().to_be_bytes())
             .collect();
         let relocation = self.relocation.iter().map(RelocationEntry::lower).collect();
         let references = self.references.iter().map(ReferenceEntry::lower).collect();
@@ -45,7 +44,9 @@ impl IrProgram {
                 // TODO: Change to version 2 when we have the module name in the
                 //       symbol & string tables
                 version: R2KVersion::Version1,
-                flags: 0, // Module flags are not used by R2K
+                // Module flags are not used by R2K, but we use them to mark
+                // kernel modules
+                flags: if self.kernel { MODULE_KERNEL } else { 0 },
                 entry: 0, // Object modules do not specify an entry point
                 section_sizes,
             },
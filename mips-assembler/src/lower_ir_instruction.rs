@@ -1,16 +1,20 @@
 //! Lower IR instructions into MIPS
 
-use crate::ast::{ITypeOp, JTypeOp, RTypeOp};
+use crate::ast::{Cop0Op, Cop1MoveOp, Cop1Op, FloatFormat, ITypeOp, JTypeOp, RTypeOp};
 use crate::ir::IrInstruction;
 use mips_types::constants::{
-    FUNCTION_ADD, FUNCTION_ADDU, FUNCTION_AND, FUNCTION_BREAK, FUNCTION_DIV, FUNCTION_DIVU,
-    FUNCTION_JALR, FUNCTION_JR, FUNCTION_MFHI, FUNCTION_MFLO, FUNCTION_MTHI, FUNCTION_MTLO,
-    FUNCTION_MULT, FUNCTION_MULTU, FUNCTION_NOR, FUNCTION_OR, FUNCTION_SLL, FUNCTION_SLLV,
-    FUNCTION_SLT, FUNCTION_SLTU, FUNCTION_SRA, FUNCTION_SRAV, FUNCTION_SRL, FUNCTION_SRLV,
-    FUNCTION_SUB, FUNCTION_SUBU, FUNCTION_SYSCALL, FUNCTION_XOR, OP_ADDI, OP_ADDIU, OP_ANDI,
-    OP_BCOND, OP_BEQ, OP_BGTZ, OP_BLEZ, OP_BNE, OP_J, OP_JAL, OP_LB, OP_LBU, OP_LH, OP_LHU, OP_LUI,
-    OP_LW, OP_LWL, OP_LWR, OP_ORI, OP_R_TYPE, OP_SB, OP_SH, OP_SLTI, OP_SLTIU, OP_SW, OP_SWL,
-    OP_SWR, OP_XORI,
+    COP0_FUNCTION_RFE, COP0_RS_CO, COP0_RS_MF, COP0_RS_MT, COP1_FMT_D, COP1_FMT_S, COP1_FMT_W,
+    COP1_FUNCTION_ABS, COP1_FUNCTION_ADD, COP1_FUNCTION_CVT_D, COP1_FUNCTION_CVT_S,
+    COP1_FUNCTION_CVT_W, COP1_FUNCTION_C_EQ, COP1_FUNCTION_C_LE, COP1_FUNCTION_C_LT,
+    COP1_FUNCTION_DIV, COP1_FUNCTION_MOV, COP1_FUNCTION_MUL, COP1_FUNCTION_NEG, COP1_FUNCTION_SUB,
+    COP1_RS_MF, COP1_RS_MT, FUNCTION_ADD, FUNCTION_ADDU, FUNCTION_AND, FUNCTION_BREAK,
+    FUNCTION_DIV, FUNCTION_DIVU, FUNCTION_JALR, FUNCTION_JR, FUNCTION_MFHI, FUNCTION_MFLO,
+    FUNCTION_MTHI, FUNCTION_MTLO, FUNCTION_MULT, FUNCTION_MULTU, FUNCTION_NOR, FUNCTION_OR,
+    FUNCTION_SLL, FUNCTION_SLLV, FUNCTION_SLT, FUNCTION_SLTU, FUNCTION_SRA, FUNCTION_SRAV,
+    FUNCTION_SRL, FUNCTION_SRLV, FUNCTION_SUB, FUNCTION_SUBU, FUNCTION_SYSCALL, FUNCTION_XOR,
+    OP_ADDI, OP_ADDIU, OP_ANDI, OP_BCOND, OP_BEQ, OP_BGTZ, OP_BLEZ, OP_BNE, OP_COP0, OP_COP1, OP_J,
+    OP_JAL, OP_LB, OP_LBU, OP_LH, OP_LHU, OP_LUI, OP_LW, OP_LWC1, OP_LWL, OP_LWR, OP_ORI,
+    OP_R_TYPE, OP_SB, OP_SH, OP_SLTI, OP_SLTIU, OP_SW, OP_SWC1, OP_SWL, OP_SWR, OP_XORI,
 };
 
 impl IrInstruction {
@@ -45,6 +49,33 @@ impl IrInstruction {
                 op_code,
                 pseudo_address,
             } => (op_code.code() as u32) << 26 | pseudo_address,
+            IrInstruction::Cop0 { op_code, rt, rd } => {
+                (OP_COP0 as u32) << 26
+                    | (op_code.rs_code() as u32) << 21
+                    | (rt as u32) << 16
+                    | (rd as u32) << 11
+                    | op_code.function_code() as u32
+            }
+            IrInstruction::Cop1 {
+                op_code,
+                format,
+                fd,
+                fs,
+                ft,
+            } => {
+                (OP_COP1 as u32) << 26
+                    | (format.code() as u32) << 21
+                    | (ft as u32) << 16
+                    | (fs as u32) << 11
+                    | (fd as u32) << 6
+                    | op_code.function_code() as u32
+            }
+            IrInstruction::Cop1Move { op_code, rt, fs } => {
+                (OP_COP1 as u32) << 26
+                    | (op_code.rs_code() as u32) << 21
+                    | (rt as u32) << 16
+                    | (fs as u32) << 11
+            }
             IrInstruction::Word(word) => word,
         }
     }
@@ -115,6 +146,70 @@ impl ITypeOp {
             ITypeOp::Swl => OP_SWL,
             ITypeOp::Swr => OP_SWR,
             ITypeOp::Xori => OP_XORI,
+            ITypeOp::Bc1 => OP_COP1,
+            ITypeOp::Lwc1 => OP_LWC1,
+            ITypeOp::Swc1 => OP_SWC1,
+        }
+    }
+}
+
+impl Cop0Op {
+    /// Get the rs field, which selects the kind of coprocessor 0 operation
+    pub fn rs_code(&self) -> u8 {
+        match self {
+            Cop0Op::Mfc0 => COP0_RS_MF,
+            Cop0Op::Mtc0 => COP0_RS_MT,
+            Cop0Op::Rfe => COP0_RS_CO,
+        }
+    }
+
+    /// Get the function code (only used by coprocessor operations)
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Cop0Op::Mfc0 | Cop0Op::Mtc0 => 0,
+            Cop0Op::Rfe => COP0_FUNCTION_RFE,
+        }
+    }
+}
+
+impl Cop1Op {
+    /// Get the function code for this coprocessor 1 operation
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Cop1Op::Add => COP1_FUNCTION_ADD,
+            Cop1Op::Sub => COP1_FUNCTION_SUB,
+            Cop1Op::Mul => COP1_FUNCTION_MUL,
+            Cop1Op::Div => COP1_FUNCTION_DIV,
+            Cop1Op::Abs => COP1_FUNCTION_ABS,
+            Cop1Op::Mov => COP1_FUNCTION_MOV,
+            Cop1Op::Neg => COP1_FUNCTION_NEG,
+            Cop1Op::CvtS => COP1_FUNCTION_CVT_S,
+            Cop1Op::CvtD => COP1_FUNCTION_CVT_D,
+            Cop1Op::CvtW => COP1_FUNCTION_CVT_W,
+            Cop1Op::CEq => COP1_FUNCTION_C_EQ,
+            Cop1Op::CLt => COP1_FUNCTION_C_LT,
+            Cop1Op::CLe => COP1_FUNCTION_C_LE,
+        }
+    }
+}
+
+impl FloatFormat {
+    /// Get the fmt code, which is stored in the rs field
+    pub fn code(&self) -> u8 {
+        match self {
+            FloatFormat::Single => COP1_FMT_S,
+            FloatFormat::Double => COP1_FMT_D,
+            FloatFormat::Word => COP1_FMT_W,
+        }
+    }
+}
+
+impl Cop1MoveOp {
+    /// Get the rs field, which selects the direction of the move
+    pub fn rs_code(&self) -> u8 {
+        match self {
+            Cop1MoveOp::Mfc1 => COP1_RS_MF,
+            Cop1MoveOp::Mtc1 => COP1_RS_MT,
         }
     }
 }
@@ -78,9 +78,8 @@ fn exit_with_error(file_str: &str, span: Span, message: &str) -> ! {
         .lines()
         .nth(line_start - 1)
         .expect("Out of bounds line");
-    let underline: String = std::iter::repeat(' ')
-        .take(col_start - 1)
-        .chain(std::iter::repeat('^').take(col_end - col_start))
+    let underline: String = std::iter::repeat_n(' ', col_start - 1)
+        .chain(std::iter::repeat_n('^', col_end - col_start))
         .collect();
     log::error!(
         "Error at line {} column {}:\n| {}\n| {}\nerror: {}",
@@ -99,7 +98,7 @@ fn parse(file_str: &str) -> Program {
     match parser::ProgramParser::new().parse(file_str) {
         Ok(parsed_ast) => parsed_ast,
         Err(ParseError::InvalidToken { location }) => {
-            let (line, col) = index_to_line_col(&file_str, location);
+            let (line, col) = index_to_line_col(file_str, location);
             log::error!("Invalid token at line {}, column {}", line, col);
             std::process::exit(1);
         }
@@ -107,7 +106,7 @@ fn parse(file_str: &str) -> Program {
             token: (lspan, token, _rspan),
             expected,
         }) => {
-            let (line, col) = index_to_line_col(&file_str, lspan);
+            let (line, col) = index_to_line_col(file_str, lspan);
             log::error!(
                 "Unrecognized token '{}' at line {}, column {}, expected [{}]",
                 token,
@@ -118,7 +117,7 @@ fn parse(file_str: &str) -> Program {
             std::process::exit(1);
         }
         Err(ParseError::UnrecognizedEOF { location, expected }) => {
-            let (line, col) = index_to_line_col(&file_str, location);
+            let (line, col) = index_to_line_col(file_str, location);
             log::error!(
                 "Unexpected EOF at line {}, column {}, expected [{}]",
                 line,
@@ -130,7 +129,7 @@ fn parse(file_str: &str) -> Program {
         Err(ParseError::ExtraToken {
             token: (lspan, token, _rspan),
         }) => {
-            let (line, col) = index_to_line_col(&file_str, lspan);
+            let (line, col) = index_to_line_col(file_str, lspan);
             log::error!(
                 "Unexpected extra token '{}' at line {}, column {}",
                 token,
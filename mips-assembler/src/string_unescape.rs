@@ -1,4 +1,5 @@
 #[derive(Debug)]
+#[allow(dead_code)] // Fields are only read through Debug
 pub enum UnescapeError {
     TrailingBackslash,
     UnknownEscape(char),
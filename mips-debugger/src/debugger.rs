@@ -1,31 +1,66 @@
+use crate::output::ProgramOutput;
+use crate::watchpoint::{format_value, WatchKind, Watchpoint};
 use colored::Colorize;
-use mips_simulator::Processor;
+use mips_simulator::changes::{StateChange, StepChanges};
+use mips_simulator::memory_map::Access;
+use mips_simulator::snapshot::Snapshot;
+use mips_simulator::{Instruction, Processor};
 use mips_types::constants::REGISTER_NAMES;
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{BufWriter, Cursor, Write};
+
+/// The default number of steps which can be reversed
+const DEFAULT_HISTORY_SIZE: usize = 100_000;
+/// The number of bytes watched if a watchpoint doesn't give a length
+const DEFAULT_WATCH_SIZE: u32 = 4;
+/// The number of instructions listed if a count isn't given
+const DEFAULT_LIST_COUNT: u32 = 10;
 
 pub struct Debugger {
     processor: Processor,
+    output: ProgramOutput,
     breakpoints: Vec<u32>,
+    watchpoints: Vec<Watchpoint>,
     trace: bool,
+    /// The number of instructions listed after each step (none if 0)
+    autolist: u32,
+    /// The last checkpoint of the processor's state
+    checkpoint: Option<Snapshot>,
+    /// The changes made by the most recent steps, so they can be reversed
+    history: VecDeque<StepChanges>,
+    /// The maximum number of steps kept in the history
+    history_size: usize,
 }
 
 impl Debugger {
-    pub fn new(processor: Processor) -> Self {
+    pub fn new(processor: Processor, output: ProgramOutput) -> Self {
         Self {
             processor,
+            output,
             breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
             trace: false,
+            autolist: 0,
+            checkpoint: None,
+            history: VecDeque::new(),
+            history_size: DEFAULT_HISTORY_SIZE,
         }
     }
 
     /// Run the debugger
     pub fn run(&mut self) -> Result<(), io::Error> {
         loop {
+            self.output.finish_line();
             eprint!("mips-debugger> ");
             io::stdout().flush()?;
             let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            if io::stdin().read_line(&mut input)? == 0 {
+                // End of input
+                break;
+            }
 
             self.run_command(&input);
 
@@ -47,9 +82,37 @@ impl Debugger {
             ["continue"] | ["c"] => self.continue_exec(),
             ["step"] | ["s"] => {
                 self.step();
+                self.autolist();
             }
+            ["reverse-step"] | ["rs"] => {
+                self.reverse_step();
+                self.autolist();
+            }
+            ["reverse-continue"] | ["rc"] => self.reverse_continue(),
+            ["history"] => eprintln!(
+                "{} of {} steps are in the history",
+                self.history.len(),
+                self.history_size
+            ),
+            ["history", size] => self.set_history_size(size),
             ["breakpoint", address] | ["b", address] => self.set_breakpoint(address),
+            ["watch", range] => self.set_watchpoint(WatchKind::Write, range),
+            ["rwatch", range] => self.set_watchpoint(WatchKind::Read, range),
+            ["awatch", range] => self.set_watchpoint(WatchKind::Access, range),
+            ["list"] | ["disas"] => self.list(None, None),
+            ["list", address] | ["disas", address] => self.list(Some(address), None),
+            ["list", address, count] | ["disas", address, count] => {
+                self.list(Some(address), Some(count))
+            }
+            ["autolist", count] => self.set_autolist(count),
             ["print"] | ["p"] => self.print_registers(),
+            ["print", "float"] | ["pf"] => self.print_float_registers(),
+            ["checkpoint"] => self.checkpoint(None),
+            ["checkpoint", path] => self.checkpoint(Some(path)),
+            ["restore"] => self.restore(None),
+            ["restore", path] => self.restore(Some(path)),
+            ["stats"] => self.print_statistics(false),
+            ["stats", "json"] => self.print_statistics(true),
             ["exit"] | ["quit"] => self.processor.running = false,
             ["help"] => Self::print_help(),
             [""] => {} // Ignore empty input
@@ -63,8 +126,20 @@ impl Debugger {
              trace <on|off>\t\t\t\t\t\tTurn instruction tracing on/off\n\
              continue | c\t\t\t\t\t\tContinue execution until the next breakpoint\n\
              step | s\t\t\t\t\t\t\tExecute a single instruction\n\
-             breakpoint <address> | b <address>\tSet a breakpoint at the address\n\
+             reverse-step | rs\t\t\t\t\tUndo the last instruction\n\
+             reverse-continue | rc\t\t\t\tUndo instructions until the previous breakpoint\n\
+             history [size]\t\t\t\t\t\tShow or set how many steps can be reversed\n\
+             breakpoint <address> | b <address>\tSet a breakpoint at the address (ex. main, loop+8, *0x400010)\n\
+             watch <address>[,length]\t\t\tStop when the memory (4 bytes by default) is written\n\
+             rwatch <address>[,length]\t\t\tStop when the memory is read\n\
+             awatch <address>[,length]\t\t\tStop when the memory is read or written\n\
+             list | disas [address] [count]\t\tList the instructions at the address (or around the PC)\n\
+             autolist <count|off>\t\t\t\tList the next instructions after each step\n\
              print | p\t\t\t\t\t\t\tPrint all registers\n\
+             print float | pf\t\t\t\t\tPrint the floating point registers\n\
+             checkpoint [file]\t\t\t\t\tSave the processor's state (and write it to the file)\n\
+             restore [file]\t\t\t\t\t\tRestore the last checkpoint (or the snapshot in the file)\n\
+             stats [json]\t\t\t\t\t\tPrint the execution statistics (as text or JSON)\n\
              exit | quit\t\t\t\t\t\t\tExit the debugger\n\
              help\t\t\t\t\t\t\t\tPrint this help message"
         );
@@ -72,30 +147,267 @@ impl Debugger {
 
     /// Execute the next instruction. Return if execution should continue.
     fn step(&mut self) -> bool {
+        let program_counter = self.processor.program_counter;
+        let instruction = self.processor.load_next_instruction();
         if self.trace {
-            let instruction = self.processor.load_next_instruction();
+            self.output.finish_line();
             eprintln!(
-                "0x{:08x}\t{}",
-                self.processor.program_counter,
-                instruction.stringify(self.processor.program_counter)
+                "{}\t{}",
+                self.format_address(program_counter),
+                instruction.stringify(program_counter)
             );
         }
 
-        self.processor.step();
+        let (result, changes) = self.processor.step_with_changes();
+        let watchpoint_hit = result.is_ok()
+            && self.check_watchpoints(&changes, || {
+                format!(
+                    "{}\t{}",
+                    self.format_address(program_counter),
+                    instruction.stringify(program_counter)
+                )
+            });
+        // A failed step doesn't change anything, so there is nothing to reverse
+        if self.history_size > 0 && result.is_ok() {
+            if self.history.len() == self.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(changes);
+        }
+
+        if let Err(e) = result {
+            self.output.finish_line();
+            eprintln!("{} {}", "Execution error:".red(), e);
+            return false;
+        }
+
+        if let Some(hazard) = self.processor.load_delay_hazard() {
+            self.output.finish_line();
+            eprintln!("{} {}", "Warning:".yellow(), hazard);
+        }
 
         if self.breakpoints.contains(&self.processor.program_counter) {
-            eprintln!("Breakpoint hit");
+            self.output.finish_line();
+            eprintln!(
+                "Breakpoint hit at {}",
+                self.format_address(self.processor.program_counter)
+            );
             false
         } else {
-            self.processor.running
+            self.processor.running && !watchpoint_hit
         }
     }
 
+    /// Print the watchpoints accessed by the last instruction (described by
+    /// `instruction`). Returns if any were accessed.
+    fn check_watchpoints(&self, changes: &StepChanges, instruction: impl Fn() -> String) -> bool {
+        let mut hit = false;
+
+        for watchpoint in &self.watchpoints {
+            let memory_access = match self
+                .processor
+                .memory_accesses()
+                .iter()
+                .find(|memory_access| watchpoint.matches(memory_access))
+            {
+                Some(memory_access) => *memory_access,
+                None => continue,
+            };
+
+            if !hit {
+                self.output.finish_line();
+            }
+            hit = true;
+
+            let access = match memory_access.access {
+                Access::Write => "Wrote",
+                _ => "Read",
+            };
+            eprintln!(
+                "{} {}\n{}\n{} {} bytes at 0x{:08x}",
+                "Watchpoint hit:".yellow(),
+                watchpoint,
+                instruction(),
+                access,
+                memory_access.size,
+                memory_access.address
+            );
+
+            let new_value = self
+                .processor
+                .read_memory(watchpoint.address, watchpoint.size);
+            if memory_access.access == Access::Write {
+                // The first change to a byte has its value before the step
+                let mut old_value = new_value.clone();
+                for change in changes.changes.iter().rev() {
+                    if let StateChange::Memory {
+                        address,
+                        old_value: byte,
+                    } = *change
+                    {
+                        let offset = address.wrapping_sub(watchpoint.address);
+                        if offset < watchpoint.size {
+                            old_value[offset as usize] = byte;
+                        }
+                    }
+                }
+
+                eprintln!("Old value: {}", format_value(&old_value));
+                eprintln!("New value: {}", format_value(&new_value));
+            } else {
+                eprintln!("Value: {}", format_value(&new_value));
+            }
+        }
+
+        hit
+    }
+
     /// Continue running until a breakpoint is hit or the program stops
     fn continue_exec(&mut self) {
         while self.step() {}
     }
 
+    /// Undo the last instruction. Return if there are more instructions to
+    /// undo and the previous instruction doesn't have a breakpoint.
+    fn reverse_step(&mut self) -> bool {
+        let changes = match self.history.pop_back() {
+            Some(changes) => changes,
+            None => {
+                eprintln!("There is no more history");
+                return false;
+            }
+        };
+        self.processor.undo(&changes);
+
+        if self.trace {
+            let instruction = self.processor.load_next_instruction();
+            eprintln!(
+                "{}\t{}",
+                self.format_address(self.processor.program_counter),
+                instruction.stringify(self.processor.program_counter)
+            );
+        }
+
+        if self.breakpoints.contains(&self.processor.program_counter) {
+            eprintln!(
+                "Breakpoint hit at {}",
+                self.format_address(self.processor.program_counter)
+            );
+            false
+        } else {
+            !self.history.is_empty()
+        }
+    }
+
+    /// Undo instructions until a breakpoint is hit or the history runs out
+    fn reverse_continue(&mut self) {
+        while self.reverse_step() {}
+    }
+
+    /// Set the maximum number of steps kept in the history
+    fn set_history_size(&mut self, size: &str) {
+        let size = match size.parse() {
+            Ok(size) => size,
+            Err(_) => {
+                eprintln!("Invalid size");
+                return;
+            }
+        };
+
+        self.history_size = size;
+        while self.history.len() > size {
+            self.history.pop_front();
+        }
+    }
+
+    /// List the instructions at the address, or around the PC if there isn't
+    /// one
+    fn list(&self, address: Option<&str>, count: Option<&str>) {
+        let count = match count.map(str::parse) {
+            None => DEFAULT_LIST_COUNT,
+            Some(Ok(count)) if count > 0 => count,
+            Some(_) => {
+                eprintln!("Invalid count");
+                return;
+            }
+        };
+        let start = match address {
+            Some(address) => match self.parse_address(address) {
+                Some(address) => address & !3,
+                None => {
+                    eprintln!("Invalid address");
+                    return;
+                }
+            },
+            None => self
+                .processor
+                .program_counter
+                .saturating_sub(4 * (count / 2)),
+        };
+
+        self.print_instructions(start, count);
+    }
+
+    /// List the next instructions, if enabled and the program is running
+    fn autolist(&self) {
+        if self.autolist > 0 && self.processor.running {
+            self.print_instructions(self.processor.program_counter, self.autolist);
+        }
+    }
+
+    /// Print `count` instructions starting at the address. Labels are shown
+    /// before their instruction, and the PC and breakpoints are marked.
+    fn print_instructions(&self, start: u32, count: u32) {
+        self.output.finish_line();
+
+        for index in 0..count {
+            let address = start.wrapping_add(4 * index);
+            if let Some(symbol) = self.processor.symbols().symbol_at(address) {
+                eprintln!("{}:", symbol.name.green());
+            }
+
+            let bytes = self.processor.read_memory(address, 4);
+            let instruction =
+                Instruction(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+            let is_pc = address == self.processor.program_counter;
+            let marker = match (is_pc, self.breakpoints.contains(&address)) {
+                (true, true) => "*>".red(),
+                (true, false) => "=>".yellow(),
+                (false, true) => " *".red(),
+                (false, false) => "  ".normal(),
+            };
+            let target = match instruction.target(address) {
+                Some(target) => format!("\t# {}", self.format_address(target)),
+                None => String::new(),
+            };
+
+            eprintln!(
+                "{} 0x{:08x}\t{}{}",
+                marker,
+                address,
+                instruction.stringify(address),
+                target
+            );
+        }
+    }
+
+    /// Set how many instructions are listed after each step
+    fn set_autolist(&mut self, option: &str) {
+        match option {
+            "off" => {
+                self.autolist = 0;
+                eprintln!("Listing after each step is OFF");
+            }
+            count => match count.parse() {
+                Ok(count) => {
+                    self.autolist = count;
+                    eprintln!("Listing {} instructions after each step", count);
+                }
+                Err(_) => eprintln!("Invalid count"),
+            },
+        }
+    }
+
     /// Set the trace option
     fn set_trace(&mut self, option: &str) {
         match option {
@@ -112,23 +424,166 @@ impl Debugger {
     }
 
     fn set_breakpoint(&mut self, address: &str) {
-        let address = address.strip_prefix("0x").unwrap_or(address);
-        let address = match u32::from_str_radix(address, 16) {
-            Ok(address) => address,
-            Err(_) => {
+        let address = match self.parse_address(address) {
+            Some(address) => address,
+            None => {
+                eprintln!("Invalid address");
+                return;
+            }
+        };
+
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+        eprintln!("Breakpoint set at {}", self.format_address(address));
+    }
+
+    /// Parse an address, which is a symbol or hex number with an optional
+    /// offset (ex. "loop+8" or "loop+0x8"), or "*" and a hex number (ex.
+    /// "*0x400010")
+    fn parse_address(&self, address: &str) -> Option<u32> {
+        if let Some(address) = address.strip_prefix('*') {
+            return parse_hex(address);
+        }
+
+        let (base, offset) = match address.find(&['+', '-'][..]) {
+            Some(index) => {
+                let offset = parse_offset(&address[index + 1..])?;
+                let offset = if address[index..].starts_with('-') {
+                    offset.wrapping_neg()
+                } else {
+                    offset
+                };
+
+                (&address[..index], offset)
+            }
+            None => (address, 0),
+        };
+        let base = match self.processor.symbols().address(base) {
+            Some(base) => base,
+            None => parse_hex(base)?,
+        };
+
+        Some(base.wrapping_add(offset))
+    }
+
+    /// Format an address with the symbol it is in, if there is one (ex.
+    /// "0x00400018 <loop+0x8>")
+    fn format_address(&self, address: u32) -> String {
+        match self.processor.symbols().describe(address) {
+            Some(symbol) => format!("0x{:08x} <{}>", address, symbol),
+            None => format!("0x{:08x}", address),
+        }
+    }
+
+    /// Add a watchpoint for "ADDRESS[,LENGTH]"
+    fn set_watchpoint(&mut self, kind: WatchKind, range: &str) {
+        let (name, size) = match range.split_once(',') {
+            Some((name, size)) => match size.parse() {
+                Ok(size) if size > 0 => (name, size),
+                _ => {
+                    eprintln!("Invalid length");
+                    return;
+                }
+            },
+            None => (range, DEFAULT_WATCH_SIZE),
+        };
+        let address = match self.parse_address(name) {
+            Some(address) => address,
+            None => {
                 eprintln!("Invalid address");
                 return;
             }
         };
 
-        self.breakpoints.push(address);
+        let watchpoint = Watchpoint {
+            kind,
+            address,
+            size,
+            name: name.to_string(),
+        };
+        eprintln!("Set {}", watchpoint);
+        // Watchpoints are checked against the memory accesses of each step
+        if self.watchpoints.is_empty() {
+            self.processor.record_memory_accesses();
+        }
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Save a checkpoint of the processor's state. It is also written to the
+    /// file if there is one.
+    fn checkpoint(&mut self, path: Option<&str>) {
+        let snapshot = self.processor.snapshot();
+
+        if let Some(path) = path {
+            let result = File::create(path).and_then(|file| {
+                let mut output = BufWriter::new(file);
+                snapshot.write(&mut output)?;
+                output.flush()
+            });
+
+            if let Err(e) = result {
+                eprintln!("Could not save the checkpoint: {}", e);
+                return;
+            }
+        }
+
+        self.checkpoint = Some(snapshot);
+        eprintln!("Saved checkpoint");
+    }
+
+    /// Restore the last checkpoint, or the snapshot in the file if there is
+    /// one
+    fn restore(&mut self, path: Option<&str>) {
+        let mut snapshot = match path {
+            Some(path) => {
+                match fs::read(path).and_then(|data| Snapshot::parse(&mut Cursor::new(data))) {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        eprintln!("Could not load the snapshot: {}", e);
+                        return;
+                    }
+                }
+            }
+            None => match &self.checkpoint {
+                Some(checkpoint) => checkpoint.clone(),
+                None => {
+                    eprintln!("There is no checkpoint");
+                    return;
+                }
+            },
+        };
+
+        // The debugger doesn't run with limits, and the file sandbox is not
+        // saved in snapshots
+        snapshot.config.max_instructions = None;
+        snapshot.config.timeout = None;
+        snapshot.config.file_sandbox = self.processor.config().file_sandbox.clone();
+
+        self.processor.restore(&snapshot);
+        self.history.clear();
+        eprintln!(
+            "Restored to {} ({} instructions executed)",
+            self.format_address(self.processor.program_counter),
+            self.processor.instruction_count
+        );
+    }
+
+    /// Print the statistics collected since the debugger started. Reversed
+    /// instructions are still counted.
+    fn print_statistics(&self, json: bool) {
+        match self.processor.statistics_report() {
+            Some(report) if json => println!("{}", report.to_json()),
+            Some(report) => print!("{}", report),
+            None => eprintln!("Statistics are not enabled"),
+        }
     }
 
     fn print_registers(&self) {
         println!(
-            "{} = 0x{:08x}",
+            "{} = {}",
             "PC".yellow(),
-            self.processor.program_counter
+            self.format_address(self.processor.program_counter)
         );
 
         for row in 0..8 {
@@ -145,5 +600,147 @@ impl Debugger {
                 col4.to_string().blue(), REGISTER_NAMES[col4].yellow(), self.processor.registers.get(col4 as u8)
             )
         }
+
+        if let Some(pending_load) = self.processor.pending_load() {
+            println!(
+                "{} = 0x{:08x} (loaded at {}, written after the next instruction)",
+                pending_load.register.to_string().yellow(),
+                pending_load.value,
+                self.format_address(pending_load.program_counter)
+            );
+        }
+    }
+
+    /// Print the floating point registers as raw values, single precision
+    /// values, and (for each even/odd pair) double precision values
+    fn print_float_registers(&self) {
+        let cp1 = &self.processor.cp1;
+        println!("{} = {}", "Condition".yellow(), cp1.condition);
+
+        for even in (0..32).step_by(2) {
+            let odd = even + 1;
+
+            println!(
+                "{:4} = 0x{:08x} = {:<14} {:4} = 0x{:08x} = {:<14} {} = {:?}",
+                format!("$f{}", even).yellow(),
+                cp1.get(even),
+                format!("{:?}", cp1.get_single(even)),
+                format!("$f{}", odd).yellow(),
+                cp1.get(odd),
+                format!("{:?}", cp1.get_single(odd)),
+                "double".blue(),
+                cp1.get_double(even)
+            )
+        }
+    }
+}
+
+/// Parse a hex number, with or without "0x"
+fn parse_hex(number: &str) -> Option<u32> {
+    let number = number.strip_prefix("0x").unwrap_or(number);
+    u32::from_str_radix(number, 16).ok()
+}
+
+/// Parse an offset from a symbol, which is decimal or hex with "0x"
+fn parse_offset(offset: &str) -> Option<u32> {
+    match offset.strip_prefix("0x") {
+        Some(offset) => u32::from_str_radix(offset, 16).ok(),
+        None => offset.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_hex, parse_offset, Debugger};
+    use crate::output::ProgramOutput;
+    use mips_simulator::config::Config;
+    use mips_simulator::Processor;
+    use mips_types::constants::*;
+    use mips_types::module::{R2KModule, R2KModuleHeader, R2KSection, R2KSymbolEntry, TEXT_INDEX};
+
+    /// Create a debugger for a program with four instructions, and a label
+    /// ("loop") at the third one
+    fn debugger(text: [u32; 4]) -> Debugger {
+        let mut header = R2KModuleHeader {
+            entry: TEXT_OFFSET,
+            ..R2KModuleHeader::default()
+        };
+        header.section_sizes[TEXT_INDEX] = 16;
+        let module = R2KModule {
+            header,
+            text_section: text.iter().flat_map(|word| word.to_be_bytes()).collect(),
+            rdata_section: Vec::new(),
+            data_section: Vec::new(),
+            sdata_section: Vec::new(),
+            sbss_size: 0,
+            bss_size: 0,
+            relocation_section: Vec::new(),
+            reference_section: Vec::new(),
+            symbol_table: vec![R2KSymbolEntry {
+                flags: SYM_DEF_SEEN | SYM_DEF_LABEL | SYM_GLOBAL | R2KSection::Text as u32,
+                value: 8,
+                str_idx: 0,
+            }],
+            string_table: b"loop\0".to_vec(),
+        };
+        let mut processor = Processor::new(Config::default());
+        processor.load_rsim_module(&module);
+
+        Debugger::new(processor, ProgramOutput::new())
+    }
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse_hex("0x400010"), Some(0x40_0010));
+        assert_eq!(parse_hex("ffffffff"), Some(u32::MAX));
+        assert_eq!(parse_hex("0x"), None);
+        assert_eq!(parse_hex("100000000"), None);
+
+        assert_eq!(parse_offset("16"), Some(16));
+        assert_eq!(parse_offset("0x10"), Some(16));
+        assert_eq!(parse_offset("1f"), None);
+    }
+
+    #[test]
+    fn parse_addresses() {
+        let debugger = debugger([0; 4]);
+        let loop_address = TEXT_OFFSET + 8;
+
+        assert_eq!(debugger.parse_address("loop"), Some(loop_address));
+        assert_eq!(debugger.parse_address("loop+4"), Some(loop_address + 4));
+        assert_eq!(debugger.parse_address("loop+0x10"), Some(loop_address + 16));
+        assert_eq!(debugger.parse_address("loop-8"), Some(TEXT_OFFSET));
+        assert_eq!(debugger.parse_address("400000+4"), Some(TEXT_OFFSET + 4));
+        assert_eq!(debugger.parse_address("*0x400008"), Some(loop_address));
+        assert_eq!(debugger.parse_address("*loop"), None);
+        assert_eq!(debugger.parse_address("missing"), None);
+        assert_eq!(debugger.parse_address("loop+"), None);
+    }
+
+    #[test]
+    fn format_addresses() {
+        let debugger = debugger([0; 4]);
+
+        assert_eq!(debugger.format_address(TEXT_OFFSET), "0x00400000");
+        assert_eq!(
+            debugger.format_address(TEXT_OFFSET + 8),
+            "0x00400008 <loop>"
+        );
+        assert_eq!(
+            debugger.format_address(TEXT_OFFSET + 12),
+            "0x0040000c <loop+0x4>"
+        );
+        assert_eq!(debugger.format_address(TEXT_OFFSET + 16), "0x00400010");
+    }
+
+    #[test]
+    fn failed_steps_are_not_reversible() {
+        // A nop, then a reserved instruction
+        let mut debugger = debugger([0, 0xfc00_0000, 0, 0]);
+        assert!(debugger.step());
+        assert_eq!(debugger.history.len(), 1);
+
+        assert!(!debugger.step());
+        assert_eq!(debugger.history.len(), 1);
     }
 }
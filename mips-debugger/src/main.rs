@@ -1,9 +1,12 @@
 use crate::debugger::Debugger;
+use crate::output::ProgramOutput;
 use mips_simulator::config::Config;
+use mips_simulator::streams::Streams;
 use mips_simulator::Processor;
 use mips_types::module::R2KModule;
 use std::error::Error;
 use std::fs;
+use std::io;
 use std::io::Cursor;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -12,6 +15,8 @@ use structopt::StructOpt;
 extern crate log;
 
 mod debugger;
+mod output;
+mod watchpoint;
 
 #[derive(StructOpt)]
 struct CliArgs {
@@ -19,8 +24,60 @@ struct CliArgs {
     #[structopt(long)]
     enable_delay_slots: bool,
 
+    /// Enables load delay slots: the instruction after a load sees the
+    /// register's old value, like the R2000.
+    #[structopt(long)]
+    enable_load_delay_slots: bool,
+
+    /// Warns when an instruction reads the register loaded by the instruction
+    /// before it (which only works without load delay slots).
+    #[structopt(long)]
+    warn_load_delay_slots: bool,
+
+    /// Allows unaligned memory accesses instead of raising address errors.
+    #[structopt(long)]
+    allow_unaligned_access: bool,
+
+    /// Maps the SPIM/MARS console device at 0xffff0000 for memory-mapped I/O.
+    #[structopt(long)]
+    mapped_io: bool,
+
+    /// Allows reading, writing, and executing any address instead of faulting
+    /// on accesses outside of the program's segments (ex. writing to .text).
+    #[structopt(long)]
+    disable_memory_protection: bool,
+
+    /// The maximum size of the heap (grown by sbrk) in bytes. By default, the
+    /// heap can grow until it reaches the stack.
+    #[structopt(long)]
+    heap_limit: Option<u32>,
+
+    /// The directory which the program can access with the file syscalls.
+    /// Without it, the program can not open files.
+    #[structopt(long, parse(from_os_str))]
+    file_sandbox: Option<PathBuf>,
+
+    /// Seeds the random number generators, so the program is deterministic.
+    #[structopt(long)]
+    random_seed: Option<u64>,
+
+    /// A kernel module (assembled from .ktext/.kdata) whose exception handler
+    /// will handle exceptions raised by the program.
+    #[structopt(long, parse(from_os_str))]
+    kernel: Option<PathBuf>,
+
+    /// An environment variable to pass to the program, either NAME=VALUE or
+    /// NAME to copy it from the current environment. Can be used multiple
+    /// times.
+    #[structopt(long = "env", number_of_values = 1)]
+    environment: Vec<String>,
+
     #[structopt(parse(from_os_str))]
     file_path: PathBuf,
+
+    /// The arguments to pass to the program (after "--")
+    #[structopt(last = true)]
+    program_args: Vec<String>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -34,12 +91,42 @@ fn main() -> Result<(), Box<dyn Error>> {
     info!("Loaded module with header: {:?}", module.header);
 
     // Setup the processor and debugger
-    let mut processor = Processor::new(Config {
+    let output = ProgramOutput::new();
+    let streams = Streams::new(
+        Box::new(io::stdin()),
+        Box::new(output.clone()),
+        Box::new(io::stderr()),
+    );
+    let config = Config {
         enable_delay_slots: args.enable_delay_slots,
-    });
-    processor.load_rsim_module(&module);
+        enable_load_delay_slots: args.enable_load_delay_slots,
+        warn_load_delay_slots: args.warn_load_delay_slots,
+        allow_unaligned_access: args.allow_unaligned_access,
+        enable_mapped_io: args.mapped_io,
+        disable_memory_protection: args.disable_memory_protection,
+        heap_limit: args.heap_limit,
+        file_sandbox: args.file_sandbox,
+        random_seed: args.random_seed,
+        max_instructions: None,
+        timeout: None,
+        pipeline: None,
+        instruction_cache: None,
+        data_cache: None,
+        branch_prediction: None,
+    };
+    let mut processor = Processor::with_streams(config, streams);
+    // The first argument is the program's path
+    let mut program_args = vec![args.file_path.display().to_string()];
+    program_args.extend(args.program_args);
+    processor.load_program(
+        &module,
+        &program_args,
+        &args.environment,
+        args.kernel.as_deref(),
+    )?;
+    processor.enable_statistics();
     info!("Loaded processor with code");
-    let mut debugger = Debugger::new(processor);
+    let mut debugger = Debugger::new(processor, output);
 
     debugger.run()?;
 
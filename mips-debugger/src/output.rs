@@ -0,0 +1,40 @@
+use std::cell::Cell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+/// The program's output stream. It remembers if the program stopped writing in
+/// the middle of a line, so the debugger's messages can start on a new line.
+#[derive(Clone, Default)]
+pub struct ProgramOutput {
+    mid_line: Rc<Cell<bool>>,
+}
+
+impl ProgramOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// End the program's current line of output, if it has one
+    pub fn finish_line(&self) {
+        if self.mid_line.replace(false) {
+            println!();
+        }
+    }
+}
+
+impl Write for ProgramOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = io::stdout().write(buf)?;
+
+        if let Some(last_byte) = buf[..count].last() {
+            self.mid_line.set(*last_byte != b'\n');
+        }
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
@@ -0,0 +1,130 @@
+use mips_simulator::memory_map::{Access, MemoryAccess};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The accesses which stop execution at a watchpoint
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    /// Stop when the range is written (watch)
+    Write,
+    /// Stop when the range is read (rwatch)
+    Read,
+    /// Stop when the range is read or written (awatch)
+    Access,
+}
+
+/// A range of memory which stops execution when it is accessed
+#[derive(Clone, Debug)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub address: u32,
+    /// The number of bytes watched
+    pub size: u32,
+    /// How the range was given (ex. a symbol)
+    pub name: String,
+}
+
+impl Watchpoint {
+    /// Check if the memory access should stop at the watchpoint
+    pub fn matches(&self, memory_access: &MemoryAccess) -> bool {
+        let kind_matches = match self.kind {
+            WatchKind::Write => memory_access.access == Access::Write,
+            WatchKind::Read => memory_access.access == Access::Read,
+            WatchKind::Access => memory_access.access != Access::Execute,
+        };
+
+        kind_matches && memory_access.overlaps(self.address, self.size)
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Write => "watch",
+            WatchKind::Read => "rwatch",
+            WatchKind::Access => "awatch",
+        };
+
+        write!(
+            f,
+            "{} {} (0x{:08x}, {} byte{})",
+            kind,
+            self.name,
+            self.address,
+            self.size,
+            if self.size == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Format a value in memory. Up to a word is shown as a number, and longer
+/// values as bytes.
+pub fn format_value(bytes: &[u8]) -> String {
+    if bytes.len() <= 4 {
+        let value = bytes
+            .iter()
+            .fold(0u32, |value, byte| (value << 8) | *byte as u32);
+        format!("0x{:0width$x} ({})", value, value, width = 2 * bytes.len())
+    } else {
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_value, WatchKind, Watchpoint};
+    use mips_simulator::memory_map::{Access, MemoryAccess};
+
+    fn watchpoint(kind: WatchKind) -> Watchpoint {
+        Watchpoint {
+            kind,
+            address: 0x1000_0004,
+            size: 4,
+            name: "value".to_string(),
+        }
+    }
+
+    fn access(address: u32, size: u32, access: Access) -> MemoryAccess {
+        MemoryAccess {
+            address,
+            size,
+            access,
+        }
+    }
+
+    #[test]
+    fn match_accesses() {
+        let read = access(0x1000_0004, 4, Access::Read);
+        let write = access(0x1000_0004, 4, Access::Write);
+        assert!(watchpoint(WatchKind::Write).matches(&write));
+        assert!(!watchpoint(WatchKind::Write).matches(&read));
+        assert!(watchpoint(WatchKind::Read).matches(&read));
+        assert!(!watchpoint(WatchKind::Read).matches(&write));
+        assert!(watchpoint(WatchKind::Access).matches(&read));
+        assert!(watchpoint(WatchKind::Access).matches(&write));
+    }
+
+    #[test]
+    fn match_overlapping_accesses() {
+        let watchpoint = watchpoint(WatchKind::Write);
+        assert!(watchpoint.matches(&access(0x1000_0007, 1, Access::Write)));
+        assert!(watchpoint.matches(&access(0x1000_0002, 4, Access::Write)));
+        assert!(!watchpoint.matches(&access(0x1000_0000, 4, Access::Write)));
+        assert!(!watchpoint.matches(&access(0x1000_0008, 1, Access::Write)));
+    }
+
+    #[test]
+    fn format_watchpoints_and_values() {
+        assert_eq!(
+            watchpoint(WatchKind::Access).to_string(),
+            "awatch value (0x10000004, 4 bytes)"
+        );
+        assert_eq!(format_value(&[0x01, 0x02]), "0x0102 (258)");
+        assert_eq!(format_value(&[0xff; 4]), "0xffffffff (4294967295)");
+        assert_eq!(format_value(&[1, 2, 3, 4, 0xab]), "01 02 03 04 ab");
+    }
+}
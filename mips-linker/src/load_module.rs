@@ -3,8 +3,8 @@
 use crate::references::resolve_references;
 use crate::relocation::relocate;
 use crate::util::{make_symbol_table, R2KStrings};
-use mips_types::constants::{R2K_ENTRYPOINT, TEXT_OFFSET};
-use mips_types::module::{R2KModule, REFERENCES_INDEX, RELOCATION_INDEX};
+use mips_types::constants::R2K_ENTRYPOINT;
+use mips_types::module::{R2KModule, R2KSection, REFERENCES_INDEX, RELOCATION_INDEX};
 
 pub fn obj_to_load_module(obj_module: &mut R2KModule) {
     relocate(obj_module);
@@ -18,10 +18,11 @@ pub fn obj_to_load_module(obj_module: &mut R2KModule) {
 
     if obj_module.reference_section.is_empty() {
         // All references are resolved, the output is a load module
+        let text_offset = obj_module.get_section_offset(R2KSection::Text).unwrap();
         obj_module.header.entry = symbols
             .get(R2K_ENTRYPOINT)
-            .map(|entry_symbol| TEXT_OFFSET + entry_symbol.value)
-            .unwrap_or(TEXT_OFFSET);
+            .map(|entry_symbol| text_offset + entry_symbol.value)
+            .unwrap_or(text_offset);
     } else {
         // Not all references were resolved, the output is an object file
         obj_module.header.entry = 0;
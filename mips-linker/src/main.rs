@@ -73,21 +73,24 @@ fn link_objects(obj_file_paths: &[PathBuf], output_file_path: &Path) -> io::Resu
     log::debug!("Loaded {} object files", obj_modules.len());
 
     // Combine object files
-    let mut merged_module = obj_modules.into_iter().reduce(merge_obj_modules).unwrap();
+    let mut obj_modules = obj_modules.into_iter();
+    let first_module = obj_modules.next().unwrap();
+    let mut merged_module = obj_modules.try_fold(first_module, merge_obj_modules)?;
 
-    // Add in r2k_startup if no entry is found
+    // Add in r2k_startup if no entry is found. Kernel modules are entered
+    // through the exception vector, so they don't need it.
     let strings = R2KStrings::new(&merged_module.string_table);
     let contains_entry = merged_module.symbol_table.iter().any(|symbol| {
         strings.get_str(symbol.str_idx).unwrap() == R2K_ENTRYPOINT && symbol.has_definition()
     });
-    if !contains_entry {
+    if !contains_entry && !merged_module.is_kernel_module() {
         log::debug!(
             "No entrypoint found ({}). Linking in r2k_startup.",
             R2K_ENTRYPOINT
         );
         let r2k_startup = R2KModule::parse(&mut Cursor::new(R2K_STARTUP_OBJ))
             .expect("The embedded r2k_startup obj should be valid");
-        merged_module = merge_obj_modules(merged_module, r2k_startup);
+        merged_module = merge_obj_modules(merged_module, r2k_startup)?;
     }
 
     // Try to build a load module
@@ -1,21 +1,32 @@
 use crate::relocation::add_to_relocation_value;
 use crate::util::R2KStrings;
 use mips_types::module::{
-    R2KModule, R2KModuleHeader, R2KSection, DATA_INDEX, RDATA_INDEX, REFERENCES_INDEX,
-    RELOCATION_INDEX, SDATA_INDEX, SECTION_COUNT, STRINGS_INDEX, SYMBOLS_INDEX, TEXT_INDEX,
+    R2KModule, R2KModuleHeader, R2KSection, BSS_INDEX, DATA_INDEX, RDATA_INDEX, REFERENCES_INDEX,
+    RELOCATION_INDEX, SBSS_INDEX, SDATA_INDEX, SECTION_COUNT, STRINGS_INDEX, SYMBOLS_INDEX,
+    TEXT_INDEX,
 };
 use mips_types::string_table::StringTable;
 use std::collections::HashSet;
+use std::io;
 
 /// Merge two object modules. The right module's sections will be placed after
-/// the left module's sections.
-pub fn merge_obj_modules(mut left: R2KModule, right: R2KModule) -> R2KModule {
+/// the left module's sections. Kernel and user modules can't be merged.
+pub fn merge_obj_modules(mut left: R2KModule, right: R2KModule) -> io::Result<R2KModule> {
+    if left.is_kernel_module() != right.is_kernel_module() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot link kernel and user modules together",
+        ));
+    }
+
     let left_sizes = left.header.section_sizes;
     let update_address = |section, address: &mut u32, entry_type: &str| match section {
         R2KSection::Text => *address += left_sizes[TEXT_INDEX],
         R2KSection::RData => *address += left_sizes[RDATA_INDEX],
         R2KSection::Data => *address += left_sizes[DATA_INDEX],
         R2KSection::SData => *address += left_sizes[SDATA_INDEX],
+        R2KSection::SBss => *address += left_sizes[SBSS_INDEX],
+        R2KSection::Bss => *address += left_sizes[BSS_INDEX],
         _ => {
             log::warn!(
                 "Unexpected section for {} entry during merge: {:?}",
@@ -124,13 +135,16 @@ pub fn merge_obj_modules(mut left: R2KModule, right: R2KModule) -> R2KModule {
     section_sizes[DATA_INDEX] = merged_data.len() as u32;
     section_sizes[RDATA_INDEX] = merged_rdata.len() as u32;
     section_sizes[SDATA_INDEX] = merged_sdata.len() as u32;
+    section_sizes[SBSS_INDEX] = left.sbss_size + right.sbss_size;
+    section_sizes[BSS_INDEX] = left.bss_size + right.bss_size;
     section_sizes[RELOCATION_INDEX] = merged_relocation.len() as u32;
     section_sizes[REFERENCES_INDEX] = merged_references.len() as u32;
     section_sizes[SYMBOLS_INDEX] = merged_symbols.len() as u32;
     section_sizes[STRINGS_INDEX] = merged_str_table.len() as u32;
 
-    R2KModule {
+    Ok(R2KModule {
         header: R2KModuleHeader {
+            flags: left.header.flags,
             section_sizes,
             ..Default::default()
         },
@@ -138,11 +152,11 @@ pub fn merge_obj_modules(mut left: R2KModule, right: R2KModule) -> R2KModule {
         rdata_section: merged_rdata,
         data_section: merged_data,
         sdata_section: merged_sdata,
-        sbss_size: left.sbss_size + right.sbss_size,
-        bss_size: left.bss_size + right.bss_size,
+        sbss_size: section_sizes[SBSS_INDEX],
+        bss_size: section_sizes[BSS_INDEX],
         relocation_section: merged_relocation,
         reference_section: merged_references,
         symbol_table: merged_symbols,
         string_table: merged_str_table,
-    }
+    })
 }
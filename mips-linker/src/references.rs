@@ -28,12 +28,12 @@ pub fn resolve_references(obj_module: &mut R2KModule) {
         let symbol_section_offset = obj_module.get_section_offset(symbol.section()).unwrap_or(0);
         let symbol_value = match symbol.section() {
             R2KSection::Undefined | R2KSection::Absolute => symbol.value,
-            R2KSection::Text | R2KSection::RData | R2KSection::Data | R2KSection::SData => {
-                symbol.value + symbol_section_offset
-            }
-            R2KSection::SBss | R2KSection::Bss => {
-                unimplemented!()
-            }
+            R2KSection::Text
+            | R2KSection::RData
+            | R2KSection::Data
+            | R2KSection::SData
+            | R2KSection::SBss
+            | R2KSection::Bss => symbol.value + symbol_section_offset,
             R2KSection::External => {
                 log::info!(
                     "Could not find symbol '{}' when resolving references",
@@ -0,0 +1,90 @@
+//! Measures how fast the simulator executes the bundled programs
+//!
+//! Run with `cargo bench -p mips-simulator`
+
+use mips_simulator::config::Config;
+use mips_simulator::streams::Streams;
+use mips_simulator::{Processor, StepOutcome};
+use mips_types::module::R2KModule;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The number of times each benchmark is run. The fastest run is reported.
+const SAMPLES: usize = 5;
+
+struct Benchmark {
+    name: &'static str,
+    /// The module to run, relative to the programs directory
+    path: &'static str,
+    /// The program's stdin
+    input: &'static [u8],
+    /// The number of times the program is run per sample
+    iterations: usize,
+}
+
+const BENCHMARKS: &[Benchmark] = &[
+    Benchmark {
+        name: "torture",
+        path: "torture/torture.out",
+        input: b"3\n4\n5\n0\n",
+        iterations: 2000,
+    },
+    Benchmark {
+        name: "compute",
+        path: "compute/compute.out",
+        input: b"",
+        iterations: 1,
+    },
+];
+
+/// Run the module to completion, returning the number of executed instructions
+fn run(module: &R2KModule, input: &'static [u8]) -> u64 {
+    let streams = Streams::new(
+        Box::new(Cursor::new(input)),
+        Box::new(io::sink()),
+        Box::new(io::sink()),
+    );
+    let mut processor = Processor::with_streams(Config::default(), streams);
+    processor.load_rsim_module(module);
+    processor.load_arguments(&["benchmark".to_string()], &[]);
+
+    loop {
+        match processor.step() {
+            Ok(StepOutcome::Running) => {}
+            Ok(_) => break,
+            Err(e) => panic!("Benchmark program failed: {}", e),
+        }
+    }
+
+    processor.instruction_count
+}
+
+fn main() {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("../programs");
+
+    for benchmark in BENCHMARKS {
+        let file_data = fs::read(programs.join(benchmark.path)).unwrap();
+        let module = R2KModule::parse(&mut Cursor::new(file_data)).unwrap();
+
+        let mut best = Duration::MAX;
+        let mut instructions = 0;
+        for _ in 0..SAMPLES {
+            let start = Instant::now();
+            instructions = (0..benchmark.iterations)
+                .map(|_| run(&module, benchmark.input))
+                .sum();
+            best = best.min(start.elapsed());
+        }
+
+        println!(
+            "{:<10} {:>12} instructions in {:>10.3?} ({:.1} MIPS)",
+            benchmark.name,
+            instructions,
+            best,
+            instructions as f64 / best.as_secs_f64() / 1_000_000.0
+        );
+    }
+}
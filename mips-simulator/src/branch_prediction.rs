@@ -0,0 +1,580 @@
+//! Branch predictors which run alongside execution, to show how well each
+//! would have predicted the program's conditional branches. Jumps are predicted
+//! by a branch target buffer (BTB). The predictors only count their correct
+//! predictions, they don't change how the program runs.
+
+use crate::instruction::Instruction;
+use crate::processor::Processor;
+use mips_types::constants::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A conditional branch predictor
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Predictor {
+    /// Always predict not taken
+    NotTaken,
+    /// Predict backward branches (ex. loops) as taken and forward branches
+    /// as not taken
+    BackwardTaken,
+    /// A table of the last outcome of the branches, indexed by address
+    OneBit,
+    /// A table of 2-bit saturating counters, indexed by address
+    TwoBit,
+}
+
+impl Predictor {
+    pub const ALL: [Predictor; 4] = [
+        Predictor::NotTaken,
+        Predictor::BackwardTaken,
+        Predictor::OneBit,
+        Predictor::TwoBit,
+    ];
+
+    /// Get the name of the predictor, as used in options and reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Predictor::NotTaken => "not-taken",
+            Predictor::BackwardTaken => "backward-taken",
+            Predictor::OneBit => "1-bit",
+            Predictor::TwoBit => "2-bit",
+        }
+    }
+}
+
+impl FromStr for Predictor {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Predictor::ALL
+            .iter()
+            .find(|predictor| predictor.name() == name)
+            .copied()
+            .ok_or_else(|| "Expected not-taken, backward-taken, 1-bit, or 2-bit".to_string())
+    }
+}
+
+impl Display for Predictor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The config of the branch predictors
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BranchPredictionConfig {
+    /// The conditional branch predictors to run
+    pub predictors: Vec<Predictor>,
+    /// The number of entries in the 1-bit and 2-bit predictors' tables
+    pub table_size: u32,
+    /// The number of entries in the branch target buffer
+    pub btb_size: u32,
+}
+
+impl Default for BranchPredictionConfig {
+    /// Run all of the predictors, with 256 entry tables and a 64 entry BTB
+    fn default() -> Self {
+        Self {
+            predictors: Predictor::ALL.to_vec(),
+            table_size: 256,
+            btb_size: 64,
+        }
+    }
+}
+
+/// A predictor and its state
+#[derive(Clone, Debug)]
+struct PredictorState {
+    predictor: Predictor,
+    /// The table of last outcomes (1-bit) or counters (2-bit)
+    table: Vec<u8>,
+    correct: u64,
+}
+
+impl PredictorState {
+    fn new(predictor: Predictor, table_size: u32) -> Self {
+        let table_size = match predictor {
+            Predictor::OneBit | Predictor::TwoBit => table_size.max(1) as usize,
+            _ => 0,
+        };
+
+        Self {
+            predictor,
+            // 2-bit counters start weakly not taken
+            table: vec![(predictor == Predictor::TwoBit) as u8; table_size],
+            correct: 0,
+        }
+    }
+
+    /// Predict the branch, then update the predictor with its outcome.
+    /// Returns if the prediction was correct.
+    fn predict(&mut self, program_counter: u32, target: u32, taken: bool) -> bool {
+        let index = (program_counter >> 2) as usize % self.table.len().max(1);
+        let prediction = match self.predictor {
+            Predictor::NotTaken => false,
+            Predictor::BackwardTaken => target <= program_counter,
+            Predictor::OneBit => {
+                let prediction = self.table[index] != 0;
+                self.table[index] = taken as u8;
+                prediction
+            }
+            Predictor::TwoBit => {
+                let counter = &mut self.table[index];
+                let prediction = *counter >= 2;
+                *counter = if taken {
+                    (*counter + 1).min(3)
+                } else {
+                    counter.saturating_sub(1)
+                };
+                prediction
+            }
+        };
+
+        let correct = prediction == taken;
+        if correct {
+            self.correct += 1;
+        }
+        correct
+    }
+}
+
+/// The outcomes and predictions of a conditional branch
+#[derive(Clone, Debug, Default)]
+struct BranchSite {
+    taken: u64,
+    not_taken: u64,
+    /// The number of correct predictions, in the order of the config's
+    /// predictors
+    correct: Vec<u64>,
+}
+
+/// The branch predictors and the branch target buffer
+#[derive(Clone, Debug)]
+pub(crate) struct BranchPrediction {
+    config: BranchPredictionConfig,
+    predictors: Vec<PredictorState>,
+    sites: BTreeMap<u32, BranchSite>,
+    /// The address and target of the last jump in each entry
+    btb: Vec<Option<(u32, u32)>>,
+    btb_report: BtbReport,
+}
+
+impl BranchPrediction {
+    pub fn new(config: BranchPredictionConfig) -> Self {
+        Self {
+            predictors: config
+                .predictors
+                .iter()
+                .map(|predictor| PredictorState::new(*predictor, config.table_size))
+                .collect(),
+            sites: BTreeMap::new(),
+            btb: vec![None; config.btb_size as usize],
+            btb_report: BtbReport {
+                size: config.btb_size,
+                ..Default::default()
+            },
+            config,
+        }
+    }
+
+    /// Predict a conditional branch to the target, and update the predictors
+    fn branch(&mut self, program_counter: u32, target: u32, taken: bool) {
+        let site = self.sites.entry(program_counter).or_default();
+        site.correct.resize(self.predictors.len(), 0);
+        if taken {
+            site.taken += 1;
+        } else {
+            site.not_taken += 1;
+        }
+
+        for (predictor, correct) in self.predictors.iter_mut().zip(&mut site.correct) {
+            if predictor.predict(program_counter, target, taken) {
+                *correct += 1;
+            }
+        }
+    }
+
+    /// Predict a jump's target with the BTB, and update it
+    fn jump(&mut self, program_counter: u32, target: u32) {
+        let report = &mut self.btb_report;
+        report.jumps += 1;
+        if self.btb.is_empty() {
+            report.misses += 1;
+            return;
+        }
+
+        let index = (program_counter >> 2) as usize % self.btb.len();
+        match self.btb[index] {
+            Some((address, predicted)) if address == program_counter => {
+                if predicted == target {
+                    report.hits += 1;
+                } else {
+                    report.wrong_targets += 1;
+                }
+            }
+            _ => report.misses += 1,
+        }
+        self.btb[index] = Some((program_counter, target));
+    }
+}
+
+impl Processor {
+    /// Run the branch predictors on the last executed instruction, if it is a
+    /// conditional branch or jump
+    pub(crate) fn record_branch_prediction(
+        &mut self,
+        program_counter: u32,
+        instruction: Instruction,
+        executed: bool,
+    ) {
+        if !executed {
+            return;
+        }
+
+        let branch = matches!(
+            instruction.op_code(),
+            OP_BCOND | OP_BEQ | OP_BNE | OP_BLEZ | OP_BGTZ
+        );
+        let jump = matches!(instruction.op_code(), OP_J | OP_JAL)
+            || instruction.op_code() == OP_R_TYPE
+                && matches!(instruction.function(), FUNCTION_JR | FUNCTION_JALR);
+        if !branch && !jump {
+            return;
+        }
+
+        let control_transfer = self.control_transfer(program_counter);
+        let branch_prediction = match &mut self.branch_prediction {
+            Some(branch_prediction) => branch_prediction,
+            None => return,
+        };
+
+        if branch {
+            let target = instruction.target(program_counter).unwrap_or_default();
+            branch_prediction.branch(program_counter, target, self.branch_taken);
+        } else {
+            // A jump to the next instruction doesn't transfer control
+            let sequential = if self.config.enable_delay_slots { 8 } else { 4 };
+            let target =
+                control_transfer.unwrap_or_else(|| program_counter.wrapping_add(sequential));
+            branch_prediction.jump(program_counter, target);
+        }
+    }
+
+    /// Get the branch prediction report, if branch prediction is enabled
+    pub fn branch_prediction_report(&self) -> Option<BranchPredictionReport> {
+        let branch_prediction = self.branch_prediction.as_ref()?;
+        let sites: Vec<_> = branch_prediction
+            .sites
+            .iter()
+            .map(|(address, site)| BranchSiteReport {
+                address: *address,
+                symbol: self.symbols.describe(*address),
+                taken: site.taken,
+                not_taken: site.not_taken,
+                correct: site.correct.clone(),
+            })
+            .collect();
+
+        Some(BranchPredictionReport {
+            config: branch_prediction.config.clone(),
+            branches: sites.iter().map(BranchSiteReport::executions).sum(),
+            taken: sites.iter().map(|site| site.taken).sum(),
+            correct: branch_prediction
+                .predictors
+                .iter()
+                .map(|predictor| predictor.correct)
+                .collect(),
+            btb: branch_prediction.btb_report.clone(),
+            sites,
+        })
+    }
+}
+
+/// The predictions of the branch target buffer
+#[derive(Clone, Debug, Default)]
+pub struct BtbReport {
+    /// The number of entries
+    pub size: u32,
+    pub jumps: u64,
+    /// Jumps whose target was predicted correctly
+    pub hits: u64,
+    /// Jumps which were in the BTB with a different target (ex. returns)
+    pub wrong_targets: u64,
+    /// Jumps which weren't in the BTB
+    pub misses: u64,
+}
+
+/// The outcomes and predictions of a conditional branch instruction
+#[derive(Clone, Debug)]
+pub struct BranchSiteReport {
+    pub address: u32,
+    /// The address as symbol+offset, if it has a symbol
+    pub symbol: Option<String>,
+    pub taken: u64,
+    pub not_taken: u64,
+    /// The number of correct predictions, in the order of the config's
+    /// predictors
+    pub correct: Vec<u64>,
+}
+
+impl BranchSiteReport {
+    pub fn executions(&self) -> u64 {
+        self.taken + self.not_taken
+    }
+}
+
+/// The accuracy of the branch predictors
+#[derive(Clone, Debug)]
+pub struct BranchPredictionReport {
+    pub config: BranchPredictionConfig,
+    /// The number of conditional branches executed
+    pub branches: u64,
+    /// The number of conditional branches which were taken
+    pub taken: u64,
+    /// The number of correct predictions, in the order of the config's
+    /// predictors
+    pub correct: Vec<u64>,
+    pub btb: BtbReport,
+    /// The branch instructions, by address
+    pub sites: Vec<BranchSiteReport>,
+}
+
+impl BranchPredictionReport {
+    /// Get the fraction of the branches which the predictor (an index into
+    /// the config's predictors) predicted correctly
+    pub fn accuracy(&self, predictor: usize) -> f64 {
+        fraction(self.correct[predictor], self.branches)
+    }
+}
+
+/// Get the fraction as a percentage, or 0 if there is nothing to divide
+fn percent(count: u64, total: u64) -> f64 {
+    100.0 * fraction(count, total)
+}
+
+fn fraction(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+impl Display for BranchPredictionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let config = &self.config;
+        writeln!(
+            f,
+            "Branch prediction: {} branches ({} taken), {} jumps",
+            self.branches, self.taken, self.btb.jumps
+        )?;
+
+        for (predictor, correct) in config.predictors.iter().zip(&self.correct) {
+            let name = match predictor {
+                Predictor::OneBit | Predictor::TwoBit => {
+                    format!("{} ({} entries)", predictor, config.table_size)
+                }
+                _ => predictor.to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<22} {:>12} correct {:>6.2}%",
+                name,
+                correct,
+                percent(*correct, self.branches)
+            )?;
+        }
+
+        let btb = &self.btb;
+        writeln!(
+            f,
+            "  {:<22} {:>12} correct {:>6.2}% ({} wrong targets, {} misses)",
+            format!("BTB ({} entries)", btb.size),
+            btb.hits,
+            percent(btb.hits, btb.jumps),
+            btb.wrong_targets,
+            btb.misses
+        )?;
+
+        if self.sites.is_empty() {
+            return Ok(());
+        }
+
+        write!(
+            f,
+            "\nBranch sites:\n  {:<10} {:<24} {:>10} {:>7}",
+            "Address", "Symbol", "Executed", "Taken"
+        )?;
+        for predictor in &config.predictors {
+            write!(f, " {:>14}", predictor.name())?;
+        }
+        writeln!(f)?;
+
+        for site in &self.sites {
+            write!(
+                f,
+                "  0x{:08x} {:<24} {:>10} {:>6.2}%",
+                site.address,
+                site.symbol.as_deref().unwrap_or("-"),
+                site.executions(),
+                percent(site.taken, site.executions())
+            )?;
+            for correct in &site.correct {
+                write!(f, " {:>13.2}%", percent(*correct, site.executions()))?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BranchPrediction, BranchPredictionConfig, Predictor, PredictorState};
+    use crate::config::Config;
+    use crate::test_util::{exit, i_type, li, processor, r_type, run};
+    use mips_types::constants::*;
+
+    #[test]
+    fn parse_predictors() {
+        for predictor in Predictor::ALL {
+            assert_eq!(predictor.name().parse(), Ok(predictor));
+        }
+        assert!("3-bit".parse::<Predictor>().is_err());
+    }
+
+    #[test]
+    fn predict_a_loop() {
+        // A loop branch which is taken 3 times, then falls through, twice
+        let outcomes = [true, true, true, false, true, true, true, false];
+        for (predictor, expected) in [
+            (Predictor::NotTaken, 2),
+            (Predictor::BackwardTaken, 6),
+            (Predictor::OneBit, 4),
+            (Predictor::TwoBit, 5),
+        ] {
+            let mut state = PredictorState::new(predictor, 16);
+            for taken in outcomes {
+                state.predict(0x0040_0010, 0x0040_0000, taken);
+            }
+            assert_eq!(state.correct, expected, "{}", predictor);
+        }
+
+        // A forward branch is predicted not taken
+        let mut state = PredictorState::new(Predictor::BackwardTaken, 16);
+        assert!(!state.predict(0x0040_0010, 0x0040_0020, true));
+    }
+
+    #[test]
+    fn branches_share_table_entries() {
+        let mut state = PredictorState::new(Predictor::OneBit, 4);
+        state.predict(0x0040_0000, 0x0040_0000, true);
+        // The table has 4 entries, so the branch 16 bytes later aliases
+        assert!(state.predict(0x0040_0010, 0x0040_0000, true));
+        assert!(!state.predict(0x0040_0004, 0x0040_0000, true));
+    }
+
+    #[test]
+    fn branch_target_buffer() {
+        let mut branch_prediction = BranchPrediction::new(BranchPredictionConfig {
+            btb_size: 4,
+            ..BranchPredictionConfig::default()
+        });
+        branch_prediction.jump(0x0040_0000, 0x0040_0100);
+        branch_prediction.jump(0x0040_0000, 0x0040_0100);
+        branch_prediction.jump(0x0040_0000, 0x0040_0200);
+        // Aliases the first jump's entry
+        branch_prediction.jump(0x0040_0010, 0x0040_0200);
+        branch_prediction.jump(0x0040_0000, 0x0040_0200);
+
+        let report = &branch_prediction.btb_report;
+        assert_eq!(report.jumps, 5);
+        assert_eq!(report.hits, 1);
+        assert_eq!(report.wrong_targets, 1);
+        assert_eq!(report.misses, 3);
+
+        let mut branch_prediction = BranchPrediction::new(BranchPredictionConfig {
+            btb_size: 0,
+            ..BranchPredictionConfig::default()
+        });
+        branch_prediction.jump(0x0040_0000, 0x0040_0100);
+        branch_prediction.jump(0x0040_0000, 0x0040_0100);
+        assert_eq!(branch_prediction.btb_report.misses, 2);
+    }
+
+    #[test]
+    fn processor_branch_prediction() {
+        for enable_delay_slots in [false, true] {
+            let config = Config {
+                enable_delay_slots,
+                branch_prediction: Some(BranchPredictionConfig::default()),
+                ..Config::default()
+            };
+            let mut text = li(REG_A0, 3).to_vec();
+            // Count $a0 down to 0
+            text.push(i_type(OP_ADDIU, REG_A0, REG_A0, 0xffff));
+            text.push(i_type(OP_BNE, 0, REG_A0, (-2i16) as u16));
+            if enable_delay_slots {
+                text.push(0);
+            }
+            // Call a function which returns immediately
+            let call = text.len();
+            text.push(0);
+            if enable_delay_slots {
+                text.push(0);
+            }
+            text.extend(exit());
+            let function = TEXT_OFFSET + 4 * text.len() as u32;
+            text[call] = (OP_JAL as u32) << 26 | (function & 0x0fff_ffff) >> 2;
+            text.push(r_type(FUNCTION_JR, 0, REG_RA, 0));
+            if enable_delay_slots {
+                text.push(0);
+            }
+            let (mut processor, _) = processor(config, "", &text, &[]);
+            run(&mut processor).unwrap();
+
+            let report = processor.branch_prediction_report().unwrap();
+            assert_eq!(report.branches, 3);
+            assert_eq!(report.taken, 2);
+            assert_eq!(report.correct, [1, 2, 1, 1]);
+            assert_eq!(report.sites.len(), 1);
+            assert_eq!(report.sites[0].address, TEXT_OFFSET + 12);
+            assert_eq!(report.btb.jumps, 2);
+            assert_eq!(report.btb.misses, 2);
+        }
+    }
+
+    #[test]
+    fn branch_to_the_fall_through_address() {
+        for enable_delay_slots in [false, true] {
+            let config = Config {
+                enable_delay_slots,
+                branch_prediction: Some(BranchPredictionConfig::default()),
+                ..Config::default()
+            };
+            // Branches whose target is the instruction after them (and their
+            // delay slot), one taken and one not taken
+            let offset = if enable_delay_slots { 1 } else { 0 };
+            let mut text = vec![i_type(OP_BEQ, 0, 0, offset)];
+            if enable_delay_slots {
+                text.push(0);
+            }
+            text.push(i_type(OP_BNE, 0, 0, offset));
+            if enable_delay_slots {
+                text.push(0);
+            }
+            text.extend(exit());
+            let (mut processor, _) = processor(config, "", &text, &[]);
+            run(&mut processor).unwrap();
+
+            let report = processor.branch_prediction_report().unwrap();
+            assert_eq!(report.branches, 2);
+            assert_eq!(report.taken, 1);
+            assert_eq!(report.sites[0].taken, 1);
+            assert_eq!(report.sites[1].not_taken, 1);
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! A set-associative cache model for the instruction fetches and the data
+//! loads and stores. The caches only count hits and misses, they don't change
+//! how the program runs.
+
+use crate::instruction::Instruction;
+use crate::memory_map::Access;
+use crate::processor::Processor;
+use crate::random::Random;
+use crate::stats::InstructionClass;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// How the block to evict is chosen when a set is full
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReplacementPolicy {
+    /// Evict the least recently used block
+    Lru,
+    /// Evict the block which was brought in first
+    Fifo,
+    /// Evict a random block, chosen by a generator with the seed
+    Random { seed: u64 },
+}
+
+impl Display for ReplacementPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplacementPolicy::Lru => f.write_str("LRU"),
+            ReplacementPolicy::Fifo => f.write_str("FIFO"),
+            ReplacementPolicy::Random { seed } => write!(f, "random (seed {})", seed),
+        }
+    }
+}
+
+/// What happens when a store hits in the cache
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WritePolicy {
+    /// The store is written to memory immediately
+    WriteThrough,
+    /// The block is marked dirty, and written to memory when it is evicted
+    WriteBack,
+}
+
+impl Display for WritePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WritePolicy::WriteThrough => "write-through",
+            WritePolicy::WriteBack => "write-back",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// The config of a cache
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CacheConfig {
+    /// The capacity in bytes
+    pub size: u32,
+    /// The size of a block (line) in bytes
+    pub block_size: u32,
+    /// The number of blocks in each set
+    pub associativity: u32,
+    pub replacement: ReplacementPolicy,
+    pub write_policy: WritePolicy,
+    /// If a store which misses brings the block into the cache. Otherwise,
+    /// it is only written to memory.
+    pub write_allocate: bool,
+}
+
+impl CacheConfig {
+    /// Create a write-back, write-allocate cache with LRU replacement
+    pub fn new(size: u32, block_size: u32, associativity: u32) -> Self {
+        Self {
+            size,
+            block_size,
+            associativity,
+            replacement: ReplacementPolicy::Lru,
+            write_policy: WritePolicy::WriteBack,
+            write_allocate: true,
+        }
+    }
+
+    /// Check that the sizes are powers of two, and the cache holds at least
+    /// one set
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("size", self.size),
+            ("block size", self.block_size),
+            ("associativity", self.associativity),
+        ] {
+            if !value.is_power_of_two() {
+                return Err(format!("The cache {} must be a power of two", name));
+            }
+        }
+
+        if self.block_size < 4 {
+            return Err("The cache block size must be at least 4 bytes".to_string());
+        }
+
+        if (self.size as u64) < self.block_size as u64 * self.associativity as u64 {
+            return Err("The cache is smaller than one set".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn set_count(&self) -> u32 {
+        self.size / (self.block_size * self.associativity)
+    }
+}
+
+/// Parse "SIZE:BLOCK_SIZE:ASSOCIATIVITY" (ex. "4096:16:2") as a write-back,
+/// write-allocate cache with LRU replacement
+impl FromStr for CacheConfig {
+    type Err = String;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        let values = config
+            .split(':')
+            .map(|value| value.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        match values.as_slice() {
+            [size, block_size, associativity] => {
+                let config = CacheConfig::new(*size, *block_size, *associativity);
+                config.validate()?;
+                Ok(config)
+            }
+            _ => Err("Expected SIZE:BLOCK_SIZE:ASSOCIATIVITY".to_string()),
+        }
+    }
+}
+
+/// A block in the cache
+#[derive(Copy, Clone, Debug, Default)]
+struct Line {
+    valid: bool,
+    dirty: bool,
+    tag: u32,
+    /// When the block was last accessed (for LRU)
+    last_used: u64,
+    /// When the block was brought in (for FIFO)
+    filled: u64,
+}
+
+/// A set-associative cache
+#[derive(Debug)]
+pub(crate) struct Cache {
+    config: CacheConfig,
+    /// The lines of each set, one set after another
+    lines: Vec<Line>,
+    /// The number of accesses so far, used as the time for replacement
+    time: u64,
+    random: Random,
+    report: CacheReport,
+}
+
+impl Cache {
+    /// Create an empty cache. Panics if the config is invalid.
+    pub fn new(config: CacheConfig) -> Self {
+        if let Err(e) = config.validate() {
+            panic!("Invalid cache config: {}", e);
+        }
+
+        let seed = match config.replacement {
+            ReplacementPolicy::Random { seed } => seed,
+            _ => 0,
+        };
+
+        Self {
+            config,
+            lines: vec![Line::default(); (config.set_count() * config.associativity) as usize],
+            time: 0,
+            random: Random::new(seed),
+            report: CacheReport {
+                config,
+                reads: 0,
+                read_hits: 0,
+                writes: 0,
+                write_hits: 0,
+                evictions: 0,
+                write_backs: 0,
+                memory_reads: 0,
+                memory_writes: 0,
+            },
+        }
+    }
+
+    /// Access `size` bytes at the address. Each block in the range is a
+    /// separate access.
+    pub fn access(&mut self, address: u32, size: u32, write: bool) {
+        let first_block = address / self.config.block_size;
+        let last_block = address.saturating_add(size.max(1) - 1) / self.config.block_size;
+
+        for block in first_block..=last_block {
+            self.access_block(block, write);
+        }
+    }
+
+    fn access_block(&mut self, block: u32, write: bool) {
+        let set_count = self.config.set_count();
+        let set = (block % set_count) as usize;
+        let tag = block / set_count;
+        let ways = self.config.associativity as usize;
+        let write_back = self.config.write_policy == WritePolicy::WriteBack;
+        self.time += 1;
+
+        let report = &mut self.report;
+        if write {
+            report.writes += 1;
+        } else {
+            report.reads += 1;
+        }
+
+        let lines = &mut self.lines[set * ways..(set + 1) * ways];
+        if let Some(line) = lines.iter_mut().find(|line| line.valid && line.tag == tag) {
+            line.last_used = self.time;
+            if write {
+                report.write_hits += 1;
+                if write_back {
+                    line.dirty = true;
+                } else {
+                    report.memory_writes += 1;
+                }
+            } else {
+                report.read_hits += 1;
+            }
+            return;
+        }
+
+        if write && !self.config.write_allocate {
+            report.memory_writes += 1;
+            return;
+        }
+
+        // Fill an empty line, or evict one
+        let victim = match lines.iter().position(|line| !line.valid) {
+            Some(index) => index,
+            None => {
+                let index = match self.config.replacement {
+                    ReplacementPolicy::Lru => (0..ways).min_by_key(|i| lines[*i].last_used),
+                    ReplacementPolicy::Fifo => (0..ways).min_by_key(|i| lines[*i].filled),
+                    ReplacementPolicy::Random { .. } => {
+                        Some(self.random.next_int_bounded(ways as i32) as usize)
+                    }
+                }
+                .unwrap_or(0);
+
+                report.evictions += 1;
+                if lines[index].dirty {
+                    report.write_backs += 1;
+                }
+                index
+            }
+        };
+
+        report.memory_reads += 1;
+        if write && !write_back {
+            report.memory_writes += 1;
+        }
+        lines[victim] = Line {
+            valid: true,
+            dirty: write && write_back,
+            tag,
+            last_used: self.time,
+            filled: self.time,
+        };
+    }
+}
+
+impl Processor {
+    /// Send the last instruction's fetch and loads/stores to the caches
+    pub(crate) fn record_cache_accesses(&mut self, program_counter: u32, instruction: Instruction) {
+        if let Some(cache) = &mut self.instruction_cache {
+            cache.access(program_counter, 4, false);
+        }
+
+        // Only the program's loads and stores go through the data cache (not
+        // the syscalls' reads and writes)
+        let class = InstructionClass::of(instruction);
+        if let (Some(cache), Some(memory_accesses)) = (&mut self.data_cache, &self.memory_accesses)
+        {
+            if class == InstructionClass::Load || class == InstructionClass::Store {
+                for memory_access in memory_accesses {
+                    let write = memory_access.access == Access::Write;
+                    cache.access(memory_access.address, memory_access.size, write);
+                }
+            }
+        }
+    }
+
+    /// Get the instruction cache's report, if it is enabled
+    pub fn instruction_cache_report(&self) -> Option<CacheReport> {
+        self.instruction_cache
+            .as_ref()
+            .map(|cache| cache.report.clone())
+    }
+
+    /// Get the data cache's report, if it is enabled
+    pub fn data_cache_report(&self) -> Option<CacheReport> {
+        self.data_cache.as_ref().map(|cache| cache.report.clone())
+    }
+}
+
+/// The accesses to a cache
+#[derive(Clone, Debug)]
+pub struct CacheReport {
+    pub config: CacheConfig,
+    pub reads: u64,
+    pub read_hits: u64,
+    pub writes: u64,
+    pub write_hits: u64,
+    /// The number of valid blocks which were replaced
+    pub evictions: u64,
+    /// The number of dirty blocks written to memory when they were evicted
+    pub write_backs: u64,
+    /// The number of blocks read from memory
+    pub memory_reads: u64,
+    /// The number of stores written directly to memory (with write-through
+    /// or no-write-allocate)
+    pub memory_writes: u64,
+}
+
+impl CacheReport {
+    pub fn accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.read_hits + self.write_hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.accesses() - self.hits()
+    }
+
+    /// Get the fraction of accesses which hit
+    pub fn hit_rate(&self) -> f64 {
+        if self.accesses() == 0 {
+            0.0
+        } else {
+            self.hits() as f64 / self.accesses() as f64
+        }
+    }
+}
+
+impl Display for CacheReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let config = &self.config;
+        writeln!(
+            f,
+            "{} bytes, {} byte blocks, {}-way, {} replacement, {}, {}",
+            config.size,
+            config.block_size,
+            config.associativity,
+            config.replacement,
+            config.write_policy,
+            if config.write_allocate {
+                "write-allocate"
+            } else {
+                "no-write-allocate"
+            }
+        )?;
+        writeln!(
+            f,
+            "  Reads:         {:>12} ({} hits, {} misses)",
+            self.reads,
+            self.read_hits,
+            self.reads - self.read_hits
+        )?;
+        writeln!(
+            f,
+            "  Writes:        {:>12} ({} hits, {} misses)",
+            self.writes,
+            self.write_hits,
+            self.writes - self.write_hits
+        )?;
+        writeln!(f, "  Hit rate:      {:>11.2}%", 100.0 * self.hit_rate())?;
+        writeln!(
+            f,
+            "  Evictions:     {:>12} ({} dirty)",
+            self.evictions, self.write_backs
+        )?;
+        writeln!(f, "  Memory reads:  {:>12}", self.memory_reads)?;
+        writeln!(f, "  Memory writes: {:>12}", self.memory_writes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, CacheConfig, ReplacementPolicy, WritePolicy};
+    use crate::config::Config;
+    use crate::test_util::{exit, i_type, li, processor, run, SYSCALL};
+    use mips_types::constants::*;
+
+    #[test]
+    fn parse_config() {
+        assert_eq!("4096:16:2".parse(), Ok(CacheConfig::new(4096, 16, 2)));
+        for config in [
+            "4096:16",
+            "4096:16:2:1",
+            "a:16:2",
+            "4096:12:2",
+            "32:16:4",
+            "64:2:1",
+        ] {
+            assert!(config.parse::<CacheConfig>().is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn direct_mapped_conflicts() {
+        // 4 sets of 16 byte blocks
+        let mut cache = Cache::new(CacheConfig::new(64, 16, 1));
+        cache.access(0x100, 4, false);
+        cache.access(0x104, 4, false);
+        cache.access(0x140, 4, false);
+        cache.access(0x100, 4, false);
+        cache.access(0x110, 4, false);
+
+        let report = &cache.report;
+        assert_eq!(report.reads, 5);
+        assert_eq!(report.read_hits, 1);
+        assert_eq!(report.evictions, 2);
+        assert_eq!(report.memory_reads, 4);
+    }
+
+    #[test]
+    fn accesses_across_blocks() {
+        let mut cache = Cache::new(CacheConfig::new(64, 16, 1));
+        cache.access(0x10e, 4, false);
+
+        assert_eq!(cache.report.reads, 2);
+        assert_eq!(cache.report.memory_reads, 2);
+    }
+
+    /// Read the blocks in a 2-way set, and get the number of hits
+    fn replacement_hits(replacement: ReplacementPolicy) -> u64 {
+        let config = CacheConfig {
+            replacement,
+            ..CacheConfig::new(32, 16, 2)
+        };
+        let mut cache = Cache::new(config);
+        for block in [0, 1, 0, 2, 0, 1] {
+            cache.access(block * 16, 4, false);
+        }
+
+        cache.report.read_hits
+    }
+
+    #[test]
+    fn replacement_policies() {
+        // LRU evicts block 1 for block 2, so block 0 stays
+        assert_eq!(replacement_hits(ReplacementPolicy::Lru), 2);
+        // FIFO evicts block 0 for block 2
+        assert_eq!(replacement_hits(ReplacementPolicy::Fifo), 1);
+
+        let seed = 5;
+        let hits = replacement_hits(ReplacementPolicy::Random { seed });
+        assert_eq!(replacement_hits(ReplacementPolicy::Random { seed }), hits);
+    }
+
+    #[test]
+    fn write_back() {
+        let mut cache = Cache::new(CacheConfig::new(16, 16, 1));
+        cache.access(0x100, 4, true);
+        cache.access(0x104, 4, true);
+        cache.access(0x200, 4, false);
+        cache.access(0x300, 4, false);
+
+        let report = &cache.report;
+        assert_eq!(report.writes, 2);
+        assert_eq!(report.write_hits, 1);
+        assert_eq!(report.memory_writes, 0);
+        assert_eq!(report.evictions, 2);
+        // Only the written block is dirty
+        assert_eq!(report.write_backs, 1);
+    }
+
+    #[test]
+    fn write_through_without_allocation() {
+        let config = CacheConfig {
+            write_policy: WritePolicy::WriteThrough,
+            write_allocate: false,
+            ..CacheConfig::new(16, 16, 1)
+        };
+        let mut cache = Cache::new(config);
+        cache.access(0x100, 4, true);
+        cache.access(0x100, 4, false);
+        cache.access(0x104, 4, true);
+        cache.access(0x200, 4, false);
+
+        let report = &cache.report;
+        assert_eq!(report.write_hits, 1);
+        assert_eq!(report.read_hits, 0);
+        assert_eq!(report.memory_writes, 2);
+        assert_eq!(report.memory_reads, 2);
+        assert_eq!(report.write_backs, 0);
+    }
+
+    #[test]
+    fn processor_caches() {
+        let config = Config {
+            instruction_cache: Some(CacheConfig::new(64, 16, 1)),
+            data_cache: Some(CacheConfig::new(64, 16, 1)),
+            ..Config::default()
+        };
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        text.push(i_type(OP_SW, REG_A1, REG_A0, 4));
+        // The syscall's write isn't a data cache access
+        text.extend(li(REG_V0, SYSCALL_PRINT_STR));
+        text.push(SYSCALL);
+        text.extend(exit());
+        let (mut processor, output) = processor(config, "", &text, b"ab\0\0\0\0\0\0");
+        run(&mut processor).unwrap();
+        assert_eq!(output.contents(), b"ab");
+
+        let instruction_cache = processor.instruction_cache_report().unwrap();
+        assert_eq!(instruction_cache.reads, text.len() as u64);
+        assert_eq!(instruction_cache.memory_reads, 3);
+        assert_eq!(instruction_cache.writes, 0);
+
+        let data_cache = processor.data_cache_report().unwrap();
+        assert_eq!(data_cache.reads, 1);
+        assert_eq!(data_cache.writes, 1);
+        assert_eq!(data_cache.write_hits, 1);
+    }
+}
@@ -0,0 +1,339 @@
+//! Tracking the state changed by each instruction, so it can be undone (ex. to
+//! execute in reverse in the debugger).
+//!
+//! Output, input, and files are outside of the processor, so their changes are
+//! not tracked.
+
+use crate::error::ExecutionError;
+use crate::load_delay::PendingLoad;
+use crate::processor::{Processor, StepOutcome};
+use crate::random::Random;
+use mips_types::constants::{CP0_BAD_VADDR, CP0_CAUSE, CP0_EPC, CP0_STATUS};
+use std::collections::HashMap;
+
+/// A piece of state which was changed by an instruction, and its old value
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StateChange {
+    /// A general purpose register
+    Register {
+        register: u8,
+        old_value: u32,
+    },
+    HiRegister(u32),
+    LoRegister(u32),
+    /// A coprocessor 0 register, by register number
+    Cp0Register {
+        register: u8,
+        old_value: u32,
+    },
+    /// A floating point register
+    Cp1Register {
+        register: u8,
+        old_value: u32,
+    },
+    Cp1Condition(bool),
+    /// A byte of memory
+    Memory {
+        address: u32,
+        old_value: u8,
+    },
+    /// The end of the heap
+    ProgramBreak(u32),
+    /// The load whose value had not been written to its register yet
+    PendingLoad(Option<PendingLoad>),
+    /// The running flag and return code (ex. changed by the exit syscalls)
+    Running {
+        running: bool,
+        return_code: i32,
+    },
+    /// A random number generator's state, or None if it was created by the
+    /// instruction
+    RandomGenerator {
+        id: u32,
+        old_state: Option<u64>,
+    },
+}
+
+/// The state changed by executing an instruction
+#[derive(Clone, Debug)]
+pub struct StepChanges {
+    /// The program counter before the instruction
+    pub program_counter: u32,
+    /// The queued program counter before the instruction (it differs from
+    /// `program_counter + 4` in a delay slot)
+    pub next_program_counter: u32,
+    /// If the instruction was in a delay slot
+    pub in_delay_slot: bool,
+    /// The instruction count before the instruction
+    pub instruction_count: u64,
+    /// The other changed state, in the order it was changed
+    pub changes: Vec<StateChange>,
+}
+
+impl Processor {
+    /// Execute the next instruction (see `step`), and get the state it changed
+    pub fn step_with_changes(&mut self) -> (Result<StepOutcome, ExecutionError>, StepChanges) {
+        let registers = self.registers.clone();
+        let cp0 = self.cp0.clone();
+        let cp1 = self.cp1.clone();
+        let program_break = self.program_break;
+        let pending_load = self.pending_load;
+        let (running, return_code) = (self.running, self.return_code);
+        let random_states: HashMap<u32, u64> = self
+            .random_generators
+            .iter()
+            .map(|(id, random)| (*id, random.state()))
+            .collect();
+        let mut step_changes = StepChanges {
+            program_counter: self.program_counter,
+            next_program_counter: self.next_program_counter,
+            in_delay_slot: self.in_delay_slot,
+            instruction_count: self.instruction_count,
+            changes: Vec::new(),
+        };
+
+        self.memory.start_write_log();
+        let result = self.step();
+        let changes = &mut step_changes.changes;
+
+        changes.extend(
+            self.memory
+                .take_write_log()
+                .into_iter()
+                .map(|(address, old_value)| StateChange::Memory { address, old_value }),
+        );
+
+        for register in 1..32 {
+            let old_value = registers.get(register);
+            if self.registers.get(register) != old_value {
+                changes.push(StateChange::Register {
+                    register,
+                    old_value,
+                });
+            }
+        }
+
+        if self.registers.hi_register != registers.hi_register {
+            changes.push(StateChange::HiRegister(registers.hi_register));
+        }
+
+        if self.registers.lo_register != registers.lo_register {
+            changes.push(StateChange::LoRegister(registers.lo_register));
+        }
+
+        for register in [CP0_BAD_VADDR, CP0_STATUS, CP0_CAUSE, CP0_EPC] {
+            let old_value = cp0.get(register);
+            if self.cp0.get(register) != old_value {
+                changes.push(StateChange::Cp0Register {
+                    register,
+                    old_value,
+                });
+            }
+        }
+
+        for register in 0..32 {
+            let old_value = cp1.get(register);
+            if self.cp1.get(register) != old_value {
+                changes.push(StateChange::Cp1Register {
+                    register,
+                    old_value,
+                });
+            }
+        }
+
+        if self.cp1.condition != cp1.condition {
+            changes.push(StateChange::Cp1Condition(cp1.condition));
+        }
+
+        if self.program_break != program_break {
+            changes.push(StateChange::ProgramBreak(program_break));
+        }
+
+        if self.pending_load != pending_load {
+            changes.push(StateChange::PendingLoad(pending_load));
+        }
+
+        if self.running != running || self.return_code != return_code {
+            changes.push(StateChange::Running {
+                running,
+                return_code,
+            });
+        }
+
+        for (id, random) in &self.random_generators {
+            let old_state = random_states.get(id).copied();
+            if old_state != Some(random.state()) {
+                changes.push(StateChange::RandomGenerator { id: *id, old_state });
+            }
+        }
+
+        (result, step_changes)
+    }
+
+    /// Undo the changes made by an instruction. Instructions must be undone in
+    /// the reverse order they were executed in.
+    pub fn undo(&mut self, step_changes: &StepChanges) {
+        self.program_counter = step_changes.program_counter;
+        self.next_program_counter = step_changes.next_program_counter;
+        self.in_delay_slot = step_changes.in_delay_slot;
+        self.instruction_count = step_changes.instruction_count;
+
+        for change in step_changes.changes.iter().rev() {
+            match *change {
+                StateChange::Register {
+                    register,
+                    old_value,
+                } => self.registers.set(register, old_value),
+                StateChange::HiRegister(old_value) => self.registers.hi_register = old_value,
+                StateChange::LoRegister(old_value) => self.registers.lo_register = old_value,
+                StateChange::Cp0Register {
+                    register,
+                    old_value,
+                } => match register {
+                    CP0_BAD_VADDR => self.cp0.bad_vaddr = old_value,
+                    CP0_STATUS => self.cp0.status = old_value,
+                    CP0_CAUSE => self.cp0.cause = old_value,
+                    CP0_EPC => self.cp0.epc = old_value,
+                    _ => {}
+                },
+                StateChange::Cp1Register {
+                    register,
+                    old_value,
+                } => self.cp1.set(register, old_value),
+                StateChange::Cp1Condition(old_value) => self.cp1.condition = old_value,
+                StateChange::Memory { address, old_value } => self.memory.set(address, old_value),
+                StateChange::ProgramBreak(old_value) => self.set_program_break(old_value),
+                StateChange::PendingLoad(old_value) => self.pending_load = old_value,
+                StateChange::Running {
+                    running,
+                    return_code,
+                } => {
+                    self.running = running;
+                    self.return_code = return_code;
+                }
+                StateChange::RandomGenerator { id, old_state } => match old_state {
+                    Some(state) => {
+                        self.random_generators.insert(id, Random::from_state(state));
+                    }
+                    None => {
+                        self.random_generators.remove(&id);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::processor::{Processor, StepOutcome};
+    use crate::test_util::{i_type, li, processor, r_type, SYSCALL};
+    use mips_types::constants::*;
+    use std::collections::BTreeMap;
+
+    /// Describe the state which instructions can change
+    fn state(processor: &Processor) -> String {
+        let random_states: BTreeMap<u32, u64> = processor
+            .random_generators
+            .iter()
+            .map(|(id, random)| (*id, random.state()))
+            .collect();
+
+        format!(
+            "{:?} {:?} {:?} {:#x} {:#x} {} {} {} {} {} {:#x} {:#x} {:?} {:?}",
+            processor.registers,
+            processor.cp0,
+            processor.cp1,
+            processor.program_counter,
+            processor.next_program_counter,
+            processor.in_delay_slot,
+            processor.instruction_count,
+            processor.running,
+            processor.return_code,
+            processor.program_break,
+            processor.memory.get_word(DATA_OFFSET),
+            processor.memory.get_word(DATA_OFFSET + 4),
+            processor.pending_load,
+            random_states,
+        )
+    }
+
+    fn syscall(text: &mut Vec<u32>, operation: u32, arguments: &[u32]) {
+        for (register, value) in [REG_A0, REG_A1].iter().zip(arguments) {
+            text.extend(li(*register, *value));
+        }
+        text.extend(li(REG_V0, operation));
+        text.push(SYSCALL);
+    }
+
+    #[test]
+    fn undo_every_step() {
+        let mut text = Vec::new();
+        text.extend(li(REG_A0, DATA_OFFSET));
+        text.extend(li(REG_A1, 0x1234_5678));
+        text.push(i_type(OP_SW, REG_A1, REG_A0, 0));
+        text.push(i_type(OP_SB, REG_A1, REG_A0, 6));
+        text.push(r_type(FUNCTION_MULT, 0, REG_A1, REG_A1));
+        text.push(i_type(OP_BEQ, 0, 0, 2));
+        // The delay slot, then the branch skips an instruction
+        text.push(i_type(OP_ORI, REG_A2, 0, 5));
+        text.push(i_type(OP_ORI, REG_A2, 0, 6));
+        syscall(&mut text, SYSCALL_SET_SEED, &[1, 99]);
+        syscall(&mut text, SYSCALL_RANDOM_INT, &[1]);
+        syscall(&mut text, SYSCALL_RANDOM_INT, &[2]);
+        syscall(&mut text, SYSCALL_SBRK, &[16]);
+        syscall(&mut text, SYSCALL_EXIT2, &[3]);
+        let config = Config {
+            enable_delay_slots: true,
+            ..Config::default()
+        };
+        let (mut processor, _) = processor(config, "", &text, &[0; 8]);
+
+        let mut history = Vec::new();
+        loop {
+            let before = state(&processor);
+            let (result, step_changes) = processor.step_with_changes();
+            history.push((before, step_changes));
+            if result.unwrap() != StepOutcome::Running {
+                break;
+            }
+        }
+        assert_eq!(processor.return_code, 3);
+        assert_eq!(processor.registers.get(REG_A2), 5);
+
+        for (before, step_changes) in history.iter().rev() {
+            processor.undo(step_changes);
+            assert_eq!(&state(&processor), before);
+        }
+    }
+
+    #[test]
+    fn undo_a_load_in_a_load_delay_slot() {
+        let mut text = Vec::new();
+        text.extend(li(REG_A0, DATA_OFFSET));
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 4));
+        text.push(i_type(OP_ORI, REG_A2, REG_A1, 0));
+        let config = Config {
+            enable_load_delay_slots: true,
+            ..Config::default()
+        };
+        let (mut processor, _) = processor(config, "", &text, &[0, 0, 0, 1, 0, 0, 0, 2]);
+
+        let mut history = Vec::new();
+        for _ in 0..text.len() {
+            let before = state(&processor);
+            let (result, step_changes) = processor.step_with_changes();
+            result.unwrap();
+            history.push((before, step_changes));
+        }
+        // The second load's delay slot sees the first load's value
+        assert_eq!(processor.registers.get(REG_A2), 1);
+
+        for (before, step_changes) in history.iter().rev() {
+            processor.undo(step_changes);
+            assert_eq!(&state(&processor), before);
+        }
+    }
+}
@@ -1,14 +1,56 @@
+use crate::branch_prediction::BranchPredictionConfig;
+use crate::cache::CacheConfig;
+use crate::pipeline::PipelineConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+
 /// The config for `Processor`
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Config {
     /// Enables jump/branch delay slots
     pub enable_delay_slots: bool,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            enable_delay_slots: false,
-        }
-    }
+    /// Enables load delay slots: the instruction after a load still sees the
+    /// loaded register's old value, like the R2000
+    pub enable_load_delay_slots: bool,
+    /// Reports when an instruction reads the register loaded by the
+    /// instruction before it (see `Processor::load_delay_hazards`)
+    pub warn_load_delay_slots: bool,
+    /// Allows unaligned word/half word accesses and instruction fetches instead
+    /// of raising address errors
+    pub allow_unaligned_access: bool,
+    /// Maps the SPIM/MARS console device (connected to stdin/stdout) at
+    /// `CONSOLE_ADDRESS`
+    pub enable_mapped_io: bool,
+    /// Allows any address to be read, written, or executed instead of faulting
+    /// on accesses outside of the program's segments
+    pub disable_memory_protection: bool,
+    /// The maximum size of the heap in bytes. By default, the heap can grow
+    /// until it reaches the stack.
+    pub heap_limit: Option<u32>,
+    /// The directory which the file syscalls can access. If not set, programs
+    /// can not open files.
+    pub file_sandbox: Option<PathBuf>,
+    /// The initial seed of the random number generators. If not set, they are
+    /// seeded from the current time.
+    pub random_seed: Option<u64>,
+    /// The maximum number of instructions to execute. The processor stops
+    /// with `StepOutcome::LimitReached` when it is reached.
+    pub max_instructions: Option<u64>,
+    /// The maximum time to run for, starting from the first instruction. The
+    /// processor stops with `StepOutcome::LimitReached` when it is reached.
+    pub timeout: Option<Duration>,
+    /// Enables the pipeline timing model, which counts the cycles the
+    /// program would take in a five-stage pipeline. It is not saved in
+    /// snapshots.
+    pub pipeline: Option<PipelineConfig>,
+    /// Enables the instruction cache model, which counts the hits and misses
+    /// of the instruction fetches. It is not saved in snapshots.
+    pub instruction_cache: Option<CacheConfig>,
+    /// Enables the data cache model, which counts the hits and misses of the
+    /// loads and stores. It is not saved in snapshots.
+    pub data_cache: Option<CacheConfig>,
+    /// Enables the branch predictors, which count how many of the conditional
+    /// branches and jumps they would have predicted correctly. It is not
+    /// saved in snapshots.
+    pub branch_prediction: Option<BranchPredictionConfig>,
 }
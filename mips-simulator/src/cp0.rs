@@ -0,0 +1,117 @@
+use mips_types::constants::{CP0_BAD_VADDR, CP0_CAUSE, CP0_EPC, CP0_STATUS};
+
+/// The Cause register's branch delay bit
+const CAUSE_BRANCH_DELAY: u32 = 0x80000000;
+/// The bits of the Cause register which hold the exception code
+const CAUSE_EXCEPTION_CODE_MASK: u32 = 0x7C;
+/// The bits of the Cause register which can be written by software (the
+/// software interrupt bits)
+const CAUSE_WRITABLE_MASK: u32 = 0x300;
+/// The bits of the Status register which hold the kernel/user mode and
+/// interrupt enable stack (current, previous, and old)
+const STATUS_MODE_STACK_MASK: u32 = 0x3F;
+
+/// Coprocessor 0 (the system control coprocessor), which handles exceptions
+#[derive(Clone, Debug, Default)]
+pub struct Coprocessor0 {
+    pub status: u32,
+    pub cause: u32,
+    pub epc: u32,
+    pub bad_vaddr: u32,
+}
+
+impl Coprocessor0 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the value of a register. Unimplemented registers read as zero.
+    pub fn get(&self, register: u8) -> u32 {
+        match register {
+            CP0_BAD_VADDR => self.bad_vaddr,
+            CP0_STATUS => self.status,
+            CP0_CAUSE => self.cause,
+            CP0_EPC => self.epc,
+            _ => 0,
+        }
+    }
+
+    /// Set the value of a register. Writes to read-only registers or bits are
+    /// ignored.
+    pub fn set(&mut self, register: u8, value: u32) {
+        match register {
+            CP0_STATUS => self.status = value,
+            CP0_CAUSE => {
+                self.cause = (self.cause & !CAUSE_WRITABLE_MASK) | (value & CAUSE_WRITABLE_MASK)
+            }
+            CP0_EPC => self.epc = value,
+            _ => {}
+        }
+    }
+
+    /// Record an exception. The mode stack in the Status register is pushed,
+    /// which puts the processor in kernel mode with interrupts disabled.
+    pub fn enter_exception(&mut self, exception_code: u8, epc: u32, in_delay_slot: bool) {
+        self.epc = epc;
+        self.cause &= !(CAUSE_EXCEPTION_CODE_MASK | CAUSE_BRANCH_DELAY);
+        self.cause |= (exception_code as u32) << 2;
+        if in_delay_slot {
+            self.cause |= CAUSE_BRANCH_DELAY;
+        }
+
+        let mode_stack = (self.status << 2) & STATUS_MODE_STACK_MASK;
+        self.status = (self.status & !STATUS_MODE_STACK_MASK) | mode_stack;
+    }
+
+    /// Pop the mode stack in the Status register (rfe)
+    pub fn return_from_exception(&mut self) {
+        let mode_stack = (self.status & STATUS_MODE_STACK_MASK) >> 2;
+        self.status = (self.status & !0xF) | (mode_stack & 0xF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coprocessor0;
+    use mips_types::constants::*;
+
+    #[test]
+    fn registers() {
+        let mut cp0 = Coprocessor0::new();
+        cp0.set(CP0_STATUS, 0xff01);
+        cp0.set(CP0_EPC, 0x40_0010);
+        cp0.set(CP0_BAD_VADDR, 0x1234);
+        // Only the software interrupt bits of Cause can be written
+        cp0.set(CP0_CAUSE, u32::MAX);
+
+        assert_eq!(cp0.get(CP0_STATUS), 0xff01);
+        assert_eq!(cp0.get(CP0_EPC), 0x40_0010);
+        assert_eq!(cp0.get(CP0_BAD_VADDR), 0);
+        assert_eq!(cp0.get(CP0_CAUSE), 0x300);
+        assert_eq!(cp0.get(0), 0);
+    }
+
+    #[test]
+    fn exception_mode_stack() {
+        let mut cp0 = Coprocessor0::new();
+        // User mode with interrupts enabled, and the interrupt mask set
+        cp0.status = 0xff03;
+        cp0.cause = 0x300;
+
+        cp0.enter_exception(EXC_OVERFLOW, 0x40_0010, true);
+        assert_eq!(cp0.status, 0xff0c);
+        assert_eq!(cp0.cause, 0x8000_0300 | (EXC_OVERFLOW as u32) << 2);
+        assert_eq!(cp0.epc, 0x40_0010);
+
+        // The old exception code and branch delay bit are replaced
+        cp0.enter_exception(EXC_SYSCALL, 0x40_0020, false);
+        assert_eq!(cp0.status, 0xff30);
+        assert_eq!(cp0.cause, 0x300 | (EXC_SYSCALL as u32) << 2);
+
+        // The old mode is kept when the stack is popped
+        cp0.return_from_exception();
+        assert_eq!(cp0.status, 0xff3c);
+        cp0.return_from_exception();
+        assert_eq!(cp0.status, 0xff3f);
+    }
+}
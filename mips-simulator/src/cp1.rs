@@ -0,0 +1,53 @@
+/// Coprocessor 1 (the floating point unit). Double precision values are held
+/// in even/odd register pairs, with the low word in the even register.
+#[derive(Clone, Debug, Default)]
+pub struct Coprocessor1 {
+    registers: [u32; 32],
+    /// The condition flag, which is set by the compare operations and tested
+    /// by bc1t/bc1f
+    pub condition: bool,
+}
+
+impl Coprocessor1 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the raw value of a register
+    pub fn get(&self, register: u8) -> u32 {
+        self.registers[register as usize]
+    }
+
+    /// Set the raw value of a register
+    pub fn set(&mut self, register: u8, value: u32) {
+        self.registers[register as usize] = value;
+    }
+
+    /// Get the single precision value of a register
+    pub fn get_single(&self, register: u8) -> f32 {
+        f32::from_bits(self.get(register))
+    }
+
+    /// Set the single precision value of a register
+    pub fn set_single(&mut self, register: u8, value: f32) {
+        self.set(register, value.to_bits());
+    }
+
+    /// Get the double precision value of a register pair. The low bit of the
+    /// register number is ignored.
+    pub fn get_double(&self, register: u8) -> f64 {
+        let register = register & !1;
+        let low = self.get(register) as u64;
+        let high = self.get(register + 1) as u64;
+        f64::from_bits(high << 32 | low)
+    }
+
+    /// Set the double precision value of a register pair. The low bit of the
+    /// register number is ignored.
+    pub fn set_double(&mut self, register: u8, value: f64) {
+        let register = register & !1;
+        let bits = value.to_bits();
+        self.set(register, bits as u32);
+        self.set(register + 1, (bits >> 32) as u32);
+    }
+}
@@ -0,0 +1,117 @@
+//! Decoding instructions into the operations which execute them. Decoded
+//! instructions are cached by memory, so each instruction word is only decoded
+//! once (until it is overwritten).
+
+use crate::error::ExecutionErrorCause;
+use crate::instruction::Instruction;
+use crate::processor::Processor;
+use mips_types::constants::*;
+
+/// A function which executes an instruction
+pub(crate) type Operation = fn(&mut Processor, Instruction) -> Result<(), ExecutionErrorCause>;
+
+/// An instruction and the operation which executes it
+#[derive(Copy, Clone)]
+pub(crate) struct DecodedInstruction {
+    pub instruction: Instruction,
+    pub operation: Operation,
+}
+
+impl DecodedInstruction {
+    pub fn new(instruction: Instruction) -> Self {
+        Self {
+            instruction,
+            operation: decode(instruction),
+        }
+    }
+}
+
+/// Wrap a processor operation in an `Operation`. Operations which can fail are
+/// marked with `?`.
+macro_rules! operation {
+    ($name:ident) => {
+        |processor, instruction| {
+            processor.$name(instruction);
+            Ok(())
+        }
+    };
+    ($name:ident?) => {
+        |processor, instruction| processor.$name(instruction)
+    };
+    ($name:ident()?) => {
+        |processor, _| processor.$name()
+    };
+}
+
+/// Get the operation which executes the instruction
+pub(crate) fn decode(instruction: Instruction) -> Operation {
+    match instruction.op_code() {
+        OP_R_TYPE => match instruction.function() {
+            FUNCTION_SLL => operation!(op_sll),
+            FUNCTION_SRL => operation!(op_srl),
+            FUNCTION_SRA => operation!(op_sra),
+            FUNCTION_SLLV => operation!(op_sllv),
+            FUNCTION_SRLV => operation!(op_srlv),
+            FUNCTION_SRAV => operation!(op_srav),
+            FUNCTION_JR => operation!(op_jr),
+            FUNCTION_JALR => operation!(op_jalr),
+            FUNCTION_SYSCALL => operation!(op_syscall()?),
+            FUNCTION_BREAK => operation!(op_break()?),
+            FUNCTION_MFHI => operation!(op_mfhi),
+            FUNCTION_MTHI => operation!(op_mthi),
+            FUNCTION_MFLO => operation!(op_mflo),
+            FUNCTION_MTLO => operation!(op_mtlo),
+            FUNCTION_MULT => operation!(op_mult),
+            FUNCTION_MULTU => operation!(op_multu),
+            FUNCTION_DIV => operation!(op_div?),
+            FUNCTION_DIVU => operation!(op_divu?),
+            FUNCTION_ADD => operation!(op_add?),
+            FUNCTION_ADDU => operation!(op_addu),
+            FUNCTION_SUB => operation!(op_sub?),
+            FUNCTION_SUBU => operation!(op_subu),
+            FUNCTION_AND => operation!(op_and),
+            FUNCTION_OR => operation!(op_or),
+            FUNCTION_XOR => operation!(op_xor),
+            FUNCTION_NOR => operation!(op_nor),
+            FUNCTION_SLT => operation!(op_slt),
+            FUNCTION_SLTU => operation!(op_sltu),
+            _ => reserved_instruction,
+        },
+        OP_BCOND => operation!(op_bcond?),
+        OP_J => operation!(op_j),
+        OP_JAL => operation!(op_jal),
+        OP_BEQ => operation!(op_beq),
+        OP_BNE => operation!(op_bne),
+        OP_BLEZ => operation!(op_blez),
+        OP_BGTZ => operation!(op_bgtz),
+        OP_ADDI => operation!(op_addi?),
+        OP_ADDIU => operation!(op_addiu),
+        OP_SLTI => operation!(op_slti),
+        OP_SLTIU => operation!(op_sltiu),
+        OP_ANDI => operation!(op_andi),
+        OP_ORI => operation!(op_ori),
+        OP_XORI => operation!(op_xori),
+        OP_LUI => operation!(op_lui),
+        OP_COP0 => operation!(op_cop0?),
+        OP_COP1 => operation!(op_cop1?),
+        OP_LB => operation!(op_lb?),
+        OP_LH => operation!(op_lh?),
+        OP_LWL => operation!(op_lwl?),
+        OP_LW => operation!(op_lw?),
+        OP_LBU => operation!(op_lbu?),
+        OP_LHU => operation!(op_lhu?),
+        OP_LWR => operation!(op_lwr?),
+        OP_SB => operation!(op_sb?),
+        OP_SH => operation!(op_sh?),
+        OP_SWL => operation!(op_swl?),
+        OP_SW => operation!(op_sw?),
+        OP_SWR => operation!(op_swr?),
+        OP_LWC1 => operation!(op_lwc1?),
+        OP_SWC1 => operation!(op_swc1?),
+        _ => reserved_instruction,
+    }
+}
+
+fn reserved_instruction(_: &mut Processor, _: Instruction) -> Result<(), ExecutionErrorCause> {
+    Err(ExecutionErrorCause::ReservedInstruction)
+}
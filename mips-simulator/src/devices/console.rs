@@ -0,0 +1,220 @@
+use crate::devices::{register_bytes, Device};
+use crate::streams::PollingInput;
+use std::io::Write;
+
+/// The address of the console device (the same as SPIM and MARS)
+pub const CONSOLE_ADDRESS: u32 = 0xffff0000;
+/// The size of the console device's address range
+pub const CONSOLE_SIZE: u32 = 0x10;
+
+// Register offsets
+const RECEIVER_CONTROL: u32 = 0x0;
+const RECEIVER_DATA: u32 = 0x4;
+const TRANSMITTER_CONTROL: u32 = 0x8;
+const TRANSMITTER_DATA: u32 = 0xc;
+
+// Control register bits
+const CONTROL_READY: u32 = 0x1;
+const CONTROL_INTERRUPT_ENABLE: u32 = 0x2;
+
+/// A SPIM/MARS compatible console device with a receiver (keyboard) and a
+/// transmitter (display).
+///
+/// The receiver reads from the input one character at a time. Reading the
+/// receiver control register checks for the next character without waiting
+/// for it, so it is only ready once the character has arrived. Reading the
+/// receiver data register consumes the character. At the end of the input,
+/// the receiver is never ready again (so a program polling for more input
+/// runs until it is stopped by a limit).
+/// The transmitter is always ready, and writes the lowest byte stored to its
+/// data register to the output.
+pub struct ConsoleDevice {
+    input: PollingInput,
+    output: Box<dyn Write>,
+    received: Option<u8>,
+    receiver_control: u32,
+    transmitter_control: u32,
+}
+
+impl ConsoleDevice {
+    pub fn new(input: PollingInput, output: Box<dyn Write>) -> Self {
+        Self {
+            input,
+            output,
+            received: None,
+            receiver_control: 0,
+            transmitter_control: CONTROL_READY,
+        }
+    }
+
+    /// Try to receive a character if we don't have one already
+    fn poll_input(&mut self) {
+        if self.received.is_some() {
+            return;
+        }
+
+        match self.input.try_read_byte() {
+            Ok(byte) => self.received = byte,
+            Err(e) => warn!("Console device failed to read input: {}", e),
+        }
+    }
+
+    /// Write a character to the output
+    fn transmit(&mut self, byte: u8) {
+        let result = self
+            .output
+            .write_all(&[byte])
+            .and_then(|_| self.output.flush());
+
+        if let Err(e) = result {
+            warn!("Console device failed to write output: {}", e);
+        }
+    }
+}
+
+impl Device for ConsoleDevice {
+    fn load(&mut self, offset: u32, size: u32) -> u32 {
+        let register = match offset & !3 {
+            RECEIVER_CONTROL => {
+                self.poll_input();
+                let ready = if self.received.is_some() {
+                    CONTROL_READY
+                } else {
+                    0
+                };
+
+                self.receiver_control | ready
+            }
+            RECEIVER_DATA => self.received.take().unwrap_or(0) as u32,
+            TRANSMITTER_CONTROL => self.transmitter_control,
+            _ => 0,
+        };
+
+        register_bytes(register, offset, size)
+    }
+
+    fn store(&mut self, offset: u32, _size: u32, value: u32) {
+        match offset & !3 {
+            RECEIVER_CONTROL => self.receiver_control = value & CONTROL_INTERRUPT_ENABLE,
+            TRANSMITTER_CONTROL => {
+                self.transmitter_control = CONTROL_READY | (value & CONTROL_INTERRUPT_ENABLE)
+            }
+            TRANSMITTER_DATA => self.transmit(value as u8),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streams::SharedBuffer;
+    use std::io;
+    use std::io::{Cursor, Read};
+    use std::sync::mpsc;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    fn console(input: impl Read + Send + 'static) -> (ConsoleDevice, SharedBuffer) {
+        let output = SharedBuffer::new();
+        let device =
+            ConsoleDevice::new(PollingInput::new(Box::new(input)), Box::new(output.clone()));
+
+        (device, output)
+    }
+
+    /// Poll the receiver until it is ready (or give up), and get its control
+    /// register
+    fn poll_until_ready(device: &mut ConsoleDevice) -> u32 {
+        for _ in 0..1000 {
+            let control = device.load(RECEIVER_CONTROL, 4);
+            if control & CONTROL_READY != 0 {
+                return control;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        device.load(RECEIVER_CONTROL, 4)
+    }
+
+    /// An input which waits for each byte to be sent by the test
+    struct Keyboard(Receiver<u8>);
+
+    impl Read for Keyboard {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.recv() {
+                Ok(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Err(_) => Ok(0),
+            }
+        }
+    }
+
+    fn keyboard() -> (ConsoleDevice, Sender<u8>) {
+        let (sender, receiver) = mpsc::channel();
+        (console(Keyboard(receiver)).0, sender)
+    }
+
+    #[test]
+    fn receive() {
+        let (mut device, _) = console(Cursor::new(b"ab".to_vec()));
+
+        assert_eq!(poll_until_ready(&mut device), CONTROL_READY);
+        // Polling again doesn't skip the character
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), CONTROL_READY);
+        assert_eq!(device.load(RECEIVER_DATA, 4), b'a' as u32);
+        assert_eq!(poll_until_ready(&mut device), CONTROL_READY);
+        assert_eq!(device.load(RECEIVER_DATA + 3, 1), b'b' as u32);
+
+        // The input has ended
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+        assert_eq!(device.load(RECEIVER_DATA, 4), 0);
+    }
+
+    #[test]
+    fn polling_does_not_wait_for_input() {
+        let (mut device, keyboard) = keyboard();
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+
+        keyboard.send(b'x').unwrap();
+        assert_eq!(poll_until_ready(&mut device), CONTROL_READY);
+        assert_eq!(device.load(RECEIVER_DATA, 4), b'x' as u32);
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+
+        drop(keyboard);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), 0);
+    }
+
+    #[test]
+    fn transmit() {
+        let (mut device, output) = console(io::empty());
+
+        assert_eq!(device.load(TRANSMITTER_CONTROL, 4), CONTROL_READY);
+        device.store(TRANSMITTER_DATA, 4, 0x1234_5668);
+        device.store(TRANSMITTER_DATA, 4, b'i' as u32);
+        assert_eq!(output.contents(), b"hi");
+    }
+
+    #[test]
+    fn interrupt_enable_bits() {
+        let (mut device, _) = console(io::empty());
+
+        device.store(RECEIVER_CONTROL, 4, u32::MAX);
+        assert_eq!(device.load(RECEIVER_CONTROL, 4), CONTROL_INTERRUPT_ENABLE);
+        device.store(TRANSMITTER_CONTROL, 4, 0);
+        assert_eq!(device.load(TRANSMITTER_CONTROL, 4), CONTROL_READY);
+        device.store(TRANSMITTER_CONTROL, 4, u32::MAX);
+        assert_eq!(
+            device.load(TRANSMITTER_CONTROL, 4),
+            CONTROL_READY | CONTROL_INTERRUPT_ENABLE
+        );
+    }
+}
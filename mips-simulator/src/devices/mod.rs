@@ -0,0 +1,39 @@
+//! Memory-mapped I/O devices
+
+mod console;
+
+pub use console::{ConsoleDevice, CONSOLE_ADDRESS, CONSOLE_SIZE};
+
+/// A memory-mapped I/O device. Loads and stores in the device's address range
+/// are sent to the device instead of RAM.
+pub trait Device {
+    /// Load `size` bytes (1, 2, or 4) at the offset from the device's address
+    fn load(&mut self, offset: u32, size: u32) -> u32;
+
+    /// Store the lower `size` bytes (1, 2, or 4) of the value at the offset
+    /// from the device's address
+    fn store(&mut self, offset: u32, size: u32, value: u32);
+}
+
+/// Get the bytes of a word-sized device register which are covered by an
+/// access of `size` bytes at the offset.
+pub fn register_bytes(register: u32, offset: u32, size: u32) -> u32 {
+    let shift = 8 * (4 - size - (offset & 3));
+    let mask = u32::MAX >> (8 * (4 - size));
+
+    (register >> shift) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::register_bytes;
+
+    #[test]
+    fn partial_register_loads() {
+        assert_eq!(register_bytes(0x1122_3344, 0, 4), 0x1122_3344);
+        assert_eq!(register_bytes(0x1122_3344, 0, 2), 0x1122);
+        assert_eq!(register_bytes(0x1122_3344, 2, 2), 0x3344);
+        assert_eq!(register_bytes(0x1122_3344, 1, 1), 0x22);
+        assert_eq!(register_bytes(0x1122_3344, 3, 1), 0x44);
+    }
+}
@@ -0,0 +1,89 @@
+//! Errors raised while executing instructions
+
+use crate::memory_map::Access;
+use mips_types::constants::*;
+use std::io;
+use thiserror::Error;
+
+/// An error which stopped the processor from executing an instruction
+#[derive(Debug, Error)]
+#[error("{cause} at 0x{program_counter:08x} (instruction 0x{instruction:08x})")]
+pub struct ExecutionError {
+    /// The address of the faulting instruction
+    pub program_counter: u32,
+    /// The raw faulting instruction
+    pub instruction: u32,
+    pub cause: ExecutionErrorCause,
+}
+
+/// The reason an instruction failed to execute
+#[derive(Debug, Error)]
+pub enum ExecutionErrorCause {
+    #[error("Address error on load or instruction fetch from 0x{0:08x}")]
+    AddressErrorLoad(u32),
+    #[error("Address error on store to 0x{0:08x}")]
+    AddressErrorStore(u32),
+    #[error("Unmapped address 0x{address:08x} on {access}")]
+    UnmappedAddress { address: u32, access: Access },
+    #[error("Cannot {access} at address 0x{address:08x} in {segment}")]
+    ProtectedAddress {
+        address: u32,
+        access: Access,
+        segment: &'static str,
+    },
+    #[error("Breakpoint")]
+    Breakpoint,
+    #[error("Integer overflow")]
+    IntegerOverflow,
+    #[error("Divide by zero")]
+    DivideByZero,
+    #[error("Reserved instruction")]
+    ReservedInstruction,
+    #[error("Unknown syscall operation {0}")]
+    UnknownSyscall(u32),
+    #[error("Input was not an integer: {0:?}")]
+    InvalidIntegerInput(String),
+    #[error("Input was not a number: {0:?}")]
+    InvalidFloatInput(String),
+    #[error("Invalid upper bound for a random integer: {0}")]
+    InvalidRandomBound(i32),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ExecutionErrorCause {
+    /// Get the exception code to raise for this error, if the processor can
+    /// trap to the exception handler for it.
+    pub fn exception_code(&self) -> Option<u8> {
+        match self {
+            ExecutionErrorCause::AddressErrorLoad(_) => Some(EXC_ADDRESS_LOAD),
+            ExecutionErrorCause::AddressErrorStore(_) => Some(EXC_ADDRESS_STORE),
+            ExecutionErrorCause::UnmappedAddress { access, .. }
+            | ExecutionErrorCause::ProtectedAddress { access, .. } => Some(match (self, access) {
+                (_, Access::Execute) => EXC_BUS_ERROR_FETCH,
+                (ExecutionErrorCause::ProtectedAddress { .. }, Access::Write) => EXC_TLB_MODIFIED,
+                _ => EXC_BUS_ERROR_DATA,
+            }),
+            ExecutionErrorCause::Breakpoint => Some(EXC_BREAKPOINT),
+            ExecutionErrorCause::IntegerOverflow => Some(EXC_OVERFLOW),
+            ExecutionErrorCause::ReservedInstruction => Some(EXC_RESERVED_INSTRUCTION),
+            ExecutionErrorCause::UnknownSyscall(_) => Some(EXC_SYSCALL),
+            ExecutionErrorCause::DivideByZero
+            | ExecutionErrorCause::InvalidIntegerInput(_)
+            | ExecutionErrorCause::InvalidFloatInput(_)
+            | ExecutionErrorCause::InvalidRandomBound(_)
+            | ExecutionErrorCause::Io(_) => None,
+        }
+    }
+
+    /// Get the address which caused an address error or memory fault
+    pub fn bad_address(&self) -> Option<u32> {
+        match self {
+            ExecutionErrorCause::AddressErrorLoad(address)
+            | ExecutionErrorCause::AddressErrorStore(address)
+            | ExecutionErrorCause::UnmappedAddress { address, .. }
+            | ExecutionErrorCause::ProtectedAddress { address, .. } => Some(*address),
+            _ => None,
+        }
+    }
+}
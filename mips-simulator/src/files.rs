@@ -0,0 +1,278 @@
+//! The file descriptor table used by the file syscalls. Programs can only
+//! access files inside of the sandbox directory.
+
+use crate::streams::Streams;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+// Open flags. The access mode is in the lowest two bits.
+const OPEN_ACCESS_MASK: u32 = 0x3;
+const OPEN_READ_ONLY: u32 = 0x0;
+const OPEN_WRITE_ONLY: u32 = 0x1;
+const OPEN_READ_WRITE: u32 = 0x2;
+/// MARS opens files for appending with 9 (write-only | 8)
+const OPEN_APPEND: u32 = 0x8;
+/// SPIM passes the host's flags through, so also accept Linux's O_APPEND
+const OPEN_APPEND_LINUX: u32 = 0x400;
+
+/// An open file descriptor
+#[derive(Debug)]
+enum FileHandle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+#[derive(Debug)]
+pub(crate) struct FileTable {
+    /// The directory which files are opened relative to. If there is no
+    /// sandbox, files can not be opened.
+    sandbox: Option<PathBuf>,
+    /// Handles indexed by file descriptor
+    handles: Vec<Option<FileHandle>>,
+}
+
+impl FileTable {
+    /// Create a file table with stdin, stdout, and stderr open
+    pub fn new(sandbox: Option<PathBuf>) -> Self {
+        Self {
+            sandbox,
+            handles: vec![
+                Some(FileHandle::Stdin),
+                Some(FileHandle::Stdout),
+                Some(FileHandle::Stderr),
+            ],
+        }
+    }
+
+    /// Open a file in the sandbox and get its file descriptor. Files opened
+    /// for writing are created if needed, and truncated unless they are opened
+    /// for appending.
+    pub fn open(&mut self, path: &str, flags: u32) -> io::Result<u32> {
+        let path = self.resolve(path)?;
+        let append = flags & (OPEN_APPEND | OPEN_APPEND_LINUX) != 0;
+        let mut options = OpenOptions::new();
+
+        match flags & OPEN_ACCESS_MASK {
+            OPEN_READ_ONLY => options.read(true),
+            OPEN_WRITE_ONLY => options.write(true),
+            OPEN_READ_WRITE => options.read(true).write(true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid flags")),
+        };
+
+        if flags & OPEN_ACCESS_MASK != OPEN_READ_ONLY {
+            options.create(true).append(append).truncate(!append);
+        }
+
+        debug!("Opening {} with flags 0x{:x}", path.display(), flags);
+        let handle = Some(FileHandle::File(options.open(path)?));
+
+        // Reuse the lowest closed file descriptor
+        let fd = match self.handles.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.handles[fd] = handle;
+                fd
+            }
+            None => {
+                self.handles.push(handle);
+                self.handles.len() - 1
+            }
+        };
+
+        Ok(fd as u32)
+    }
+
+    /// Read from the file descriptor into the buffer. Stdin is read from the
+    /// input stream.
+    pub fn read(&mut self, fd: u32, buffer: &mut [u8], streams: &mut Streams) -> io::Result<usize> {
+        match self.get(fd)? {
+            FileHandle::Stdin => streams.input.read(buffer),
+            FileHandle::File(file) => file.read(buffer),
+            FileHandle::Stdout | FileHandle::Stderr => Err(not_readable()),
+        }
+    }
+
+    /// Write the data to the file descriptor. Stdout and stderr are written to
+    /// the output and error streams.
+    pub fn write(&mut self, fd: u32, data: &[u8], streams: &mut Streams) -> io::Result<usize> {
+        match self.get(fd)? {
+            FileHandle::Stdout => write_flush(&mut streams.output, data),
+            FileHandle::Stderr => write_flush(&mut streams.error, data),
+            FileHandle::File(file) => file.write(data),
+            FileHandle::Stdin => Err(not_writable()),
+        }
+    }
+
+    /// Close the file descriptor
+    pub fn close(&mut self, fd: u32) -> io::Result<()> {
+        self.get(fd)?;
+        self.handles[fd as usize] = None;
+        Ok(())
+    }
+
+    /// Get the handle of an open file descriptor
+    fn get(&mut self, fd: u32) -> io::Result<&mut FileHandle> {
+        self.handles
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Bad file descriptor"))
+    }
+
+    /// Get the host path of a path in the sandbox. Paths which would leave the
+    /// sandbox (absolute paths, "..", or symlinks) are rejected.
+    fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let outside_sandbox = || {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Path is outside of the sandbox: {}", path),
+            )
+        };
+        let sandbox = self.sandbox.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::PermissionDenied, "File access is disabled")
+        })?;
+
+        let mut relative_path = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => relative_path.push(part),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !relative_path.pop() {
+                        return Err(outside_sandbox());
+                    }
+                }
+                Component::RootDir | Component::Prefix(_) => return Err(outside_sandbox()),
+            }
+        }
+
+        // Follow any symlinks to make sure the file is really in the sandbox.
+        // New files are checked by their parent directory. A dangling symlink
+        // would be followed when the file is created, so it is rejected.
+        let host_path = sandbox.join(relative_path);
+        let canonical_path = match host_path.canonicalize() {
+            Ok(canonical_path) => canonical_path,
+            Err(_) if host_path.symlink_metadata().is_ok() => return Err(outside_sandbox()),
+            Err(_) => host_path
+                .parent()
+                .ok_or_else(outside_sandbox)?
+                .canonicalize()?,
+        };
+
+        if canonical_path.starts_with(sandbox.canonicalize()?) {
+            Ok(host_path)
+        } else {
+            Err(outside_sandbox())
+        }
+    }
+}
+
+fn write_flush<W: Write>(output: &mut W, data: &[u8]) -> io::Result<usize> {
+    output.write_all(data)?;
+    output.flush()?;
+    Ok(data.len())
+}
+
+fn not_readable() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "File is not readable")
+}
+
+fn not_writable() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "File is not writable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    /// Create an empty sandbox directory for a test
+    fn sandbox(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "mips-simulator-files-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("dir")).unwrap();
+        path
+    }
+
+    fn is_outside_sandbox(result: io::Result<PathBuf>) -> bool {
+        matches!(result, Err(e) if e.kind() == io::ErrorKind::PermissionDenied)
+    }
+
+    #[test]
+    fn resolve_paths_in_the_sandbox() {
+        let sandbox = sandbox("inside");
+        let files = FileTable::new(Some(sandbox.clone()));
+
+        assert_eq!(files.resolve("new.txt").unwrap(), sandbox.join("new.txt"));
+        assert_eq!(
+            files.resolve("./dir/../dir/new.txt").unwrap(),
+            sandbox.join("dir/new.txt")
+        );
+    }
+
+    #[test]
+    fn reject_paths_outside_of_the_sandbox() {
+        let sandbox = sandbox("outside");
+        let files = FileTable::new(Some(sandbox.join("dir")));
+
+        assert!(is_outside_sandbox(files.resolve("../escape.txt")));
+        assert!(is_outside_sandbox(files.resolve("a/../../escape.txt")));
+        assert!(is_outside_sandbox(files.resolve("/etc/passwd")));
+    }
+
+    #[test]
+    fn reject_files_without_a_sandbox() {
+        let files = FileTable::new(None);
+
+        assert!(is_outside_sandbox(files.resolve("file.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reject_symlinks_out_of_the_sandbox() {
+        use std::os::unix::fs::symlink;
+
+        let sandbox = sandbox("symlinks");
+        fs::write(sandbox.join("secret.txt"), "secret").unwrap();
+        symlink(sandbox.join("secret.txt"), sandbox.join("dir/existing")).unwrap();
+        symlink(sandbox.join("created.txt"), sandbox.join("dir/dangling")).unwrap();
+        symlink(&sandbox, sandbox.join("dir/parent")).unwrap();
+        let mut files = FileTable::new(Some(sandbox.join("dir")));
+
+        assert!(is_outside_sandbox(files.resolve("existing")));
+        assert!(is_outside_sandbox(files.resolve("dangling")));
+        assert!(is_outside_sandbox(files.resolve("parent/new.txt")));
+        assert!(files.open("dangling", OPEN_WRITE_ONLY).is_err());
+        assert!(!sandbox.join("created.txt").exists());
+    }
+
+    #[test]
+    fn open_read_and_write_files() {
+        let sandbox = sandbox("open");
+        let mut files = FileTable::new(Some(sandbox.clone()));
+        let mut streams = Streams::new(
+            Box::new(io::empty()),
+            Box::new(io::sink()),
+            Box::new(io::sink()),
+        );
+
+        let fd = files.open("data.txt", OPEN_WRITE_ONLY).unwrap();
+        assert_eq!(fd, 3);
+        assert_eq!(files.write(fd, b"hello", &mut streams).unwrap(), 5);
+        files.close(fd).unwrap();
+        assert!(files.close(fd).is_err());
+
+        let fd = files.open("data.txt", OPEN_READ_ONLY).unwrap();
+        let mut buffer = [0; 8];
+        assert_eq!(files.read(fd, &mut buffer, &mut streams).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"hello");
+        assert!(files.write(fd, b"!", &mut streams).is_err());
+    }
+}
@@ -37,6 +37,21 @@ impl Instruction {
         (self.0 & 0x0000003F) as u8
     }
 
+    /// Get the fs register (for coprocessor 1 instructions)
+    pub fn fs_register(&self) -> u8 {
+        self.d_register()
+    }
+
+    /// Get the ft register (for coprocessor 1 instructions)
+    pub fn ft_register(&self) -> u8 {
+        self.t_register()
+    }
+
+    /// Get the fd register (for coprocessor 1 instructions)
+    pub fn fd_register(&self) -> u8 {
+        self.shift_amount()
+    }
+
     /// Get the immediate value (for I type instructions)
     pub fn immediate(&self) -> i16 {
         (self.0 & 0x0000FFFF) as i16
@@ -51,7 +66,34 @@ impl Instruction {
     /// The first four bits are taken from the program counter, and the lower
     /// two bits are zeros.
     pub fn real_address(&self, program_counter: u32) -> u32 {
-        (0xF0000000 & (program_counter + 4)) | (self.pseudo_address() << 2)
+        (0xF0000000 & program_counter.wrapping_add(4)) | (self.pseudo_address() << 2)
+    }
+
+    /// Check if this is a break instruction
+    pub fn is_break(&self) -> bool {
+        self.op_code() == OP_R_TYPE && self.function() == FUNCTION_BREAK
+    }
+
+    /// Get the address which a branch or jump goes to, if it is one. Jumps to
+    /// a register don't have a fixed address.
+    pub fn target(&self, program_counter: u32) -> Option<u32> {
+        match self.op_code() {
+            OP_BCOND | OP_BEQ | OP_BNE | OP_BLEZ | OP_BGTZ => {
+                Some(self.branch_address(program_counter))
+            }
+            OP_COP1 if self.s_register() == COP1_RS_BC => {
+                Some(self.branch_address(program_counter))
+            }
+            OP_J | OP_JAL => Some(self.real_address(program_counter)),
+            _ => None,
+        }
+    }
+
+    /// Get the address a branch goes to, which is relative to the next
+    /// instruction
+    fn branch_address(&self, program_counter: u32) -> u32 {
+        let offset = (self.immediate() as i32) << 2;
+        program_counter.wrapping_add(4).wrapping_add(offset as u32)
     }
 
     /// Decode and format the instruction
@@ -174,14 +216,14 @@ impl Instruction {
                 FUNCTION_NOR => dst("nor"),
                 FUNCTION_SLT => dst("slt"),
                 FUNCTION_SLTU => dst("sltu"),
-                function => panic!("Unknown R-type function 0x{:02x}", function),
+                _ => self.unknown(),
             },
             OP_BCOND => match self.t_register() {
                 BCOND_RT_BLTZ => si("bltz"),
                 BCOND_RT_BGEZ => si("bgez"),
                 BCOND_RT_BLTZAL => si("bltzal"),
                 BCOND_RT_BGEZAL => si("bgezal"),
-                rt => panic!("Unknown bcond operation, $rt = 0x{:02x}", rt),
+                _ => self.unknown(),
             },
             OP_J => format!("j 0x{:x}", self.real_address(program_counter)),
             OP_JAL => format!("jal 0x{:x}", self.real_address(program_counter)),
@@ -201,6 +243,21 @@ impl Instruction {
                 Register(self.t_register()),
                 self.immediate() as u16
             ),
+            OP_COP0 => match self.s_register() {
+                COP0_RS_MF => format!(
+                    "mfc0 {}, ${}",
+                    Register(self.t_register()),
+                    self.d_register()
+                ),
+                COP0_RS_MT => format!(
+                    "mtc0 {}, ${}",
+                    Register(self.t_register()),
+                    self.d_register()
+                ),
+                COP0_RS_CO if self.function() == COP0_FUNCTION_RFE => "rfe".to_string(),
+                _ => self.unknown(),
+            },
+            OP_COP1 => self.stringify_cop1(),
             OP_LB => tis("lb"),
             OP_LH => tis("lh"),
             OP_LWL => tis("lwl"),
@@ -213,11 +270,220 @@ impl Instruction {
             OP_SWL => tis("swl"),
             OP_SW => tis("sw"),
             OP_SWR => tis("swr"),
-            op_code => panic!("Unknown op code 0x{:02x}", op_code),
+            OP_LWC1 => format!(
+                "lwc1 {}, {}({})",
+                FloatRegister(self.ft_register()),
+                self.immediate(),
+                Register(self.s_register())
+            ),
+            OP_SWC1 => format!(
+                "swc1 {}, {}({})",
+                FloatRegister(self.ft_register()),
+                self.immediate(),
+                Register(self.s_register())
+            ),
+            _ => self.unknown(),
+        }
+    }
+
+    /// Decode and format a coprocessor 1 instruction
+    fn stringify_cop1(&self) -> String {
+        let fd = FloatRegister(self.fd_register());
+        let fs = FloatRegister(self.fs_register());
+        let ft = FloatRegister(self.ft_register());
+        let format = match self.s_register() {
+            COP1_RS_MF => return format!("mfc1 {}, {}", Register(self.t_register()), fs),
+            COP1_RS_MT => return format!("mtc1 {}, {}", Register(self.t_register()), fs),
+            COP1_RS_BC => {
+                return match self.t_register() {
+                    COP1_BC_RT_BC1F => format!("bc1f {}", self.immediate()),
+                    COP1_BC_RT_BC1T => format!("bc1t {}", self.immediate()),
+                    _ => self.unknown(),
+                }
+            }
+            COP1_FMT_S => "s",
+            COP1_FMT_D => "d",
+            COP1_FMT_W => "w",
+            _ => return self.unknown(),
+        };
+
+        let name = match (self.function(), format) {
+            (COP1_FUNCTION_ADD, "s" | "d") => "add",
+            (COP1_FUNCTION_SUB, "s" | "d") => "sub",
+            (COP1_FUNCTION_MUL, "s" | "d") => "mul",
+            (COP1_FUNCTION_DIV, "s" | "d") => "div",
+            (COP1_FUNCTION_ABS, "s" | "d") => "abs",
+            (COP1_FUNCTION_MOV, "s" | "d") => "mov",
+            (COP1_FUNCTION_NEG, "s" | "d") => "neg",
+            (COP1_FUNCTION_CVT_S, "d" | "w") => "cvt.s",
+            (COP1_FUNCTION_CVT_D, "s" | "w") => "cvt.d",
+            (COP1_FUNCTION_CVT_W, "s" | "d") => "cvt.w",
+            (COP1_FUNCTION_C_EQ, "s" | "d") => "c.eq",
+            (COP1_FUNCTION_C_LT, "s" | "d") => "c.lt",
+            (COP1_FUNCTION_C_LE, "s" | "d") => "c.le",
+            _ => return self.unknown(),
+        };
+
+        match self.function() {
+            COP1_FUNCTION_ADD | COP1_FUNCTION_SUB | COP1_FUNCTION_MUL | COP1_FUNCTION_DIV => {
+                format!("{}.{} {}, {}, {}", name, format, fd, fs, ft)
+            }
+            COP1_FUNCTION_C_EQ | COP1_FUNCTION_C_LT | COP1_FUNCTION_C_LE => {
+                format!("{}.{} {}, {}", name, format, fs, ft)
+            }
+            _ => format!("{}.{} {}, {}", name, format, fd, fs),
+        }
+    }
+
+    /// Format an instruction which could not be decoded
+    fn unknown(&self) -> String {
+        format!("unknown 0x{:08x}", self.0)
+    }
+
+    /// Get the registers read and written by the instruction
+    pub(crate) fn operands(&self) -> Operands {
+        let rs = Operands::register(self.s_register());
+        let rt = Operands::register(self.t_register());
+        let rd = Operands::register(self.d_register());
+        let hi_lo = Operands::register(REG_HI) | Operands::register(REG_LO);
+        let operands = |sources, destinations| Operands {
+            sources,
+            destinations,
+        };
+
+        match self.op_code() {
+            OP_R_TYPE => match self.function() {
+                FUNCTION_SLL | FUNCTION_SRL | FUNCTION_SRA => operands(rt, rd),
+                FUNCTION_JR => operands(rs, 0),
+                FUNCTION_JALR => operands(rs, rd),
+                FUNCTION_SYSCALL => operands(
+                    [REG_V0, REG_A0, REG_A1, REG_A2]
+                        .iter()
+                        .map(|register| Operands::register(*register))
+                        .fold(0, |sources, register| sources | register),
+                    Operands::register(REG_V0),
+                ),
+                FUNCTION_BREAK => operands(0, 0),
+                FUNCTION_MFHI => operands(Operands::register(REG_HI), rd),
+                FUNCTION_MTHI => operands(rs, Operands::register(REG_HI)),
+                FUNCTION_MFLO => operands(Operands::register(REG_LO), rd),
+                FUNCTION_MTLO => operands(rs, Operands::register(REG_LO)),
+                FUNCTION_MULT | FUNCTION_MULTU | FUNCTION_DIV | FUNCTION_DIVU => {
+                    operands(rs | rt, hi_lo)
+                }
+                _ => operands(rs | rt, rd),
+            },
+            OP_BCOND => match self.t_register() {
+                BCOND_RT_BLTZAL | BCOND_RT_BGEZAL => operands(rs, Operands::register(REG_RA)),
+                _ => operands(rs, 0),
+            },
+            OP_J => operands(0, 0),
+            OP_JAL => operands(0, Operands::register(REG_RA)),
+            OP_BEQ | OP_BNE => operands(rs | rt, 0),
+            OP_BLEZ | OP_BGTZ => operands(rs, 0),
+            OP_LUI => operands(0, rt),
+            OP_ADDI..=OP_XORI => operands(rs, rt),
+            OP_COP0 => match self.s_register() {
+                COP0_RS_MF => operands(0, rt),
+                COP0_RS_MT => operands(rt, 0),
+                _ => operands(0, 0),
+            },
+            OP_COP1 => self.cop1_operands(),
+            // The unaligned loads merge the loaded bytes into the register
+            OP_LWL | OP_LWR => operands(rs | rt, rt),
+            OP_LB | OP_LH | OP_LW | OP_LBU | OP_LHU => operands(rs, rt),
+            OP_SB | OP_SH | OP_SWL | OP_SW | OP_SWR => operands(rs | rt, 0),
+            OP_LWC1 => operands(rs, Operands::float_register(self.ft_register(), false)),
+            OP_SWC1 => operands(rs | Operands::float_register(self.ft_register(), false), 0),
+            _ => operands(0, 0),
+        }
+    }
+
+    /// Get the registers read and written by a coprocessor 1 instruction
+    fn cop1_operands(&self) -> Operands {
+        let format = self.s_register();
+        let function = self.function();
+        // Double precision values are held in even/odd register pairs
+        let double = format == COP1_FMT_D || function == COP1_FUNCTION_CVT_D;
+        let fs = Operands::float_register(self.fs_register(), double);
+        let ft = Operands::float_register(self.ft_register(), double);
+        let fd = Operands::float_register(self.fd_register(), double);
+        let condition = Operands::register(REG_FP_CONDITION);
+        let operands = |sources, destinations| Operands {
+            sources,
+            destinations,
+        };
+
+        match format {
+            COP1_RS_MF => operands(
+                Operands::float_register(self.fs_register(), false),
+                Operands::register(self.t_register()),
+            ),
+            COP1_RS_MT => operands(
+                Operands::register(self.t_register()),
+                Operands::float_register(self.fs_register(), false),
+            ),
+            COP1_RS_BC => operands(condition, 0),
+            _ => match function {
+                COP1_FUNCTION_ADD | COP1_FUNCTION_SUB | COP1_FUNCTION_MUL | COP1_FUNCTION_DIV => {
+                    operands(fs | ft, fd)
+                }
+                COP1_FUNCTION_C_EQ | COP1_FUNCTION_C_LT | COP1_FUNCTION_C_LE => {
+                    operands(fs | ft, condition)
+                }
+                _ => operands(fs, fd),
+            },
         }
     }
 }
 
+/// The HI register's number in `Operands`
+pub(crate) const REG_HI: u8 = 32;
+/// The LO register's number in `Operands`
+pub(crate) const REG_LO: u8 = 33;
+/// The first floating point register's number in `Operands`
+pub(crate) const REG_FP_BASE: u8 = 34;
+/// The floating point condition flag's number in `Operands`
+pub(crate) const REG_FP_CONDITION: u8 = REG_FP_BASE + 32;
+/// The number of registers tracked by `Operands`
+pub(crate) const OPERAND_REGISTER_COUNT: usize = REG_FP_CONDITION as usize + 1;
+
+/// The registers read and written by an instruction, as bit sets. Bits 0-31
+/// are the general purpose registers, followed by HI, LO, the floating point
+/// registers, and the floating point condition flag. $zero is never included.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Operands {
+    pub sources: u128,
+    pub destinations: u128,
+}
+
+impl Operands {
+    /// Get the bit for the register
+    fn register(register: u8) -> u128 {
+        if register == 0 {
+            0
+        } else {
+            1 << register
+        }
+    }
+
+    /// Get the bit for the floating point register (and the odd register of
+    /// its pair if `double`)
+    fn float_register(register: u8, double: bool) -> u128 {
+        let bits = 1 << (REG_FP_BASE + register);
+        if double {
+            bits | 1 << (REG_FP_BASE + (register | 1))
+        } else {
+            bits
+        }
+    }
+
+    /// Iterate over the register numbers in the bit set
+    pub fn registers(bits: u128) -> impl Iterator<Item = u8> {
+        (0..OPERAND_REGISTER_COUNT as u8).filter(move |register| bits & 1 << register != 0)
+    }
+}
+
 /// Pretty-print the register using its name
 struct Register(u8);
 
@@ -228,3 +494,73 @@ impl Display for Register {
         f.write_str(REGISTER_NAMES[self.0 as usize])
     }
 }
+
+/// Pretty-print the floating point register
+struct FloatRegister(u8);
+
+impl Display for FloatRegister {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "$f{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Instruction;
+    use crate::test_util::{i_type, r_type};
+    use mips_types::constants::*;
+
+    const PC: u32 = TEXT_OFFSET + 0x10;
+
+    #[test]
+    fn branch_and_jump_targets() {
+        let backwards = (-2i16) as u16;
+        for (instruction, target) in [
+            (i_type(OP_BEQ, 0, 0, 3), Some(PC + 4 + 12)),
+            (i_type(OP_BNE, 0, 0, backwards), Some(PC - 4)),
+            (i_type(OP_BCOND, BCOND_RT_BGEZAL, 0, 1), Some(PC + 8)),
+            (
+                i_type(OP_COP1, COP1_BC_RT_BC1T, COP1_RS_BC, 1),
+                Some(PC + 8),
+            ),
+            ((OP_JAL as u32) << 26 | 0x10_0004, Some(0x40_0010)),
+            // Keeps the upper bits of the PC
+            ((OP_J as u32) << 26 | 4, Some(0x10)),
+            (r_type(FUNCTION_JR, 0, REG_RA, 0), None),
+            (i_type(OP_ADDIU, 0, 0, 3), None),
+            (i_type(OP_COP1, 0, COP1_RS_MT, 0), None),
+        ] {
+            assert_eq!(
+                Instruction(instruction).target(PC),
+                target,
+                "{}",
+                Instruction(instruction).stringify(PC)
+            );
+        }
+        assert_eq!(
+            Instruction((OP_J as u32) << 26 | 4).target(0x9000_0000),
+            Some(0x9000_0010)
+        );
+    }
+
+    #[test]
+    fn stringify() {
+        for (instruction, expected) in [
+            (0, "noop"),
+            (
+                r_type(FUNCTION_ADDU, REG_V0, REG_A0, REG_A1),
+                "addu $v0, $a0, $a1",
+            ),
+            (
+                i_type(OP_ADDIU, REG_SP, REG_SP, (-8i16) as u16),
+                "addiu $sp, $sp, -8",
+            ),
+            (i_type(OP_ORI, REG_A0, 0, 0xffff), "ori $a0, $zero, 0xffff"),
+            (i_type(OP_LW, REG_RA, REG_SP, 4), "lw $ra, 4($sp)"),
+            (i_type(OP_BEQ, REG_A1, REG_A0, 3), "beq $a0, $a1, 3"),
+            ((OP_JAL as u32) << 26 | 0x10_0004, "jal 0x400010"),
+        ] {
+            assert_eq!(Instruction(instruction).stringify(PC), expected);
+        }
+    }
+}
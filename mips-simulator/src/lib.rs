@@ -5,12 +5,33 @@
 #[macro_use]
 extern crate log;
 
+pub mod branch_prediction;
+pub mod cache;
+pub mod changes;
 pub mod config;
+mod cp0;
+mod cp1;
+mod decode;
+pub mod devices;
+pub mod error;
+mod files;
 mod instruction;
+pub mod load_delay;
 mod math;
 mod memory;
+pub mod memory_map;
 mod operations;
+pub mod pipeline;
 mod processor;
+mod random;
 mod registers;
+pub mod snapshot;
+pub mod stats;
+pub mod streams;
+pub mod symbols;
+#[cfg(test)]
+mod test_util;
+pub mod trace;
 
-pub use processor::Processor;
+pub use instruction::Instruction;
+pub use processor::{Limit, Processor, StepOutcome};
@@ -0,0 +1,353 @@
+//! The R2000's load delay slot. A loaded value reaches its register a cycle
+//! late, so the instruction after a load still sees the register's old value.
+//! Reads of a register in its load delay slot can also be reported, since
+//! such programs behave differently with and without load delay slots.
+
+use crate::decode::DecodedInstruction;
+use crate::error::ExecutionErrorCause;
+use crate::instruction::{Instruction, REG_FP_BASE};
+use crate::processor::Processor;
+use mips_types::constants::*;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The register written by a load
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LoadRegister {
+    /// A general purpose register
+    General(u8),
+    /// A floating point register (lwc1)
+    Float(u8),
+}
+
+impl LoadRegister {
+    /// Get the register written by the instruction, if it is a load
+    fn of(instruction: Instruction) -> Option<Self> {
+        match instruction.op_code() {
+            OP_LB | OP_LH | OP_LWL | OP_LW | OP_LBU | OP_LHU | OP_LWR => {
+                match instruction.t_register() {
+                    0 => None,
+                    register => Some(LoadRegister::General(register)),
+                }
+            }
+            OP_LWC1 => Some(LoadRegister::Float(instruction.ft_register())),
+            _ => None,
+        }
+    }
+
+    /// Get the register's bit in `Operands`
+    fn operand_bit(self) -> u128 {
+        match self {
+            LoadRegister::General(register) => 1 << register,
+            LoadRegister::Float(register) => 1 << (REG_FP_BASE + register),
+        }
+    }
+}
+
+impl Display for LoadRegister {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadRegister::General(register) => f.write_str(REGISTER_NAMES[*register as usize]),
+            LoadRegister::Float(register) => write!(f, "$f{}", register),
+        }
+    }
+}
+
+/// A load whose delay slot is the next instruction. With load delay slots,
+/// its value hasn't been written to the register yet.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PendingLoad {
+    /// The address of the load
+    pub program_counter: u32,
+    pub register: LoadRegister,
+    /// The loaded value
+    pub value: u32,
+}
+
+/// An instruction which read the register loaded by the instruction before it
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct LoadDelayHazard {
+    /// The address of the instruction in the delay slot
+    pub program_counter: u32,
+    /// The address of the load
+    pub load_address: u32,
+    pub register: LoadRegister,
+}
+
+impl Display for LoadDelayHazard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The instruction at 0x{:08x} reads {} in the delay slot of the load at 0x{:08x}",
+            self.program_counter, self.register, self.load_address
+        )
+    }
+}
+
+impl Processor {
+    /// Execute the instruction, delaying its register write if it is a load
+    /// and load delay slots are enabled, and checking if it reads the register
+    /// loaded by the last instruction. If the instruction faults, the load
+    /// before it is still pending.
+    pub(crate) fn execute_with_load_delay(
+        &mut self,
+        program_counter: u32,
+        decoded: DecodedInstruction,
+    ) -> Result<(), ExecutionErrorCause> {
+        let instruction = decoded.instruction;
+        let delay_slots = self.config.enable_load_delay_slots;
+        let previous = self.pending_load.take();
+        let load = LoadRegister::of(instruction);
+        // The unaligned loads get the value of a load into the same register
+        // forwarded to them, so lwl/lwr pairs work
+        let forwarded = previous
+            .filter(|previous| {
+                matches!(instruction.op_code(), OP_LWL | OP_LWR) && load == Some(previous.register)
+            })
+            .map(|previous| (previous, self.load_register(previous.register)));
+        self.load_delay_hazard = None;
+
+        if let Some(previous) = previous {
+            if self.config.warn_load_delay_slots
+                && forwarded.is_none()
+                && instruction.operands().sources & previous.register.operand_bit() != 0
+            {
+                let hazard = LoadDelayHazard {
+                    program_counter,
+                    load_address: previous.program_counter,
+                    register: previous.register,
+                };
+                *self.load_delay_hazards.entry(hazard).or_insert(0) += 1;
+                self.load_delay_hazard = Some(hazard);
+            }
+        }
+
+        if delay_slots {
+            if let Some((previous, _)) = forwarded {
+                self.set_load_register(previous.register, previous.value);
+            }
+        }
+
+        let old_value = load.map(|register| self.load_register(register));
+        if let Err(cause) = (decoded.operation)(self, instruction) {
+            if let (true, Some((previous, original_value))) = (delay_slots, forwarded) {
+                self.set_load_register(previous.register, original_value);
+            }
+            self.pending_load = previous;
+            return Err(cause);
+        }
+
+        // The load's value is held back until after the next instruction
+        self.pending_load = load.zip(old_value).map(|(register, old_value)| {
+            let value = self.load_register(register);
+            if delay_slots {
+                self.set_load_register(register, old_value);
+            }
+
+            PendingLoad {
+                program_counter,
+                register,
+                value,
+            }
+        });
+
+        // The last load's value arrives, unless this instruction overwrote
+        // the register (a load into it is still pending, so it doesn't count)
+        if let (true, Some(previous)) = (delay_slots, previous) {
+            let overwritten = load.is_none()
+                && instruction.operands().destinations & previous.register.operand_bit() != 0;
+            if !overwritten {
+                self.set_load_register(previous.register, previous.value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the pending load's value to its register (ex. before jumping to
+    /// the exception handler)
+    pub(crate) fn complete_pending_load(&mut self) {
+        if let Some(pending_load) = self.pending_load.take() {
+            if self.config.enable_load_delay_slots {
+                self.set_load_register(pending_load.register, pending_load.value);
+            }
+        }
+    }
+
+    /// Get the load whose value will be written to its register after the
+    /// next instruction, if load delay slots are enabled
+    pub fn pending_load(&self) -> Option<PendingLoad> {
+        self.pending_load
+            .filter(|_| self.config.enable_load_delay_slots)
+    }
+
+    /// Get the load delay slot hazard caused by the last instruction, if it
+    /// read the register loaded by the instruction before it. Hazards are
+    /// only checked if `warn_load_delay_slots` is enabled.
+    pub fn load_delay_hazard(&self) -> Option<LoadDelayHazard> {
+        self.load_delay_hazard
+    }
+
+    /// Get the load delay slot hazards so far and the number of times each
+    /// happened, sorted by address
+    pub fn load_delay_hazards(&self) -> Vec<(LoadDelayHazard, u64)> {
+        let mut hazards: Vec<_> = self
+            .load_delay_hazards
+            .iter()
+            .map(|(hazard, count)| (*hazard, *count))
+            .collect();
+        hazards.sort_unstable();
+        hazards
+    }
+
+    fn load_register(&self, register: LoadRegister) -> u32 {
+        match register {
+            LoadRegister::General(register) => self.registers.get(register),
+            LoadRegister::Float(register) => self.cp1.get(register),
+        }
+    }
+
+    fn set_load_register(&mut self, register: LoadRegister, value: u32) {
+        match register {
+            LoadRegister::General(register) => self.registers.set(register, value),
+            LoadRegister::Float(register) => self.cp1.set(register, value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoadDelayHazard, LoadRegister, PendingLoad};
+    use crate::config::Config;
+    use crate::processor::{Processor, StepOutcome};
+    use crate::test_util::{exit, i_type, li, processor, r_type, run};
+    use mips_types::constants::*;
+
+    const DATA: [u8; 8] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88];
+
+    /// Run the instructions after loading the data's address into $a0 and 1
+    /// into $a1
+    fn run_with(config: Config, instructions: &[u32]) -> Processor {
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(i_type(OP_ORI, REG_A1, 0, 1));
+        text.extend(instructions);
+        text.extend(exit());
+        let (mut processor, _) = processor(config, "", &text, &DATA);
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+
+        processor
+    }
+
+    fn load_delay_slots() -> Config {
+        Config {
+            enable_load_delay_slots: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn delay_slot_reads_the_old_value() {
+        let instructions = [
+            i_type(OP_LW, REG_A1, REG_A0, 0),
+            r_type(FUNCTION_OR, REG_A2, REG_A1, 0),
+        ];
+
+        let processor = run_with(load_delay_slots(), &instructions);
+        assert_eq!(processor.registers.get(REG_A2), 1);
+        assert_eq!(processor.registers.get(REG_A1), 0x1122_3344);
+
+        let processor = run_with(Config::default(), &instructions);
+        assert_eq!(processor.registers.get(REG_A2), 0x1122_3344);
+    }
+
+    #[test]
+    fn delay_slot_overwrites_the_register() {
+        let processor = run_with(
+            load_delay_slots(),
+            &[
+                i_type(OP_LW, REG_A1, REG_A0, 0),
+                i_type(OP_ORI, REG_A1, 0, 9),
+            ],
+        );
+        assert_eq!(processor.registers.get(REG_A1), 9);
+
+        // A second load to the register in the delay slot wins
+        let processor = run_with(
+            load_delay_slots(),
+            &[
+                i_type(OP_LW, REG_A1, REG_A0, 0),
+                i_type(OP_LW, REG_A1, REG_A0, 4),
+            ],
+        );
+        assert_eq!(processor.registers.get(REG_A1), 0x5566_7788);
+    }
+
+    #[test]
+    fn unaligned_load_pairs() {
+        let processor = run_with(
+            load_delay_slots(),
+            &[
+                i_type(OP_LWL, REG_A1, REG_A0, 1),
+                i_type(OP_LWR, REG_A1, REG_A0, 4),
+            ],
+        );
+        assert_eq!(processor.registers.get(REG_A1), 0x2233_4455);
+    }
+
+    #[test]
+    fn pending_load() {
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        // An unaligned load faults in the delay slot
+        text.push(i_type(OP_LW, REG_A2, REG_A0, 2));
+        let (mut processor, _) = processor(load_delay_slots(), "", &text, &DATA);
+
+        for _ in 0..3 {
+            processor.step().unwrap();
+        }
+        let pending_load = PendingLoad {
+            program_counter: TEXT_OFFSET + 8,
+            register: LoadRegister::General(REG_A1),
+            value: 0x1122_3344,
+        };
+        assert_eq!(processor.pending_load(), Some(pending_load));
+        assert_eq!(processor.registers.get(REG_A1), 0);
+
+        // The load is still pending after the fault
+        assert!(processor.step().is_err());
+        assert_eq!(processor.pending_load(), Some(pending_load));
+        assert_eq!(processor.registers.get(REG_A1), 0);
+    }
+
+    #[test]
+    fn warn_about_hazards() {
+        let config = Config {
+            warn_load_delay_slots: true,
+            ..Config::default()
+        };
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        text.push(r_type(FUNCTION_OR, REG_A2, REG_A1, 0));
+        text.extend(exit());
+        let (mut processor, _) = processor(config, "", &text, &DATA);
+
+        for _ in 0..3 {
+            processor.step().unwrap();
+        }
+        // The load isn't delayed, so it isn't pending
+        assert_eq!(processor.pending_load(), None);
+        processor.step().unwrap();
+        let hazard = LoadDelayHazard {
+            program_counter: TEXT_OFFSET + 12,
+            load_address: TEXT_OFFSET + 8,
+            register: LoadRegister::General(REG_A1),
+        };
+        assert_eq!(processor.load_delay_hazard(), Some(hazard));
+        assert_eq!(processor.registers.get(REG_A2), 0x1122_3344);
+
+        processor.step().unwrap();
+        assert_eq!(processor.load_delay_hazard(), None);
+        run(&mut processor).unwrap();
+        assert_eq!(processor.load_delay_hazards(), [(hazard, 1)]);
+    }
+}
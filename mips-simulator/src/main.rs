@@ -1,45 +1,418 @@
+use mips_simulator::branch_prediction::{BranchPredictionConfig, Predictor};
+use mips_simulator::cache::{CacheConfig, ReplacementPolicy, WritePolicy};
 use mips_simulator::config::Config;
-use mips_simulator::Processor;
+use mips_simulator::pipeline::{BranchStage, PipelineConfig};
+use mips_simulator::snapshot::Snapshot;
+use mips_simulator::trace::TraceFormat;
+use mips_simulator::{Processor, StepOutcome};
 use mips_types::module::R2KModule;
 use std::error::Error;
 use std::fs;
-use std::io::Cursor;
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 
 #[macro_use]
 extern crate log;
 
+/// The exit code used when the program is stopped by an execution error
+const EXECUTION_ERROR_EXIT_CODE: i32 = 255;
+/// The exit code used when the program is stopped by --max-instructions or
+/// --timeout
+const LIMIT_REACHED_EXIT_CODE: i32 = 254;
+
 #[derive(StructOpt)]
 struct CliArgs {
     /// Enables jump/branch delay slots. RSIM code does not use this option.
     #[structopt(long)]
     enable_delay_slots: bool,
 
-    #[structopt(parse(from_os_str))]
-    file_path: PathBuf,
+    /// Enables load delay slots: the instruction after a load sees the
+    /// register's old value, like the R2000.
+    #[structopt(long)]
+    enable_load_delay_slots: bool,
+
+    /// Reports the instructions which read the register loaded by the
+    /// instruction before them (which only works without load delay slots)
+    /// when the program stops.
+    #[structopt(long)]
+    warn_load_delay_slots: bool,
+
+    /// Allows unaligned memory accesses instead of raising address errors.
+    #[structopt(long)]
+    allow_unaligned_access: bool,
+
+    /// Maps the SPIM/MARS console device at 0xffff0000 for memory-mapped I/O.
+    #[structopt(long)]
+    mapped_io: bool,
+
+    /// Allows reading, writing, and executing any address instead of faulting
+    /// on accesses outside of the program's segments (ex. writing to .text).
+    #[structopt(long)]
+    disable_memory_protection: bool,
+
+    /// The maximum size of the heap (grown by sbrk) in bytes. By default, the
+    /// heap can grow until it reaches the stack.
+    #[structopt(long)]
+    heap_limit: Option<u32>,
+
+    /// The directory which the program can access with the file syscalls.
+    /// Without it, the program can not open files.
+    #[structopt(long, parse(from_os_str))]
+    file_sandbox: Option<PathBuf>,
+
+    /// Seeds the random number generators, so the program is deterministic.
+    #[structopt(long)]
+    random_seed: Option<u64>,
+
+    /// Stops the program after this many instructions (ex. to catch infinite
+    /// loops). The exit code is 254 if the limit is reached.
+    #[structopt(long)]
+    max_instructions: Option<u64>,
+
+    /// Stops the program after this many seconds. The exit code is 254 if the
+    /// timeout is reached.
+    #[structopt(long, parse(try_from_str = parse_seconds))]
+    timeout: Option<Duration>,
+
+    /// A kernel module (assembled from .ktext/.kdata) whose exception handler
+    /// will handle exceptions raised by the program.
+    #[structopt(long, parse(from_os_str))]
+    kernel: Option<PathBuf>,
+
+    /// An environment variable to pass to the program, either NAME=VALUE or
+    /// NAME to copy it from the current environment. Can be used multiple
+    /// times.
+    #[structopt(long = "env", number_of_values = 1)]
+    environment: Vec<String>,
+
+    /// Saves the processor's state to this file when the program stops (ex.
+    /// after reaching --max-instructions), so it can be resumed later.
+    #[structopt(long, parse(from_os_str))]
+    save_snapshot: Option<PathBuf>,
+
+    /// Resumes the program saved in this snapshot instead of loading a module.
+    /// The options are restored from the snapshot, except for the limits, the
+    /// models, and the file sandbox.
+    #[structopt(long, parse(from_os_str))]
+    load_snapshot: Option<PathBuf>,
+
+    /// Prints execution statistics (instruction counts by class, mnemonic,
+    /// and function, branches, and memory traffic) when the program stops.
+    #[structopt(long)]
+    stats: bool,
+
+    /// The format of the statistics: text or json.
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    stats_format: String,
+
+    /// Writes the statistics to this file instead of stderr.
+    #[structopt(long, parse(from_os_str))]
+    stats_file: Option<PathBuf>,
+
+    /// Writes a record of each executed instruction (its address, word,
+    /// disassembly, symbol, and the registers and memory it wrote) to this
+    /// file. Traces can be compared with mips-trace-diff.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// The format of the trace: text (tab-separated) or json (JSON lines).
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    trace_format: TraceFormat,
+
+    /// Counts the cycles the program would take in a five-stage pipeline
+    /// (IF, ID, EX, MEM, WB), and prints the CPI and stalls when it stops.
+    #[structopt(long)]
+    pipeline: bool,
+
+    /// The forwarding paths in the pipeline: full, ex-mem, mem-wb, or none.
+    #[structopt(long, default_value = "full", possible_values = &["full", "ex-mem", "mem-wb", "none"])]
+    forwarding: String,
+
+    /// The pipeline stage where branches are resolved: id, ex, or mem.
+    #[structopt(long, default_value = "id")]
+    branch_stage: BranchStage,
+
+    /// Writes a diagram of the instructions in each pipeline stage, one line
+    /// per cycle, to this file.
+    #[structopt(long, parse(from_os_str))]
+    pipeline_diagram: Option<PathBuf>,
+
+    /// Simulates an instruction cache with the size, block size, and
+    /// associativity (ex. 4096:16:2), and prints its hits and misses when the
+    /// program stops.
+    #[structopt(long)]
+    icache: Option<CacheConfig>,
+
+    /// Simulates a data cache with the size, block size, and associativity
+    /// (ex. 4096:16:2), and prints its hits and misses when the program stops.
+    #[structopt(long)]
+    dcache: Option<CacheConfig>,
+
+    /// The cache replacement policy: lru, fifo, or random. The random policy
+    /// is seeded with --random-seed (or 0).
+    #[structopt(long, default_value = "lru", possible_values = &["lru", "fifo", "random"])]
+    cache_replacement: String,
+
+    /// The data cache's write policy: write-back or write-through.
+    #[structopt(long, default_value = "write-back", possible_values = &["write-back", "write-through"])]
+    cache_write_policy: String,
+
+    /// Stores which miss in the data cache are only written to memory,
+    /// instead of bringing the block into the cache.
+    #[structopt(long)]
+    cache_no_write_allocate: bool,
+
+    /// Runs branch predictors alongside the program, and prints how many of
+    /// the conditional branches (and jumps, with a BTB) each predicted
+    /// correctly when it stops.
+    #[structopt(long)]
+    branch_prediction: bool,
+
+    /// The branch predictors to run, separated by commas: not-taken,
+    /// backward-taken, 1-bit, and 2-bit. All of them are run by default.
+    #[structopt(long, require_delimiter = true)]
+    branch_predictors: Vec<Predictor>,
+
+    /// The number of entries in the 1-bit and 2-bit predictors' tables.
+    #[structopt(long, default_value = "256")]
+    predictor_table_size: u32,
+
+    /// The number of entries in the branch target buffer, which predicts the
+    /// targets of jumps.
+    #[structopt(long, default_value = "64")]
+    btb_size: u32,
+
+    #[structopt(parse(from_os_str), required_unless = "load-snapshot")]
+    file_path: Option<PathBuf>,
+
+    /// The arguments to pass to the program (after "--")
+    #[structopt(last = true)]
+    program_args: Vec<String>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Setup logging and parse CLI args
-    env_logger::init();
-    let args = CliArgs::from_args();
+/// Parse a (possibly fractional) number of seconds
+fn parse_seconds(seconds: &str) -> Result<Duration, Box<dyn Error>> {
+    Ok(Duration::try_from_secs_f64(seconds.parse()?)?)
+}
+
+/// Get the pipeline timing model's config, if it is enabled
+fn pipeline_config(args: &CliArgs) -> Option<PipelineConfig> {
+    if !args.pipeline {
+        return None;
+    }
+
+    Some(PipelineConfig {
+        forward_ex_mem: matches!(args.forwarding.as_str(), "full" | "ex-mem"),
+        forward_mem_wb: matches!(args.forwarding.as_str(), "full" | "mem-wb"),
+        branch_stage: args.branch_stage,
+    })
+}
+
+/// Apply the cache policy options to a cache's config
+fn cache_config(args: &CliArgs, cache: Option<CacheConfig>) -> Option<CacheConfig> {
+    let mut cache = cache?;
+    cache.replacement = match args.cache_replacement.as_str() {
+        "fifo" => ReplacementPolicy::Fifo,
+        "random" => ReplacementPolicy::Random {
+            seed: args.random_seed.unwrap_or(0),
+        },
+        _ => ReplacementPolicy::Lru,
+    };
+    cache.write_policy = match args.cache_write_policy.as_str() {
+        "write-through" => WritePolicy::WriteThrough,
+        _ => WritePolicy::WriteBack,
+    };
+    cache.write_allocate = !args.cache_no_write_allocate;
+
+    Some(cache)
+}
+
+/// Get the branch predictors' config, if they are enabled
+fn branch_prediction_config(args: &CliArgs) -> Option<BranchPredictionConfig> {
+    if !args.branch_prediction {
+        return None;
+    }
+
+    Some(BranchPredictionConfig {
+        predictors: if args.branch_predictors.is_empty() {
+            Predictor::ALL.to_vec()
+        } else {
+            args.branch_predictors.clone()
+        },
+        table_size: args.predictor_table_size,
+        btb_size: args.btb_size,
+    })
+}
+
+/// Load the program's module (and kernel module) into a new processor
+fn load_program(args: &CliArgs) -> Result<Processor, Box<dyn Error>> {
+    let file_path = args
+        .file_path
+        .as_ref()
+        .ok_or("A module or snapshot must be given")?;
 
     // Load the executable module
-    let file_data = fs::read(&args.file_path)?;
+    let file_data = fs::read(file_path)?;
     let module = R2KModule::parse(&mut Cursor::new(file_data))?;
     info!("Loaded module with header: {:?}", module.header);
 
     // Setup the processor
     let mut processor = Processor::new(Config {
         enable_delay_slots: args.enable_delay_slots,
+        enable_load_delay_slots: args.enable_load_delay_slots,
+        warn_load_delay_slots: args.warn_load_delay_slots,
+        allow_unaligned_access: args.allow_unaligned_access,
+        enable_mapped_io: args.mapped_io,
+        disable_memory_protection: args.disable_memory_protection,
+        heap_limit: args.heap_limit,
+        file_sandbox: args.file_sandbox.clone(),
+        random_seed: args.random_seed,
+        max_instructions: args.max_instructions,
+        timeout: args.timeout,
+        pipeline: pipeline_config(args),
+        instruction_cache: cache_config(args, args.icache),
+        data_cache: cache_config(args, args.dcache),
+        branch_prediction: branch_prediction_config(args),
     });
-    processor.load_rsim_module(&module);
+    // The first argument is the program's path
+    let mut program_args = vec![file_path.display().to_string()];
+    program_args.extend(args.program_args.iter().cloned());
+    processor.load_program(
+        &module,
+        &program_args,
+        &args.environment,
+        args.kernel.as_deref(),
+    )?;
+
+    Ok(processor)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Setup logging and parse CLI args
+    env_logger::init();
+    let args = CliArgs::from_args();
+
+    let mut processor = match &args.load_snapshot {
+        Some(snapshot_path) => {
+            let file_data = fs::read(snapshot_path)?;
+            let mut snapshot = Snapshot::parse(&mut Cursor::new(file_data))?;
+            snapshot.config.max_instructions = args.max_instructions;
+            snapshot.config.timeout = args.timeout;
+            snapshot.config.pipeline = pipeline_config(&args);
+            snapshot.config.instruction_cache = cache_config(&args, args.icache);
+            snapshot.config.data_cache = cache_config(&args, args.dcache);
+            snapshot.config.branch_prediction = branch_prediction_config(&args);
+            snapshot.config.file_sandbox = args.file_sandbox.clone();
+
+            let mut processor = Processor::new(snapshot.config.clone());
+            processor.restore(&snapshot);
+            info!("Loaded snapshot from {}", snapshot_path.display());
+            processor
+        }
+        None => load_program(&args)?,
+    };
+
+    if args.stats {
+        processor.enable_statistics();
+    }
+
+    if let Some(trace_path) = &args.trace {
+        let output = BufWriter::new(File::create(trace_path)?);
+        processor.set_trace(Box::new(output), args.trace_format);
+    }
+
+    if let Some(diagram_path) = &args.pipeline_diagram {
+        let output = BufWriter::new(File::create(diagram_path)?);
+        processor.set_pipeline_diagram(Box::new(output))?;
+    }
 
     // Run the code
-    while processor.running {
-        processor.step();
+    let return_code = loop {
+        // A restored program might have already stopped
+        if !processor.running {
+            break processor.return_code;
+        }
+
+        match processor.step() {
+            Ok(StepOutcome::Running) => {}
+            Ok(StepOutcome::Exited(return_code)) => break return_code,
+            Ok(StepOutcome::Break) => break processor.return_code,
+            Ok(StepOutcome::LimitReached(limit)) => {
+                eprintln!(
+                    "{} after {} instructions",
+                    limit, processor.instruction_count
+                );
+                break LIMIT_REACHED_EXIT_CODE;
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                break EXECUTION_ERROR_EXIT_CODE;
+            }
+        }
+    };
+
+    info!("Executed {} instructions", processor.instruction_count);
+    processor.finish_trace()?;
+
+    if let Some(report) = processor.statistics_report() {
+        let report = match args.stats_format.as_str() {
+            "json" => format!("{}\n", report.to_json()),
+            _ => report.to_string(),
+        };
+
+        match &args.stats_file {
+            Some(stats_path) => fs::write(stats_path, report)?,
+            None => eprint!("{}", report),
+        }
+    }
+
+    let hazards = processor.load_delay_hazards();
+    if !hazards.is_empty() {
+        eprintln!("Load delay slot hazards:");
+        for (hazard, count) in hazards {
+            let symbol = processor
+                .symbols()
+                .describe(hazard.program_counter)
+                .map(|symbol| format!(" ({})", symbol))
+                .unwrap_or_default();
+            eprintln!(
+                "  0x{:08x}{} reads {} loaded at 0x{:08x} ({} time{})",
+                hazard.program_counter,
+                symbol,
+                hazard.register,
+                hazard.load_address,
+                count,
+                if count == 1 { "" } else { "s" }
+            );
+        }
+    }
+
+    if let Some(report) = processor.pipeline_report() {
+        processor.finish_pipeline_diagram()?;
+        eprint!("{}", report);
+    }
+
+    if let Some(report) = processor.instruction_cache_report() {
+        eprint!("Instruction cache: {}", report);
+    }
+
+    if let Some(report) = processor.data_cache_report() {
+        eprint!("Data cache: {}", report);
+    }
+
+    if let Some(report) = processor.branch_prediction_report() {
+        eprint!("{}", report);
+    }
+
+    if let Some(snapshot_path) = &args.save_snapshot {
+        let mut output = BufWriter::new(File::create(snapshot_path)?);
+        processor.snapshot().write(&mut output)?;
+        output.flush()?;
+        info!("Saved snapshot to {}", snapshot_path.display());
     }
 
-    std::process::exit(processor.return_code);
+    std::process::exit(return_code);
 }
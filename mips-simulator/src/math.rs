@@ -6,3 +6,16 @@ pub fn add_unsigned(a: u32, b: i32) -> u32 {
         a.wrapping_add(b as u32)
     }
 }
+
+/// Convert a floating point value to a word, rounding to the nearest integer
+/// (ties to even). Values which are out of range or NaN convert to the
+/// invalid operation result, `i32::MAX`.
+pub fn round_to_word(value: f64) -> u32 {
+    let rounded = value.round_ties_even();
+
+    if rounded >= i32::MIN as f64 && rounded <= i32::MAX as f64 {
+        rounded as i32 as u32
+    } else {
+        i32::MAX as u32
+    }
+}
@@ -1,17 +1,89 @@
-use std::collections::HashMap;
-use std::ffi::CString;
+use crate::decode::DecodedInstruction;
+use crate::devices::Device;
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 
-/// 1MB pages
-const PAGE_SIZE: usize = 1024 * 1024;
+/// 4KB pages
+const PAGE_BITS: u32 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// Each page table holds 1024 pages (4MB), and the directory holds 1024 page
+/// tables (4GB).
+const TABLE_BITS: u32 = 10;
+const TABLE_SIZE: usize = 1 << TABLE_BITS;
+/// A page table entry which does not have a page
+const NO_PAGE: u32 = u32::MAX;
+/// A page cache entry which does not match any page number
+const NO_CACHED_PAGE: (u32, u32) = (u32::MAX, NO_PAGE);
 
-/// An implementation of paged memory
-/// TODO: Check for unaligned memory access
-#[derive(Default)]
+type Page = [u8; PAGE_SIZE];
+/// The decoded instructions in a page, indexed by word
+type DecodedPage = [Option<DecodedInstruction>; PAGE_SIZE / 4];
+/// Indices into `Memory::pages`, indexed by the page number's lower bits
+type PageTable = [u32; TABLE_SIZE];
+
+/// An implementation of paged memory, using a two-level page table. Pages are
+/// only allocated when written to, and unallocated memory reads as zero.
+/// Unaligned accesses are allowed here, the processor is responsible for
+/// checking alignment.
 pub struct Memory {
-    /// Pages indexed by first address in the page
-    pages: HashMap<u32, [u8; PAGE_SIZE]>,
+    /// The first level of the page table, indexed by the upper bits of the
+    /// address
+    directory: Vec<Option<Box<PageTable>>>,
+    /// The allocated pages
+    pages: Vec<Box<Page>>,
+    /// The instructions decoded from each page, indexed like `pages`. These
+    /// are only allocated once an instruction in the page is executed, and a
+    /// word's instruction is removed when the word is written to.
+    decoded_pages: Vec<Option<Box<DecodedPage>>>,
+    /// The page number (address >> PAGE_BITS) and index of the last page used
+    /// by a load or store. Most accesses are to the same page as the last one,
+    /// which skips the page table lookup.
+    last_page: Cell<(u32, u32)>,
+    /// The last page used by an instruction fetch. This is separate from
+    /// `last_page` so fetches and data accesses don't evict each other.
+    last_fetch_page: Cell<(u32, u32)>,
+    /// Memory-mapped devices, which take the place of RAM in their ranges
+    devices: Vec<MappedDevice>,
+    /// The address and old value of each byte written to RAM, if writes are
+    /// being logged
+    write_log: Option<Vec<(u32, u8)>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            directory: (0..TABLE_SIZE).map(|_| None).collect(),
+            pages: Vec::new(),
+            decoded_pages: Vec::new(),
+            last_page: Cell::new(NO_CACHED_PAGE),
+            last_fetch_page: Cell::new(NO_CACHED_PAGE),
+            devices: Vec::new(),
+            write_log: None,
+        }
+    }
+}
+
+/// A device and the address range it is mapped to
+struct MappedDevice {
+    address: u32,
+    size: u32,
+    /// Devices can change state when read (ex. consuming input)
+    device: RefCell<Box<dyn Device>>,
+}
+
+impl MappedDevice {
+    fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.address) < self.size
+    }
+
+    /// Check if the device overlaps with the inclusive range
+    fn overlaps(&self, start: u32, end: u32) -> bool {
+        // Mapped devices never run past the end of memory
+        let mapped_end = self.address + (self.size - 1);
+        self.address <= end && start <= mapped_end
+    }
 }
 
 impl Memory {
@@ -19,94 +91,554 @@ impl Memory {
         Self::default()
     }
 
+    /// Map a device to the address range. Panics if the range is empty, runs
+    /// past the end of memory, or overlaps with another device.
+    pub fn map_device(&mut self, address: u32, size: u32, device: Box<dyn Device>) {
+        assert!(size > 0, "Device at 0x{:08x} has no size", address);
+        let end = address
+            .checked_add(size - 1)
+            .unwrap_or_else(|| panic!("Device at 0x{:08x} runs past the end of memory", address));
+        assert!(
+            self.devices
+                .iter()
+                .all(|mapped| !mapped.overlaps(address, end)),
+            "Device at 0x{:08x} overlaps with another device",
+            address
+        );
+
+        self.devices.push(MappedDevice {
+            address,
+            size,
+            device: RefCell::new(device),
+        });
+
+        // Instructions are fetched from the device now instead of memory
+        self.decoded_pages.iter_mut().for_each(|page| *page = None);
+    }
+
+    /// Get the device mapped at the address, if there is one
+    fn find_device(&self, address: u32) -> Option<&MappedDevice> {
+        self.devices.iter().find(|mapped| mapped.contains(address))
+    }
+
+    /// Load from a device, if the address is mapped to one
+    fn device_load(&self, address: u32, size: u32) -> Option<u32> {
+        let mapped = self.find_device(address)?;
+        let value = mapped
+            .device
+            .borrow_mut()
+            .load(address - mapped.address, size);
+
+        Some(value)
+    }
+
+    /// Store to a device, if the address is mapped to one. Returns true if
+    /// the value was stored.
+    fn device_store(&self, address: u32, size: u32, value: u32) -> bool {
+        match self.find_device(address) {
+            Some(mapped) => {
+                mapped
+                    .device
+                    .borrow_mut()
+                    .store(address - mapped.address, size, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the index of the page containing the address, if it has been
+    /// allocated. The cache is checked first, and updated on a miss.
+    #[inline(always)]
+    fn find_page(&self, address: u32, cache: &Cell<(u32, u32)>) -> Option<usize> {
+        let page_number = address >> PAGE_BITS;
+        let (cached_page_number, cached_index) = cache.get();
+
+        if cached_page_number == page_number {
+            return Some(cached_index as usize);
+        }
+
+        let table = self.directory[(page_number >> TABLE_BITS) as usize].as_ref()?;
+        let index = table[page_number as usize % TABLE_SIZE];
+
+        if index == NO_PAGE {
+            return None;
+        }
+
+        cache.set((page_number, index));
+        Some(index as usize)
+    }
+
+    /// Get the page containing the address to write `size` bytes to it,
+    /// allocating the page if needed. Any decoded instructions in those bytes
+    /// are removed, and the old bytes are logged if needed.
+    #[inline(always)]
+    fn page_mut(&mut self, address: u32, size: usize) -> &mut Page {
+        let index = match self.find_page(address, &self.last_page) {
+            Some(index) => index,
+            None => self.allocate_page(address),
+        };
+        let offset = address as usize % PAGE_SIZE;
+
+        if let Some(decoded_page) = &mut self.decoded_pages[index] {
+            decoded_page[offset / 4..=(offset + size - 1) / 4].fill(None);
+        }
+
+        if let Some(write_log) = &mut self.write_log {
+            let page = &self.pages[index];
+            write_log.extend((0..size).map(|i| (address.wrapping_add(i as u32), page[offset + i])));
+        }
+
+        &mut self.pages[index]
+    }
+
+    /// Allocate the page containing the address, and get its index
+    #[cold]
+    fn allocate_page(&mut self, address: u32) -> usize {
+        let page_number = address >> PAGE_BITS;
+        let index = self.pages.len() as u32;
+        self.pages.push(Box::new([0; PAGE_SIZE]));
+        self.decoded_pages.push(None);
+
+        let table = self.directory[(page_number >> TABLE_BITS) as usize]
+            .get_or_insert_with(|| Box::new([NO_PAGE; TABLE_SIZE]));
+        table[page_number as usize % TABLE_SIZE] = index;
+        self.last_page.set((page_number, index));
+
+        index as usize
+    }
+
+    /// Get the `N` bytes at the address, if they are all in one page. Bytes
+    /// in an unallocated page are zero.
+    #[inline(always)]
+    fn get_in_page<const N: usize>(
+        &self,
+        address: u32,
+        cache: &Cell<(u32, u32)>,
+    ) -> Option<[u8; N]> {
+        let offset = address as usize % PAGE_SIZE;
+
+        if offset + N > PAGE_SIZE {
+            return None;
+        }
+
+        Some(match self.find_page(address, cache) {
+            Some(index) => self.pages[index][offset..offset + N].try_into().unwrap(),
+            None => [0; N],
+        })
+    }
+
+    /// Set the `N` bytes at the address. Returns false if they are not all in
+    /// one page.
+    #[inline(always)]
+    fn set_in_page<const N: usize>(&mut self, address: u32, bytes: [u8; N]) -> bool {
+        let offset = address as usize % PAGE_SIZE;
+
+        if offset + N > PAGE_SIZE {
+            return false;
+        }
+
+        self.page_mut(address, N)[offset..offset + N].copy_from_slice(&bytes);
+        true
+    }
+
     /// Get the byte at the address
     #[inline]
     pub fn get(&self, address: u32) -> u8 {
-        let page_index = self.page_index(address);
-        let address_offset = self.address_offset(address);
+        if let Some(value) = self.device_load(address, 1) {
+            return value as u8;
+        }
 
-        if let Some(page) = self.pages.get(&page_index) {
-            page[address_offset]
-        } else {
-            0
+        match self.find_page(address, &self.last_page) {
+            Some(index) => self.pages[index][address as usize % PAGE_SIZE],
+            None => 0,
+        }
+    }
+
+    /// Get the byte at the address without accessing any device mapped to it
+    /// (device registers read as 0)
+    pub fn peek(&self, address: u32) -> u8 {
+        if self.find_device(address).is_some() {
+            return 0;
+        }
+
+        match self.find_page(address, &self.last_page) {
+            Some(index) => self.pages[index][address as usize % PAGE_SIZE],
+            None => 0,
         }
     }
 
     /// Get the bytes in the address range
     pub fn get_range<const N: usize>(&self, address: u32) -> [u8; N] {
+        if let Some(bytes) = self.get_in_page(address, &self.last_page) {
+            return bytes;
+        }
+
+        // The range crosses a page boundary
         let mut bytes = [0; N];
 
         for (i, byte) in bytes.iter_mut().enumerate() {
-            *byte = self.get(address + i as u32);
+            *byte = self.get(address.wrapping_add(i as u32));
         }
 
         bytes
     }
 
+    /// Get the half word (2 bytes) at the address
+    #[inline]
+    pub fn get_half(&self, address: u32) -> u16 {
+        if let Some(value) = self.device_load(address, 2) {
+            return value as u16;
+        }
+
+        u16::from_be_bytes(self.get_range(address))
+    }
+
     /// Get the word (4 bytes) at the address
+    #[inline]
     pub fn get_word(&self, address: u32) -> u32 {
-        let bytes = self.get_range(address);
-        u32::from_be_bytes(bytes)
+        if let Some(value) = self.device_load(address, 4) {
+            return value;
+        }
+
+        u32::from_be_bytes(self.get_range(address))
     }
 
-    /// Get a null-terminated string starting from the address
-    pub fn get_str(&self, address: u32) -> CString {
-        let mut bytes = Vec::new();
+    /// Get the instruction word at the address. This is the same as
+    /// `get_word`, but it uses its own page cache.
+    #[inline(always)]
+    pub fn fetch_word(&self, address: u32) -> u32 {
+        if self.devices.is_empty() {
+            if let Some(bytes) = self.get_in_page(address, &self.last_fetch_page) {
+                return u32::from_be_bytes(bytes);
+            }
+        }
 
-        for i in address.. {
-            let byte = self.get(i);
+        self.get_word(address)
+    }
 
-            if byte == 0 {
-                break;
-            }
+    /// Get the decoded instruction at the address, if it has been cached
+    #[inline(always)]
+    pub fn get_decoded(&self, address: u32) -> Option<DecodedInstruction> {
+        let index = self.find_page(address, &self.last_fetch_page)?;
+        let decoded_page = self.decoded_pages[index].as_ref()?;
+
+        if !address.is_multiple_of(4) {
+            return None;
+        }
+
+        decoded_page[address as usize % PAGE_SIZE / 4]
+    }
 
-            bytes.push(byte);
+    /// Cache the decoded instruction at the address. Instructions aren't
+    /// cached if they are unaligned, in an unallocated page, or fetched from a
+    /// device.
+    pub fn set_decoded(&mut self, address: u32, decoded: DecodedInstruction) {
+        if !address.is_multiple_of(4) || self.find_device(address).is_some() {
+            return;
         }
 
-        CString::new(bytes).unwrap()
+        if let Some(index) = self.find_page(address, &self.last_fetch_page) {
+            self.decoded_pages[index].get_or_insert_with(|| Box::new([None; PAGE_SIZE / 4]))
+                [address as usize % PAGE_SIZE / 4] = Some(decoded);
+        }
     }
 
     /// Set a byte at the address
     #[inline(always)]
     pub fn set(&mut self, address: u32, value: u8) {
-        let page_index = self.page_index(address);
-        let address_offset = self.address_offset(address);
-        let page = self
-            .pages
-            .entry(page_index)
-            .or_insert_with(|| [0; PAGE_SIZE]);
+        if self.device_store(address, 1, value as u32) {
+            return;
+        }
+
+        self.page_mut(address, 1)[address as usize % PAGE_SIZE] = value;
+    }
+
+    /// Set the bytes in the address range
+    pub fn set_range<const N: usize>(&mut self, address: u32, bytes: [u8; N]) {
+        if self.set_in_page(address, bytes) {
+            return;
+        }
+
+        // The range crosses a page boundary
+        for (i, byte) in bytes.iter().enumerate() {
+            self.set(address.wrapping_add(i as u32), *byte);
+        }
+    }
+
+    /// Set a half word (2 bytes) at the address
+    #[inline]
+    pub fn set_half(&mut self, address: u32, value: u16) {
+        if self.device_store(address, 2, value as u32) {
+            return;
+        }
 
-        page[address_offset] = value;
+        self.set_range(address, value.to_be_bytes());
     }
 
     /// Set a word (4 bytes) at the address
     #[inline(always)]
     pub fn set_word(&mut self, address: u32, value: u32) {
-        for (i, byte) in value.to_be_bytes().iter().enumerate() {
-            self.set(address + i as u32, *byte);
+        if self.device_store(address, 4, value) {
+            return;
         }
+
+        self.set_range(address, value.to_be_bytes());
+    }
+
+    /// Start logging the old value of each byte written to RAM (writes to
+    /// devices aren't logged)
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop logging writes, and get the address and old value of each byte
+    /// written since the log was started
+    pub fn take_write_log(&mut self) -> Vec<(u32, u8)> {
+        self.write_log.take().unwrap_or_default()
+    }
+
+    /// Get the start address and data of each allocated page
+    pub fn pages(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        let pages = &self.pages;
+
+        self.directory
+            .iter()
+            .enumerate()
+            .filter_map(|(directory_index, table)| Some((directory_index, table.as_ref()?)))
+            .flat_map(move |(directory_index, table)| {
+                table
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, index)| **index != NO_PAGE)
+                    .map(move |(table_index, index)| {
+                        let page_number = ((directory_index << TABLE_BITS) | table_index) as u32;
+                        (page_number << PAGE_BITS, &pages[*index as usize][..])
+                    })
+            })
+    }
+
+    /// Free all of the pages, so memory reads as zero again. Mapped devices are
+    /// kept.
+    pub fn clear(&mut self) {
+        let devices = std::mem::take(&mut self.devices);
+        *self = Self {
+            devices,
+            ..Self::default()
+        };
     }
 
     /// Load some data into memory
     pub fn load_into_memory(&mut self, data: &[u8], offset: u32) {
-        for (i, byte) in data.iter().enumerate() {
-            self.set(offset + i as u32, *byte);
+        if !self.devices.is_empty() {
+            // The data might overlap a device
+            for (i, byte) in data.iter().enumerate() {
+                self.set(offset.wrapping_add(i as u32), *byte);
+            }
+            return;
         }
-    }
 
-    /// Get the index of the page the address is in
-    fn page_index(&self, address: u32) -> u32 {
-        address - self.address_offset(address) as u32
-    }
+        // Copy the data one page at a time
+        let mut address = offset;
+        let mut data = data;
+        while !data.is_empty() {
+            let page_offset = address as usize % PAGE_SIZE;
+            let (part, rest) = data.split_at((PAGE_SIZE - page_offset).min(data.len()));
 
-    /// Get the address's offset in its page
-    fn address_offset(&self, address: u32) -> usize {
-        address as usize % PAGE_SIZE
+            self.page_mut(address, part.len())[page_offset..page_offset + part.len()]
+                .copy_from_slice(part);
+            address = address.wrapping_add(part.len() as u32);
+            data = rest;
+        }
     }
 }
 
 impl Debug for Memory {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "Memory({} pages)", self.pages.len())
+        write!(
+            f,
+            "Memory({} pages, {} devices)",
+            self.pages.len(),
+            self.devices.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+
+    /// A device which remembers the last value stored to it
+    struct Latch(u32);
+
+    impl Device for Latch {
+        fn load(&mut self, _offset: u32, _size: u32) -> u32 {
+            self.0
+        }
+
+        fn store(&mut self, _offset: u32, _size: u32, value: u32) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn big_endian_accesses() {
+        let mut memory = Memory::new();
+        memory.set_word(0x1000, 0x0102_0304);
+        assert_eq!(memory.get(0x1000), 0x01);
+        assert_eq!(memory.get(0x1003), 0x04);
+        assert_eq!(memory.get_half(0x1002), 0x0304);
+
+        memory.set_half(0x1001, 0xaabb);
+        assert_eq!(memory.get_word(0x1000), 0x01aa_bb04);
+        memory.set(0x1003, 0xcc);
+        assert_eq!(memory.get_word(0x1000), 0x01aa_bbcc);
+
+        // Unaligned accesses are allowed
+        assert_eq!(memory.get_word(0x1001), 0xaabb_cc00);
+    }
+
+    #[test]
+    fn unwritten_memory_reads_as_zero() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.get_word(0x1000_0000), 0);
+        assert_eq!(memory.fetch_word(0x40_0000), 0);
+        assert_eq!(memory.pages().count(), 0);
+
+        memory.set(0x1000_0000, 1);
+        assert_eq!(memory.get_word(0x1000_0ffc), 0);
+        assert_eq!(memory.get_word(0x1000_1000), 0);
+        assert_eq!(memory.pages().count(), 1);
+    }
+
+    #[test]
+    fn accesses_across_pages() {
+        let mut memory = Memory::new();
+
+        // Across a page, across a page table, and wrapping around memory
+        for address in [0x1000_0ffe, 0x103f_fffd, 0xffff_ffff] {
+            memory.set_word(address, 0x1122_3344);
+            assert_eq!(memory.get_word(address), 0x1122_3344, "{:08x}", address);
+            assert_eq!(memory.get_half(address.wrapping_add(1)), 0x2233);
+            assert_eq!(memory.get(address.wrapping_add(3)), 0x44);
+        }
+
+        let pages: Vec<u32> = memory.pages().map(|(address, _)| address).collect();
+        assert_eq!(
+            pages,
+            [
+                0,
+                0x1000_0000,
+                0x1000_1000,
+                0x103f_f000,
+                0x1040_0000,
+                0xffff_f000
+            ]
+        );
+    }
+
+    #[test]
+    fn load_and_clear() {
+        let mut memory = Memory::new();
+        let data: Vec<u8> = (0..0x2000).map(|i| i as u8).collect();
+        memory.load_into_memory(&data, 0x1000_0800);
+        assert_eq!(memory.pages().count(), 3);
+        assert_eq!(memory.get(0x1000_0800), 0);
+        assert_eq!(memory.get_word(0x1000_0ffe), 0xfeff_0001);
+        assert_eq!(memory.get(0x1000_27ff), 0xff);
+        assert_eq!(memory.get(0x1000_2800), 0);
+
+        memory.clear();
+        assert_eq!(memory.pages().count(), 0);
+        assert_eq!(memory.get_word(0x1000_0ffe), 0);
+    }
+
+    #[test]
+    fn writes_remove_decoded_instructions() {
+        let mut memory = Memory::new();
+        let decoded = DecodedInstruction::new(Instruction(0));
+        memory.set_word(0x40_0000, 0);
+        for address in [0x40_0000, 0x40_0004] {
+            memory.set_decoded(address, decoded);
+            assert!(memory.get_decoded(address).is_some());
+        }
+
+        // Only the words which are written to are removed
+        memory.set(0x40_0003, 1);
+        assert!(memory.get_decoded(0x40_0000).is_none());
+        assert!(memory.get_decoded(0x40_0004).is_some());
+        memory.set_word(0x40_0002, 1);
+        assert!(memory.get_decoded(0x40_0004).is_none());
+    }
+
+    #[test]
+    fn decoded_instructions_which_are_not_cached() {
+        let mut memory = Memory::new();
+        let decoded = DecodedInstruction::new(Instruction(0));
+        memory.set_word(0x40_0000, 0);
+        memory.map_device(0x40_0008, 4, Box::new(Latch(0)));
+        for address in [0x40_0002, 0x40_0008, 0x50_0000] {
+            memory.set_decoded(address, decoded);
+            assert!(memory.get_decoded(address).is_none(), "{:08x}", address);
+        }
+    }
+
+    #[test]
+    fn devices_handle_their_range() {
+        let mut memory = Memory::new();
+        memory.map_device(0x1000, 8, Box::new(Latch(0)));
+
+        memory.set_word(0x1004, 42);
+        assert_eq!(memory.get_word(0x1004), 42);
+        assert_eq!(memory.peek(0x1004), 0);
+
+        memory.set_word(0x1008, 7);
+        assert_eq!(memory.get_word(0x1008), 7);
+        assert_eq!(memory.peek(0x100b), 7);
+    }
+
+    #[test]
+    fn device_at_the_end_of_memory() {
+        let mut memory = Memory::new();
+        memory.map_device(0xffff_fff0, 0x10, Box::new(Latch(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "no size")]
+    fn empty_device() {
+        Memory::new().map_device(0x1000, 0, Box::new(Latch(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end of memory")]
+    fn device_past_the_end_of_memory() {
+        Memory::new().map_device(0xffff_fff0, 0x20, Box::new(Latch(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps")]
+    fn device_enclosing_another_device() {
+        let mut memory = Memory::new();
+        memory.map_device(0x1004, 4, Box::new(Latch(0)));
+        memory.map_device(0x1000, 0x10, Box::new(Latch(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps")]
+    fn device_inside_another_device() {
+        let mut memory = Memory::new();
+        memory.map_device(0x1000, 0x10, Box::new(Latch(0)));
+        memory.map_device(0x1004, 4, Box::new(Latch(0)));
+    }
+
+    #[test]
+    fn adjacent_devices() {
+        let mut memory = Memory::new();
+        memory.map_device(0x1000, 4, Box::new(Latch(1)));
+        memory.map_device(0x1004, 4, Box::new(Latch(2)));
+
+        assert_eq!(memory.get_word(0x1000), 1);
+        assert_eq!(memory.get_word(0x1004), 2);
     }
 }
@@ -0,0 +1,275 @@
+//! The memory map, which describes the valid address ranges (segments) and
+//! what they can be used for.
+
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A kind of memory access
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Execute => "execute",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// A load or store made by an instruction (or syscall)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryAccess {
+    /// The first address accessed
+    pub address: u32,
+    /// The number of bytes accessed
+    pub size: u32,
+    /// Either `Access::Read` or `Access::Write`
+    pub access: Access,
+}
+
+impl MemoryAccess {
+    /// Check if the access touches any of the `size` bytes at the address
+    pub fn overlaps(&self, address: u32, size: u32) -> bool {
+        let start = self.address as u64;
+        let end = start + self.size as u64;
+        let other_start = address as u64;
+        let other_end = other_start + size as u64;
+
+        start < other_end && other_start < end
+    }
+}
+
+/// The accesses allowed in a segment
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const READ_ONLY: Self = Self {
+        read: true,
+        write: false,
+        execute: false,
+    };
+    pub const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    pub const READ_EXECUTE: Self = Self {
+        read: true,
+        write: false,
+        execute: true,
+    };
+
+    /// Check if the access is allowed
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+/// A range of addresses with the same permissions
+#[derive(Clone, Debug)]
+pub struct Segment {
+    /// The name of the segment (ex. ".text" or "stack")
+    pub name: &'static str,
+    /// The first address in the segment
+    pub start: u32,
+    /// The size of the segment in bytes
+    pub size: u32,
+    pub permissions: Permissions,
+}
+
+impl Segment {
+    /// Check if the address is in the segment
+    pub fn contains(&self, address: u32) -> bool {
+        address.wrapping_sub(self.start) < self.size
+    }
+
+    /// Check if the whole address range is in the segment
+    pub fn contains_range(&self, address: u32, size: u32) -> bool {
+        let offset = address.wrapping_sub(self.start);
+        offset < self.size && size <= self.size - offset
+    }
+}
+
+/// The reason a memory access was not allowed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AccessViolation {
+    /// The address range is not (completely) inside of a segment
+    Unmapped,
+    /// The segment does not allow the access
+    NotPermitted(&'static str),
+}
+
+/// The segments of memory which can be accessed
+#[derive(Debug, Default)]
+pub struct MemoryMap {
+    segments: Vec<Segment>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a segment to the map
+    pub fn add_segment(
+        &mut self,
+        name: &'static str,
+        start: u32,
+        size: u32,
+        permissions: Permissions,
+    ) {
+        trace!(
+            "Mapping {} at 0x{:08x} (0x{:x} bytes, {:?})",
+            name,
+            start,
+            size,
+            permissions
+        );
+        self.segments.push(Segment {
+            name,
+            start,
+            size,
+            permissions,
+        });
+    }
+
+    /// Resize the segment with the name. Panics if there is no such segment.
+    pub fn set_segment_size(&mut self, name: &str, size: u32) {
+        let segment = self
+            .segments
+            .iter_mut()
+            .find(|segment| segment.name == name)
+            .unwrap_or_else(|| panic!("Unknown segment {}", name));
+
+        trace!("Resizing {} to 0x{:x} bytes", name, size);
+        segment.size = size;
+    }
+
+    /// Get the segments in the map
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Get the segment which contains the address, if there is one
+    pub fn find_segment(&self, address: u32) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|segment| segment.contains(address))
+    }
+
+    /// Check if an access of `size` bytes at the address is allowed
+    pub fn check(&self, address: u32, size: u32, access: Access) -> Result<(), AccessViolation> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.contains_range(address, size))
+            .ok_or(AccessViolation::Unmapped)?;
+
+        if segment.permissions.allows(access) {
+            Ok(())
+        } else {
+            Err(AccessViolation::NotPermitted(segment.name))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, AccessViolation, MemoryMap, Permissions};
+
+    fn memory_map() -> MemoryMap {
+        let mut memory_map = MemoryMap::new();
+        memory_map.add_segment(".text", 0x1000, 0x100, Permissions::READ_EXECUTE);
+        memory_map.add_segment(".data", 0x1100, 0x100, Permissions::READ_WRITE);
+        memory_map.add_segment("top", 0xffff_fff0, 0x10, Permissions::READ_ONLY);
+        memory_map
+    }
+
+    #[test]
+    fn check_permissions() {
+        let memory_map = memory_map();
+
+        assert_eq!(memory_map.check(0x1000, 4, Access::Execute), Ok(()));
+        assert_eq!(memory_map.check(0x10fc, 4, Access::Read), Ok(()));
+        assert_eq!(
+            memory_map.check(0x1000, 4, Access::Write),
+            Err(AccessViolation::NotPermitted(".text"))
+        );
+        assert_eq!(memory_map.check(0x1100, 4, Access::Write), Ok(()));
+        assert_eq!(
+            memory_map.check(0x1100, 4, Access::Execute),
+            Err(AccessViolation::NotPermitted(".data"))
+        );
+        assert_eq!(
+            memory_map.check(0xffff_fffc, 4, Access::Write),
+            Err(AccessViolation::NotPermitted("top"))
+        );
+    }
+
+    #[test]
+    fn unmapped_addresses() {
+        let memory_map = memory_map();
+
+        assert_eq!(
+            memory_map.check(0, 1, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+        assert_eq!(
+            memory_map.check(0x1200, 1, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+        // Accesses can't straddle segments, even adjacent ones
+        assert_eq!(
+            memory_map.check(0x10fe, 4, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+        assert_eq!(
+            memory_map.check(0x11fe, 4, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+        // Or wrap around the end of memory
+        assert_eq!(
+            memory_map.check(0xffff_fffe, 4, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+    }
+
+    #[test]
+    fn resize_a_segment() {
+        let mut memory_map = MemoryMap::new();
+        memory_map.add_segment("heap", 0x2000, 0, Permissions::READ_WRITE);
+        assert_eq!(memory_map.find_segment(0x2000).map(|s| s.name), None);
+        assert_eq!(
+            memory_map.check(0x2000, 1, Access::Read),
+            Err(AccessViolation::Unmapped)
+        );
+
+        memory_map.set_segment_size("heap", 0x10);
+        assert_eq!(
+            memory_map.find_segment(0x200f).map(|s| s.name),
+            Some("heap")
+        );
+        assert_eq!(memory_map.check(0x200c, 4, Access::Write), Ok(()));
+        assert_eq!(
+            memory_map.check(0x2010, 1, Access::Write),
+            Err(AccessViolation::Unmapped)
+        );
+    }
+}
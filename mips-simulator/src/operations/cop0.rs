@@ -0,0 +1,38 @@
+use crate::error::ExecutionErrorCause;
+use crate::instruction::Instruction;
+use crate::Processor;
+use mips_types::constants::*;
+
+impl Processor {
+    /// Coprocessor 0 operations (mfc0, mtc0, rfe)
+    pub(crate) fn op_cop0(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        match instruction.s_register() {
+            COP0_RS_MF => self.op_mfc0(instruction),
+            COP0_RS_MT => self.op_mtc0(instruction),
+            COP0_RS_CO if instruction.function() == COP0_FUNCTION_RFE => self.op_rfe(),
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        }
+
+        Ok(())
+    }
+
+    /// Move from coprocessor 0
+    fn op_mfc0(&mut self, instruction: Instruction) {
+        let value = self.cp0.get(instruction.d_register());
+        self.registers.set(instruction.t_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Move to coprocessor 0
+    fn op_mtc0(&mut self, instruction: Instruction) {
+        let value = self.registers.get(instruction.t_register());
+        self.cp0.set(instruction.d_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Restore from exception
+    fn op_rfe(&mut self) {
+        self.cp0.return_from_exception();
+        self.advance_program_counter();
+    }
+}
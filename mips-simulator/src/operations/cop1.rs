@@ -0,0 +1,371 @@
+use crate::error::ExecutionErrorCause;
+use crate::instruction::Instruction;
+use crate::math::round_to_word;
+use crate::memory_map::Access;
+use crate::Processor;
+use mips_types::constants::*;
+
+impl Processor {
+    /// Coprocessor 1 operations (floating point arithmetic, conversions,
+    /// compares, moves, and branches)
+    pub(crate) fn op_cop1(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        match instruction.s_register() {
+            COP1_RS_MF => self.op_mfc1(instruction),
+            COP1_RS_MT => self.op_mtc1(instruction),
+            COP1_RS_BC => self.op_bc1(instruction)?,
+            COP1_FMT_S => self.op_cop1_single(instruction)?,
+            COP1_FMT_D => self.op_cop1_double(instruction)?,
+            COP1_FMT_W => self.op_cop1_word(instruction)?,
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        }
+
+        Ok(())
+    }
+
+    /// Move from coprocessor 1
+    fn op_mfc1(&mut self, instruction: Instruction) {
+        let value = self.cp1.get(instruction.fs_register());
+        self.registers.set(instruction.t_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Move to coprocessor 1
+    fn op_mtc1(&mut self, instruction: Instruction) {
+        let value = self.registers.get(instruction.t_register());
+        self.cp1.set(instruction.fs_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Branch on the floating point condition flag (bc1f, bc1t)
+    fn op_bc1(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let condition = match instruction.t_register() {
+            COP1_BC_RT_BC1F => !self.cp1.condition,
+            COP1_BC_RT_BC1T => self.cp1.condition,
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        };
+
+        self.branch_if(instruction, condition);
+        Ok(())
+    }
+
+    /// Single precision operations
+    fn op_cop1_single(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let fd = instruction.fd_register();
+        let fs = self.cp1.get_single(instruction.fs_register());
+        let ft = self.cp1.get_single(instruction.ft_register());
+
+        match instruction.function() {
+            COP1_FUNCTION_ADD => self.cp1.set_single(fd, fs + ft),
+            COP1_FUNCTION_SUB => self.cp1.set_single(fd, fs - ft),
+            COP1_FUNCTION_MUL => self.cp1.set_single(fd, fs * ft),
+            COP1_FUNCTION_DIV => self.cp1.set_single(fd, fs / ft),
+            COP1_FUNCTION_ABS => self.cp1.set_single(fd, fs.abs()),
+            COP1_FUNCTION_MOV => self.cp1.set_single(fd, fs),
+            COP1_FUNCTION_NEG => self.cp1.set_single(fd, -fs),
+            COP1_FUNCTION_CVT_D => self.cp1.set_double(fd, fs as f64),
+            COP1_FUNCTION_CVT_W => self.cp1.set(fd, round_to_word(fs as f64)),
+            COP1_FUNCTION_C_EQ => self.cp1.condition = fs == ft,
+            COP1_FUNCTION_C_LT => self.cp1.condition = fs < ft,
+            COP1_FUNCTION_C_LE => self.cp1.condition = fs <= ft,
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        }
+
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Double precision operations
+    fn op_cop1_double(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let fd = instruction.fd_register();
+        let fs = self.cp1.get_double(instruction.fs_register());
+        let ft = self.cp1.get_double(instruction.ft_register());
+
+        match instruction.function() {
+            COP1_FUNCTION_ADD => self.cp1.set_double(fd, fs + ft),
+            COP1_FUNCTION_SUB => self.cp1.set_double(fd, fs - ft),
+            COP1_FUNCTION_MUL => self.cp1.set_double(fd, fs * ft),
+            COP1_FUNCTION_DIV => self.cp1.set_double(fd, fs / ft),
+            COP1_FUNCTION_ABS => self.cp1.set_double(fd, fs.abs()),
+            COP1_FUNCTION_MOV => self.cp1.set_double(fd, fs),
+            COP1_FUNCTION_NEG => self.cp1.set_double(fd, -fs),
+            COP1_FUNCTION_CVT_S => self.cp1.set_single(fd, fs as f32),
+            COP1_FUNCTION_CVT_W => self.cp1.set(fd, round_to_word(fs)),
+            COP1_FUNCTION_C_EQ => self.cp1.condition = fs == ft,
+            COP1_FUNCTION_C_LT => self.cp1.condition = fs < ft,
+            COP1_FUNCTION_C_LE => self.cp1.condition = fs <= ft,
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        }
+
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Conversions from words (fixed point values)
+    fn op_cop1_word(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let fd = instruction.fd_register();
+        let fs = self.cp1.get(instruction.fs_register()) as i32;
+
+        match instruction.function() {
+            COP1_FUNCTION_CVT_S => self.cp1.set_single(fd, fs as f32),
+            COP1_FUNCTION_CVT_D => self.cp1.set_double(fd, fs as f64),
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        }
+
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Load word to coprocessor 1
+    pub(crate) fn op_lwc1(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 4, false)?;
+        self.check_access(address, 4, Access::Read)?;
+        let value = self.memory.get_word(address);
+        self.cp1.set(instruction.ft_register(), value);
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Store word from coprocessor 1
+    pub(crate) fn op_swc1(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 4, true)?;
+        self.check_access(address, 4, Access::Write)?;
+        let value = self.cp1.get(instruction.ft_register());
+        self.memory.set_word(address, value);
+        self.advance_program_counter();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::error::ExecutionErrorCause;
+    use crate::processor::Processor;
+    use crate::test_util::{execute, i_type, processor};
+    use mips_types::constants::*;
+
+    /// Encode a coprocessor 1 instruction
+    fn cop1(rs: u8, ft: u8, fs: u8, fd: u8, function: u8) -> u32 {
+        (OP_COP1 as u32) << 26
+            | (rs as u32) << 21
+            | (ft as u32) << 16
+            | (fs as u32) << 11
+            | (fd as u32) << 6
+            | function as u32
+    }
+
+    fn new_processor() -> Processor {
+        let (processor, _) = processor(Config::default(), "", &[0; 8], &[0; 8]);
+        processor
+    }
+
+    /// Execute the single precision operation on $f2 and $f4, and get $f0
+    fn single(function: u8, s: f32, t: f32) -> f32 {
+        let mut processor = new_processor();
+        processor.cp1.set_single(2, s);
+        processor.cp1.set_single(4, t);
+        execute(&mut processor, &[], cop1(COP1_FMT_S, 4, 2, 0, function)).unwrap();
+
+        processor.cp1.get_single(0)
+    }
+
+    /// Execute the double precision operation on $f2 and $f4, and get $f0
+    fn double(function: u8, s: f64, t: f64) -> f64 {
+        let mut processor = new_processor();
+        processor.cp1.set_double(2, s);
+        processor.cp1.set_double(4, t);
+        execute(&mut processor, &[], cop1(COP1_FMT_D, 4, 2, 0, function)).unwrap();
+
+        processor.cp1.get_double(0)
+    }
+
+    #[test]
+    fn single_precision_arithmetic() {
+        assert_eq!(single(COP1_FUNCTION_ADD, 1.5, 2.25), 3.75);
+        assert_eq!(single(COP1_FUNCTION_SUB, 1.5, 2.25), -0.75);
+        assert_eq!(single(COP1_FUNCTION_MUL, 1.5, -2.0), -3.0);
+        assert_eq!(single(COP1_FUNCTION_DIV, 1.0, 4.0), 0.25);
+        assert_eq!(single(COP1_FUNCTION_DIV, 1.0, 0.0), f32::INFINITY);
+        assert!(single(COP1_FUNCTION_DIV, 0.0, 0.0).is_nan());
+        assert_eq!(single(COP1_FUNCTION_ABS, -1.5, 0.0), 1.5);
+        assert_eq!(single(COP1_FUNCTION_MOV, -1.5, 0.0), -1.5);
+        assert_eq!(single(COP1_FUNCTION_NEG, 1.5, 0.0), -1.5);
+    }
+
+    #[test]
+    fn double_precision_arithmetic() {
+        // Needs more precision than a single
+        let third = 1.0 / 3.0;
+        assert_eq!(double(COP1_FUNCTION_ADD, third, 1.0), third + 1.0);
+        assert_eq!(double(COP1_FUNCTION_SUB, 1.5, 2.25), -0.75);
+        assert_eq!(double(COP1_FUNCTION_MUL, third, 3.0), 1.0);
+        assert_eq!(double(COP1_FUNCTION_DIV, 1.0, 3.0), third);
+        assert_eq!(double(COP1_FUNCTION_ABS, -third, 0.0), third);
+        assert_eq!(double(COP1_FUNCTION_MOV, third, 0.0), third);
+        assert_eq!(double(COP1_FUNCTION_NEG, third, 0.0), -third);
+    }
+
+    #[test]
+    fn double_register_pairs() {
+        let mut processor = new_processor();
+        processor.cp1.set_double(2, 1.0);
+        assert_eq!(processor.cp1.get(2), 0);
+        assert_eq!(processor.cp1.get(3), 0x3ff0_0000);
+
+        // The low bit of the register number is ignored
+        assert_eq!(processor.cp1.get_double(3), 1.0);
+        processor.cp1.set_double(5, 2.0);
+        assert_eq!(processor.cp1.get_double(4), 2.0);
+        assert_eq!(processor.cp1.get_double(2), 1.0);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(
+            single(COP1_FUNCTION_CVT_W, -2.5, 0.0).to_bits(),
+            (-2i32) as u32
+        );
+        assert_eq!(single(COP1_FUNCTION_CVT_W, 3.5, 0.0).to_bits(), 4);
+        assert_eq!(
+            double(COP1_FUNCTION_CVT_W, 1e10, 0.0).to_bits() as u32,
+            i32::MAX as u32
+        );
+        assert_eq!(
+            double(COP1_FUNCTION_CVT_W, f64::NAN, 0.0).to_bits() as u32,
+            i32::MAX as u32
+        );
+        assert_eq!(
+            double(COP1_FUNCTION_CVT_S, 0.1, 0.0).to_bits() as u32,
+            0.1f32.to_bits()
+        );
+
+        let mut processor = new_processor();
+        processor.cp1.set_single(2, 0.1);
+        let instruction = cop1(COP1_FMT_S, 0, 2, 0, COP1_FUNCTION_CVT_D);
+        execute(&mut processor, &[], instruction).unwrap();
+        assert_eq!(processor.cp1.get_double(0), 0.1f32 as f64);
+
+        processor.cp1.set(2, (-7i32) as u32);
+        execute(
+            &mut processor,
+            &[],
+            cop1(COP1_FMT_W, 0, 2, 4, COP1_FUNCTION_CVT_S),
+        )
+        .unwrap();
+        execute(
+            &mut processor,
+            &[],
+            cop1(COP1_FMT_W, 0, 2, 6, COP1_FUNCTION_CVT_D),
+        )
+        .unwrap();
+        assert_eq!(processor.cp1.get_single(4), -7.0);
+        assert_eq!(processor.cp1.get_double(6), -7.0);
+    }
+
+    /// Compare $f2 and $f4, then branch on the condition
+    fn compare_and_branch(rs: u8, function: u8, s: f64, t: f64, branch: u8) -> u32 {
+        let mut processor = new_processor();
+        processor.cp1.set_double(2, s);
+        processor.cp1.set_double(4, t);
+        if rs == COP1_FMT_S {
+            processor.cp1.set_single(2, s as f32);
+            processor.cp1.set_single(4, t as f32);
+        }
+        execute(&mut processor, &[], cop1(rs, 4, 2, 0, function)).unwrap();
+        let instruction = i_type(OP_COP1, branch, COP1_RS_BC, 3);
+        execute(&mut processor, &[], instruction).unwrap();
+
+        processor.program_counter
+    }
+
+    #[test]
+    fn compares_and_branches() {
+        let taken = TEXT_OFFSET + 8 + 12;
+        let not_taken = TEXT_OFFSET + 8;
+        for rs in [COP1_FMT_S, COP1_FMT_D] {
+            for (function, s, t, expected) in [
+                (COP1_FUNCTION_C_EQ, 1.0, 1.0, true),
+                (COP1_FUNCTION_C_EQ, 1.0, 2.0, false),
+                (COP1_FUNCTION_C_LT, 1.0, 2.0, true),
+                (COP1_FUNCTION_C_LT, 1.0, 1.0, false),
+                (COP1_FUNCTION_C_LE, 1.0, 1.0, true),
+                (COP1_FUNCTION_C_LE, 2.0, 1.0, false),
+                // Comparisons with NaN are false
+                (COP1_FUNCTION_C_EQ, f64::NAN, f64::NAN, false),
+                (COP1_FUNCTION_C_LE, f64::NAN, 1.0, false),
+            ] {
+                let (bc1t, bc1f) = if expected {
+                    (taken, not_taken)
+                } else {
+                    (not_taken, taken)
+                };
+                let message = format!("{} {} {} {}", rs, function, s, t);
+                assert_eq!(
+                    compare_and_branch(rs, function, s, t, COP1_BC_RT_BC1T),
+                    bc1t,
+                    "{}",
+                    message
+                );
+                assert_eq!(
+                    compare_and_branch(rs, function, s, t, COP1_BC_RT_BC1F),
+                    bc1f,
+                    "{}",
+                    message
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn moves() {
+        let mut processor = new_processor();
+        let mtc1 = cop1(COP1_RS_MT, REG_A0, 3, 0, 0);
+        execute(&mut processor, &[(REG_A0, 0x4049_0fdb)], mtc1).unwrap();
+        assert_eq!(processor.cp1.get_single(3), std::f32::consts::PI);
+
+        let mfc1 = cop1(COP1_RS_MF, REG_A1, 3, 0, 0);
+        execute(&mut processor, &[], mfc1).unwrap();
+        assert_eq!(processor.registers.get(REG_A1), 0x4049_0fdb);
+    }
+
+    #[test]
+    fn loads_and_stores() {
+        let mut processor = new_processor();
+        let registers = [(REG_A0, DATA_OFFSET)];
+        processor.cp1.set(1, 0x1234_5678);
+        execute(&mut processor, &registers, i_type(OP_SWC1, 1, REG_A0, 4)).unwrap();
+        assert_eq!(processor.memory.get_word(DATA_OFFSET + 4), 0x1234_5678);
+
+        execute(&mut processor, &registers, i_type(OP_LWC1, 2, REG_A0, 4)).unwrap();
+        assert_eq!(processor.cp1.get(2), 0x1234_5678);
+
+        let address = DATA_OFFSET + 2;
+        assert!(matches!(
+            execute(&mut processor, &registers, i_type(OP_LWC1, 2, REG_A0, 2)),
+            Err(ExecutionErrorCause::AddressErrorLoad(a)) if a == address
+        ));
+        assert!(matches!(
+            execute(&mut processor, &registers, i_type(OP_SWC1, 2, REG_A0, 2)),
+            Err(ExecutionErrorCause::AddressErrorStore(a)) if a == address
+        ));
+    }
+
+    #[test]
+    fn reserved_instructions() {
+        let mut processor = new_processor();
+        for instruction in [
+            cop1(0x15, 0, 0, 0, 0),
+            cop1(COP1_FMT_S, 0, 0, 0, COP1_FUNCTION_CVT_S),
+            cop1(COP1_FMT_D, 0, 0, 0, COP1_FUNCTION_CVT_D),
+            cop1(COP1_FMT_W, 0, 0, 0, COP1_FUNCTION_CVT_W),
+            cop1(COP1_RS_BC, 2, 0, 0, 0),
+        ] {
+            assert!(matches!(
+                execute(&mut processor, &[], instruction),
+                Err(ExecutionErrorCause::ReservedInstruction)
+            ));
+        }
+    }
+}
@@ -1,45 +1,92 @@
+use crate::error::ExecutionErrorCause;
 use crate::instruction::Instruction;
 use crate::math::add_unsigned;
+use crate::memory_map::Access;
 use crate::Processor;
+use mips_types::constants::*;
 
 impl Processor {
-    /// Branch on equal
-    pub(crate) fn op_beq(&mut self, instruction: Instruction) {
+    /// Branch to the instruction's offset if the condition is true, otherwise
+    /// continue on to the next instruction.
+    pub(crate) fn branch_if(&mut self, instruction: Instruction, condition: bool) {
         let offset = (instruction.immediate() as i32) << 2;
         let address = add_unsigned(self.next_program_counter, offset);
-        let s_value = self.registers.get(instruction.s_register());
-        let t_value = self.registers.get(instruction.t_register());
+        self.branch_taken = condition;
 
-        if s_value == t_value {
+        if condition {
             self.jump_to(address);
         } else {
             self.advance_program_counter();
+            // The delay slot is executed even if the branch isn't taken
+            self.in_delay_slot = self.config.enable_delay_slots;
         }
     }
 
+    /// Get the effective address of a load/store instruction
+    pub(crate) fn load_store_address(&self, instruction: Instruction) -> u32 {
+        let s_address = self.registers.get(instruction.s_register());
+        add_unsigned(s_address, instruction.immediate() as i32)
+    }
+
+    /// Branch on equal
+    pub(crate) fn op_beq(&mut self, instruction: Instruction) {
+        let s_value = self.registers.get(instruction.s_register());
+        let t_value = self.registers.get(instruction.t_register());
+        self.branch_if(instruction, s_value == t_value);
+    }
+
     /// Branch on not equal
     pub(crate) fn op_bne(&mut self, instruction: Instruction) {
-        let offset = (instruction.immediate() as i32) << 2;
-        let address = add_unsigned(self.next_program_counter, offset);
         let s_value = self.registers.get(instruction.s_register());
         let t_value = self.registers.get(instruction.t_register());
+        self.branch_if(instruction, s_value != t_value);
+    }
 
-        if s_value != t_value {
-            self.jump_to(address);
-        } else {
-            self.advance_program_counter();
+    /// Branch on less than or equal to zero
+    pub(crate) fn op_blez(&mut self, instruction: Instruction) {
+        let s_value = self.registers.get(instruction.s_register()) as i32;
+        self.branch_if(instruction, s_value <= 0);
+    }
+
+    /// Branch on greater than zero
+    pub(crate) fn op_bgtz(&mut self, instruction: Instruction) {
+        let s_value = self.registers.get(instruction.s_register()) as i32;
+        self.branch_if(instruction, s_value > 0);
+    }
+
+    /// The BCOND family of branches (bltz, bgez, bltzal, bgezal).
+    /// The linking variants always set the return address, even if the branch
+    /// is not taken.
+    pub(crate) fn op_bcond(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let s_value = self.registers.get(instruction.s_register()) as i32;
+        let (condition, link) = match instruction.t_register() {
+            BCOND_RT_BLTZ => (s_value < 0, false),
+            BCOND_RT_BGEZ => (s_value >= 0, false),
+            BCOND_RT_BLTZAL => (s_value < 0, true),
+            BCOND_RT_BGEZAL => (s_value >= 0, true),
+            _ => return Err(ExecutionErrorCause::ReservedInstruction),
+        };
+
+        if link {
+            let offset = if self.config.enable_delay_slots { 8 } else { 4 };
+            self.registers
+                .set(REG_RA, self.program_counter.wrapping_add(offset));
         }
+
+        self.branch_if(instruction, condition);
+        Ok(())
     }
 
     /// Add immediate (with overflow check)
-    pub(crate) fn op_addi(&mut self, instruction: Instruction) {
+    pub(crate) fn op_addi(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
         let a = self.registers.get(instruction.s_register()) as i32;
         let b = instruction.immediate() as i32;
         let value = a
             .checked_add(b)
-            .unwrap_or_else(|| panic!("Overflow in addi"));
+            .ok_or(ExecutionErrorCause::IntegerOverflow)?;
         self.registers.set(instruction.t_register(), value as u32);
-        self.advance_program_counter()
+        self.advance_program_counter();
+        Ok(())
     }
 
     /// Add immediate (no overflow check)
@@ -63,6 +110,25 @@ impl Processor {
         self.advance_program_counter();
     }
 
+    /// Set on less than immediate unsigned
+    pub(crate) fn op_sltiu(&mut self, instruction: Instruction) {
+        let s = self.registers.get(instruction.s_register());
+        // The immediate is sign-extended, then compared as unsigned
+        let immediate = instruction.immediate() as i32 as u32;
+        let value = if s < immediate { 1 } else { 0 };
+        self.registers.set(instruction.t_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Bitwise and immediate
+    pub(crate) fn op_andi(&mut self, instruction: Instruction) {
+        let a = self.registers.get(instruction.s_register());
+        // Don't sign-extend the immediate
+        let immediate = instruction.immediate() as u16 as u32;
+        self.registers.set(instruction.t_register(), a & immediate);
+        self.advance_program_counter();
+    }
+
     /// Bitwise or immediate
     pub(crate) fn op_ori(&mut self, instruction: Instruction) {
         let a = self.registers.get(instruction.s_register());
@@ -72,6 +138,15 @@ impl Processor {
         self.advance_program_counter();
     }
 
+    /// Bitwise exclusive or immediate
+    pub(crate) fn op_xori(&mut self, instruction: Instruction) {
+        let a = self.registers.get(instruction.s_register());
+        // Don't sign-extend the immediate
+        let immediate = instruction.immediate() as u16 as u32;
+        self.registers.set(instruction.t_register(), a ^ immediate);
+        self.advance_program_counter();
+    }
+
     /// Load upper immediate
     pub(crate) fn op_lui(&mut self, instruction: Instruction) {
         let value = (instruction.immediate() as u32) << 16;
@@ -80,52 +155,354 @@ impl Processor {
     }
 
     /// Load byte
-    pub(crate) fn op_lb(&mut self, instruction: Instruction) {
-        let s_address = self.registers.get(instruction.s_register());
-        let value = self
-            .memory
-            .get(add_unsigned(s_address, instruction.immediate() as i32));
+    pub(crate) fn op_lb(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_access(address, 1, Access::Read)?;
+        let value = self.memory.get(address);
+        self.registers
+            .set(instruction.t_register(), value as i8 as i32 as u32);
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Load half word
+    pub(crate) fn op_lh(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 2, false)?;
+        self.check_access(address, 2, Access::Read)?;
+        let value = self.memory.get_half(address);
         self.registers
-            .set(instruction.t_register(), value as i32 as u32);
+            .set(instruction.t_register(), value as i16 as i32 as u32);
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Load word left. Loads the bytes from the address up to the end of its
+    /// word into the most significant bytes of the register.
+    pub(crate) fn op_lwl(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        let byte_offset = (address & 3) as usize;
+        self.check_access(address, 4 - byte_offset as u32, Access::Read)?;
+        let mut bytes = self.registers.get(instruction.t_register()).to_be_bytes();
+
+        for (i, byte) in bytes.iter_mut().take(4 - byte_offset).enumerate() {
+            *byte = self.memory.get(address + i as u32);
+        }
+
+        self.registers
+            .set(instruction.t_register(), u32::from_be_bytes(bytes));
+        self.advance_program_counter();
+        Ok(())
     }
 
     /// Load word
-    pub(crate) fn op_lw(&mut self, instruction: Instruction) {
-        let s_address = self.registers.get(instruction.s_register());
-        let value = self
-            .memory
-            .get_word(add_unsigned(s_address, instruction.immediate() as i32));
+    pub(crate) fn op_lw(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 4, false)?;
+        self.check_access(address, 4, Access::Read)?;
+        let value = self.memory.get_word(address);
         self.registers.set(instruction.t_register(), value);
         self.advance_program_counter();
+        Ok(())
     }
 
     /// Load byte unsigned
-    pub(crate) fn op_lbu(&mut self, instruction: Instruction) {
-        let s_address = self.registers.get(instruction.s_register());
-        let value = self
-            .memory
-            .get(add_unsigned(s_address, instruction.immediate() as i32));
+    pub(crate) fn op_lbu(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_access(address, 1, Access::Read)?;
+        let value = self.memory.get(address);
+        self.registers.set(instruction.t_register(), value as u32);
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Load half word unsigned
+    pub(crate) fn op_lhu(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 2, false)?;
+        self.check_access(address, 2, Access::Read)?;
+        let value = self.memory.get_half(address);
         self.registers.set(instruction.t_register(), value as u32);
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Load word right. Loads the bytes from the start of the address's word
+    /// up to the address into the least significant bytes of the register.
+    pub(crate) fn op_lwr(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        let byte_offset = (address & 3) as usize;
+        self.check_access(address & !3, byte_offset as u32 + 1, Access::Read)?;
+        let mut bytes = self.registers.get(instruction.t_register()).to_be_bytes();
+
+        for (i, byte) in bytes.iter_mut().rev().take(byte_offset + 1).enumerate() {
+            *byte = self.memory.get(address - i as u32);
+        }
+
+        self.registers
+            .set(instruction.t_register(), u32::from_be_bytes(bytes));
+        self.advance_program_counter();
+        Ok(())
     }
 
     /// Store byte
-    pub(crate) fn op_sb(&mut self, instruction: Instruction) {
-        let s_address = self.registers.get(instruction.s_register());
-        let address = add_unsigned(s_address, instruction.immediate() as i32);
+    pub(crate) fn op_sb(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_access(address, 1, Access::Write)?;
         let value = self.registers.get(instruction.t_register()) as u8;
         self.memory.set(address, value);
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Store half word
+    pub(crate) fn op_sh(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 2, true)?;
+        self.check_access(address, 2, Access::Write)?;
+        let value = self.registers.get(instruction.t_register()) as u16;
+        self.memory.set_half(address, value);
+        self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Store word left. Stores the most significant bytes of the register
+    /// from the address up to the end of its word.
+    pub(crate) fn op_swl(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        let byte_offset = (address & 3) as usize;
+        self.check_access(address, 4 - byte_offset as u32, Access::Write)?;
+        let bytes = self.registers.get(instruction.t_register()).to_be_bytes();
+
+        for (i, byte) in bytes.iter().take(4 - byte_offset).enumerate() {
+            self.memory.set(address + i as u32, *byte);
+        }
+
+        self.advance_program_counter();
+        Ok(())
     }
 
     /// Store word
     #[inline(always)]
-    pub(crate) fn op_sw(&mut self, instruction: Instruction) {
-        let s_address = self.registers.get(instruction.s_register());
-        let address = add_unsigned(s_address, instruction.immediate() as i32);
+    pub(crate) fn op_sw(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        self.check_alignment(address, 4, true)?;
+        self.check_access(address, 4, Access::Write)?;
         let value = self.registers.get(instruction.t_register());
         self.memory.set_word(address, value);
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Store word right. Stores the least significant bytes of the register
+    /// from the start of the address's word up to the address.
+    pub(crate) fn op_swr(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let address = self.load_store_address(instruction);
+        let byte_offset = (address & 3) as usize;
+        self.check_access(address & !3, byte_offset as u32 + 1, Access::Write)?;
+        let bytes = self.registers.get(instruction.t_register()).to_be_bytes();
+
+        for (i, byte) in bytes.iter().rev().take(byte_offset + 1).enumerate() {
+            self.memory.set(address - i as u32, *byte);
+        }
+
+        self.advance_program_counter();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::error::ExecutionErrorCause;
+    use crate::processor::Processor;
+    use crate::test_util::{execute, i_type, processor};
+    use mips_types::constants::*;
+
+    const DATA: [u8; 8] = [0x81, 0x82, 0x83, 0x84, 0x05, 0x06, 0x07, 0x08];
+
+    /// Execute the instruction with $a1 as its source, and get $a0
+    fn result(op_code: u8, s: u32, immediate: u16) -> Result<u32, ExecutionErrorCause> {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &DATA);
+        let instruction = i_type(op_code, REG_A0, REG_A1, immediate);
+        execute(&mut processor, &[(REG_A1, s)], instruction)?;
+
+        Ok(processor.registers.get(REG_A0))
+    }
+
+    #[test]
+    fn arithmetic_and_logic() {
+        assert_eq!(result(OP_ADDI, 5, (-7i16) as u16).unwrap(), (-2i32) as u32);
+        assert!(matches!(
+            result(OP_ADDI, 0x7fff_ffff, 1),
+            Err(ExecutionErrorCause::IntegerOverflow)
+        ));
+        assert_eq!(result(OP_ADDIU, 0x7fff_ffff, 1).unwrap(), 0x8000_0000);
+        assert_eq!(result(OP_ADDIU, 0, 0xffff).unwrap(), 0xffff_ffff);
+
+        // The logical immediates are zero extended
+        assert_eq!(result(OP_ANDI, 0xffff_ffff, 0x8001).unwrap(), 0x8001);
+        assert_eq!(result(OP_ORI, 0x1_0000, 0x8001).unwrap(), 0x1_8001);
+        assert_eq!(result(OP_XORI, 0xffff_ffff, 0x8001).unwrap(), 0xffff_7ffe);
+        assert_eq!(result(OP_LUI, 0, 0x8001).unwrap(), 0x8001_0000);
+
+        // The comparison immediates are sign extended
+        assert_eq!(result(OP_SLTI, (-2i32) as u32, 0xffff).unwrap(), 1);
+        assert_eq!(result(OP_SLTI, 0, 0xffff).unwrap(), 0);
+        assert_eq!(result(OP_SLTIU, 0xffff_fffe, 0xffff).unwrap(), 1);
+        assert_eq!(result(OP_SLTIU, 0xffff_ffff, 0xffff).unwrap(), 0);
+    }
+
+    #[test]
+    fn loads() {
+        assert_eq!(result(OP_LB, DATA_OFFSET, 1).unwrap(), 0xffff_ff82);
+        assert_eq!(result(OP_LBU, DATA_OFFSET, 1).unwrap(), 0x82);
+        assert_eq!(result(OP_LB, DATA_OFFSET, 4).unwrap(), 0x05);
+        assert_eq!(result(OP_LH, DATA_OFFSET, 2).unwrap(), 0xffff_8384);
+        assert_eq!(result(OP_LHU, DATA_OFFSET, 2).unwrap(), 0x8384);
+        assert_eq!(
+            result(OP_LW, DATA_OFFSET + 8, (-4i16) as u16).unwrap(),
+            0x0506_0708
+        );
+    }
+
+    /// Execute the instructions in order, and get the processor
+    fn execute_all(registers: &[(u8, u32)], instructions: &[u32]) -> Processor {
+        let text = vec![0; instructions.len()];
+        let (mut processor, _) = processor(Config::default(), "", &text, &DATA);
+        execute(&mut processor, registers, instructions[0]).unwrap();
+        for instruction in &instructions[1..] {
+            execute(&mut processor, &[], *instruction).unwrap();
+        }
+
+        processor
+    }
+
+    #[test]
+    fn unaligned_loads() {
+        let registers = [(REG_A1, DATA_OFFSET), (REG_A0, 0x1122_3344)];
+        for (offset, expected) in [
+            (0, 0x8182_8384),
+            (1, 0x8283_8405),
+            (2, 0x8384_0506),
+            (3, 0x8405_0607),
+        ] {
+            let processor = execute_all(
+                &registers,
+                &[
+                    i_type(OP_LWL, REG_A0, REG_A1, offset),
+                    i_type(OP_LWR, REG_A0, REG_A1, offset + 3),
+                ],
+            );
+            assert_eq!(processor.registers.get(REG_A0), expected);
+        }
+
+        // Only the bytes in the addressed word are replaced
+        let processor = execute_all(&registers, &[i_type(OP_LWL, REG_A0, REG_A1, 2)]);
+        assert_eq!(processor.registers.get(REG_A0), 0x8384_3344);
+        let processor = execute_all(&registers, &[i_type(OP_LWR, REG_A0, REG_A1, 1)]);
+        assert_eq!(processor.registers.get(REG_A0), 0x1122_8182);
+    }
+
+    #[test]
+    fn stores() {
+        let registers = [(REG_A1, DATA_OFFSET), (REG_A0, 0x1122_3344)];
+        let processor = execute_all(&registers, &[i_type(OP_SB, REG_A0, REG_A1, 1)]);
+        assert_eq!(
+            processor.read_memory(DATA_OFFSET, 4),
+            [0x81, 0x44, 0x83, 0x84]
+        );
+
+        let processor = execute_all(&registers, &[i_type(OP_SH, REG_A0, REG_A1, 2)]);
+        assert_eq!(
+            processor.read_memory(DATA_OFFSET, 4),
+            [0x81, 0x82, 0x33, 0x44]
+        );
+
+        let processor = execute_all(&registers, &[i_type(OP_SW, REG_A0, REG_A1, 4)]);
+        assert_eq!(
+            processor.read_memory(DATA_OFFSET + 4, 4),
+            [0x11, 0x22, 0x33, 0x44]
+        );
+
+        let processor = execute_all(
+            &registers,
+            &[
+                i_type(OP_SWL, REG_A0, REG_A1, 1),
+                i_type(OP_SWR, REG_A0, REG_A1, 4),
+            ],
+        );
+        assert_eq!(
+            processor.read_memory(DATA_OFFSET, 8),
+            [0x81, 0x11, 0x22, 0x33, 0x44, 0x06, 0x07, 0x08]
+        );
+    }
+
+    /// Execute the branch (at the second instruction) with $a1 as its source,
+    /// and get the processor
+    fn branch(op_code: u8, t_register: u8, s: u32) -> Processor {
+        let (mut processor, _) = processor(Config::default(), "", &[0; 8], &[]);
+        execute(&mut processor, &[], 0).unwrap();
+        let instruction = i_type(op_code, t_register, REG_A1, 3);
+        execute(&mut processor, &[(REG_A1, s)], instruction).unwrap();
+
+        processor
+    }
+
+    #[test]
+    fn branches() {
+        let taken = TEXT_OFFSET + 8 + 12;
+        let not_taken = TEXT_OFFSET + 8;
+        let negative = (-1i32) as u32;
+        for (op_code, t_register, s, destination) in [
+            (OP_BEQ, REG_A1, 1, taken),
+            (OP_BEQ, 0, 1, not_taken),
+            (OP_BNE, 0, 1, taken),
+            (OP_BNE, REG_A1, 1, not_taken),
+            (OP_BLEZ, 0, 0, taken),
+            (OP_BLEZ, 0, negative, taken),
+            (OP_BLEZ, 0, 1, not_taken),
+            (OP_BGTZ, 0, 1, taken),
+            (OP_BGTZ, 0, 0, not_taken),
+            (OP_BGTZ, 0, negative, not_taken),
+            (OP_BCOND, BCOND_RT_BLTZ, negative, taken),
+            (OP_BCOND, BCOND_RT_BLTZ, 0, not_taken),
+            (OP_BCOND, BCOND_RT_BGEZ, 0, taken),
+            (OP_BCOND, BCOND_RT_BGEZ, negative, not_taken),
+        ] {
+            let processor = branch(op_code, t_register, s);
+            assert_eq!(
+                processor.program_counter, destination,
+                "{} {} {}",
+                op_code, t_register, s
+            );
+        }
+
+        let (mut processor, _) = processor(Config::default(), "", &[0; 2], &[]);
+        execute(&mut processor, &[], 0).unwrap();
+        let backwards = i_type(OP_BEQ, 0, 0, (-2i16) as u16);
+        execute(&mut processor, &[], backwards).unwrap();
+        assert_eq!(processor.program_counter, TEXT_OFFSET);
+    }
+
+    #[test]
+    fn branch_and_link() {
+        // The return address is set even if the branch isn't taken
+        for (t_register, s, destination) in [
+            (BCOND_RT_BLTZAL, (-1i32) as u32, TEXT_OFFSET + 20),
+            (BCOND_RT_BLTZAL, 0, TEXT_OFFSET + 8),
+            (BCOND_RT_BGEZAL, 0, TEXT_OFFSET + 20),
+            (BCOND_RT_BGEZAL, (-1i32) as u32, TEXT_OFFSET + 8),
+        ] {
+            let processor = branch(OP_BCOND, t_register, s);
+            assert_eq!(processor.program_counter, destination);
+            assert_eq!(processor.registers.get(REG_RA), TEXT_OFFSET + 8);
+        }
+
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        assert!(matches!(
+            execute(&mut processor, &[], i_type(OP_BCOND, 0x05, 0, 1)),
+            Err(ExecutionErrorCause::ReservedInstruction)
+        ));
     }
 }
@@ -12,7 +12,44 @@ impl Processor {
     pub(crate) fn op_jal(&mut self, instruction: Instruction) {
         let offset = if self.config.enable_delay_slots { 8 } else { 4 };
 
-        self.registers.set(REG_RA, self.program_counter + offset);
+        self.registers
+            .set(REG_RA, self.program_counter.wrapping_add(offset));
         self.jump_to(instruction.real_address(self.program_counter));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_util::{execute, processor};
+    use mips_types::constants::*;
+
+    #[test]
+    fn jump_and_link() {
+        for (op_code, enable_delay_slots) in [(OP_J, false), (OP_JAL, false), (OP_JAL, true)] {
+            let config = Config {
+                enable_delay_slots,
+                ..Config::default()
+            };
+            let (mut processor, _) = processor(config, "", &[0; 4], &[]);
+            // The target keeps the upper 4 bits of the program counter
+            let target = (TEXT_OFFSET + 12) & 0x0fff_ffff;
+            let instruction = (op_code as u32) << 26 | target >> 2;
+            execute(&mut processor, &[], instruction).unwrap();
+
+            if enable_delay_slots {
+                assert_eq!(processor.program_counter, TEXT_OFFSET + 4);
+                assert_eq!(processor.next_program_counter, TEXT_OFFSET + 12);
+                assert_eq!(processor.registers.get(REG_RA), TEXT_OFFSET + 8);
+            } else {
+                assert_eq!(processor.program_counter, TEXT_OFFSET + 12);
+                let return_address = if op_code == OP_JAL {
+                    TEXT_OFFSET + 4
+                } else {
+                    0
+                };
+                assert_eq!(processor.registers.get(REG_RA), return_address);
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 //! Implementations for MIPS operations and syscalls
 
+mod cop0;
+mod cop1;
 mod i_type;
 mod j_type;
 mod r_type;
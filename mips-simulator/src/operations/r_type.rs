@@ -1,3 +1,4 @@
+use crate::error::ExecutionErrorCause;
 use crate::instruction::Instruction;
 use crate::Processor;
 
@@ -15,6 +16,47 @@ impl Processor {
         self.advance_program_counter()
     }
 
+    /// Shift right logical
+    pub(crate) fn op_srl(&mut self, instruction: Instruction) {
+        let value = self.registers.get(instruction.t_register()) >> instruction.shift_amount();
+        self.registers.set(instruction.d_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Shift right arithmetic
+    pub(crate) fn op_sra(&mut self, instruction: Instruction) {
+        let t = self.registers.get(instruction.t_register()) as i32;
+        let value = t >> instruction.shift_amount();
+        self.registers.set(instruction.d_register(), value as u32);
+        self.advance_program_counter();
+    }
+
+    /// Shift left logical variable
+    pub(crate) fn op_sllv(&mut self, instruction: Instruction) {
+        // Only the lower five bits of $s are used as the shift amount
+        let shift = self.registers.get(instruction.s_register()) & 0x1F;
+        let value = self.registers.get(instruction.t_register()) << shift;
+        self.registers.set(instruction.d_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Shift right logical variable
+    pub(crate) fn op_srlv(&mut self, instruction: Instruction) {
+        let shift = self.registers.get(instruction.s_register()) & 0x1F;
+        let value = self.registers.get(instruction.t_register()) >> shift;
+        self.registers.set(instruction.d_register(), value);
+        self.advance_program_counter();
+    }
+
+    /// Shift right arithmetic variable
+    pub(crate) fn op_srav(&mut self, instruction: Instruction) {
+        let shift = self.registers.get(instruction.s_register()) & 0x1F;
+        let t = self.registers.get(instruction.t_register()) as i32;
+        self.registers
+            .set(instruction.d_register(), (t >> shift) as u32);
+        self.advance_program_counter();
+    }
+
     /// Jump register
     pub(crate) fn op_jr(&mut self, instruction: Instruction) {
         let address = self.registers.get(instruction.s_register());
@@ -29,10 +71,16 @@ impl Processor {
         self.registers.set(return_register, return_address);
     }
 
-    /// Break (exceptions/debugger)
-    pub(crate) fn op_break(&mut self) {
+    /// Break (exceptions/debugger). If there is no exception handler, the
+    /// program stops.
+    pub(crate) fn op_break(&mut self) -> Result<(), ExecutionErrorCause> {
+        if self.kernel_loaded {
+            return Err(ExecutionErrorCause::Breakpoint);
+        }
+
         self.advance_program_counter();
         self.running = false;
+        Ok(())
     }
 
     /// Move from HI
@@ -49,10 +97,33 @@ impl Processor {
         self.advance_program_counter();
     }
 
+    /// Move to HI
+    pub(crate) fn op_mthi(&mut self, instruction: Instruction) {
+        self.registers.hi_register = self.registers.get(instruction.s_register());
+        self.advance_program_counter();
+    }
+
+    /// Move to LO
+    pub(crate) fn op_mtlo(&mut self, instruction: Instruction) {
+        self.registers.lo_register = self.registers.get(instruction.s_register());
+        self.advance_program_counter();
+    }
+
     /// Multiply
     pub(crate) fn op_mult(&mut self, instruction: Instruction) {
-        let s = self.registers.get(instruction.s_register()) as i64;
-        let t = self.registers.get(instruction.t_register()) as i64;
+        let s = self.registers.get(instruction.s_register()) as i32 as i64;
+        let t = self.registers.get(instruction.t_register()) as i32 as i64;
+        let result = s * t;
+
+        self.registers.hi_register = (result >> 32) as u32;
+        self.registers.lo_register = result as u32;
+        self.advance_program_counter();
+    }
+
+    /// Multiply unsigned
+    pub(crate) fn op_multu(&mut self, instruction: Instruction) {
+        let s = self.registers.get(instruction.s_register()) as u64;
+        let t = self.registers.get(instruction.t_register()) as u64;
         let result = s * t;
 
         self.registers.hi_register = (result >> 32) as u32;
@@ -61,31 +132,49 @@ impl Processor {
     }
 
     /// Divide
-    pub(crate) fn op_div(&mut self, instruction: Instruction) {
+    pub(crate) fn op_div(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
         let s = self.registers.get(instruction.s_register()) as i32;
         let t = self.registers.get(instruction.t_register()) as i32;
 
         if t == 0 {
-            panic!("Divide by zero");
+            return Err(ExecutionErrorCause::DivideByZero);
         }
 
-        let quotient = s / t;
-        let remainder = s % t;
+        // Dividing the smallest integer by -1 wraps instead of overflowing
+        let quotient = s.wrapping_div(t);
+        let remainder = s.wrapping_rem(t);
 
         self.registers.lo_register = quotient as u32;
         self.registers.hi_register = remainder as u32;
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Divide unsigned
+    pub(crate) fn op_divu(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        let s = self.registers.get(instruction.s_register());
+        let t = self.registers.get(instruction.t_register());
+
+        if t == 0 {
+            return Err(ExecutionErrorCause::DivideByZero);
+        }
+
+        self.registers.lo_register = s / t;
+        self.registers.hi_register = s % t;
+        self.advance_program_counter();
+        Ok(())
     }
 
     /// Add (with overflow check)
-    pub(crate) fn op_add(&mut self, instruction: Instruction) {
+    pub(crate) fn op_add(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
         let a = self.registers.get(instruction.s_register()) as i32;
         let b = self.registers.get(instruction.t_register()) as i32;
         let value = a
             .checked_add(b)
-            .unwrap_or_else(|| panic!("Overflow in add"));
+            .ok_or(ExecutionErrorCause::IntegerOverflow)?;
         self.registers.set(instruction.d_register(), value as u32);
         self.advance_program_counter();
+        Ok(())
     }
 
     /// Add unsigned (no overflow check)
@@ -98,14 +187,32 @@ impl Processor {
     }
 
     /// Subtract (with overflow check)
-    pub(crate) fn op_sub(&mut self, instruction: Instruction) {
+    pub(crate) fn op_sub(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
         let a = self.registers.get(instruction.s_register()) as i32;
         let b = self.registers.get(instruction.t_register()) as i32;
         let value = a
             .checked_sub(b)
-            .unwrap_or_else(|| panic!("Overflow in sub"));
+            .ok_or(ExecutionErrorCause::IntegerOverflow)?;
         self.registers.set(instruction.d_register(), value as u32);
         self.advance_program_counter();
+        Ok(())
+    }
+
+    /// Subtract unsigned (no overflow check)
+    pub(crate) fn op_subu(&mut self, instruction: Instruction) {
+        let a = self.registers.get(instruction.s_register());
+        let b = self.registers.get(instruction.t_register());
+        self.registers
+            .set(instruction.d_register(), a.wrapping_sub(b));
+        self.advance_program_counter();
+    }
+
+    /// Bitwise And
+    pub(crate) fn op_and(&mut self, instruction: Instruction) {
+        let a = self.registers.get(instruction.s_register());
+        let b = self.registers.get(instruction.t_register());
+        self.registers.set(instruction.d_register(), a & b);
+        self.advance_program_counter();
     }
 
     /// Bitwise Or
@@ -124,6 +231,14 @@ impl Processor {
         self.advance_program_counter();
     }
 
+    /// Bitwise Nor
+    pub(crate) fn op_nor(&mut self, instruction: Instruction) {
+        let s = self.registers.get(instruction.s_register());
+        let t = self.registers.get(instruction.t_register());
+        self.registers.set(instruction.d_register(), !(s | t));
+        self.advance_program_counter();
+    }
+
     /// Set if less than
     pub(crate) fn op_slt(&mut self, instruction: Instruction) {
         let t = self.registers.get(instruction.t_register()) as i32;
@@ -132,4 +247,190 @@ impl Processor {
         self.registers.set(instruction.d_register(), result);
         self.advance_program_counter();
     }
+
+    /// Set if less than unsigned
+    pub(crate) fn op_sltu(&mut self, instruction: Instruction) {
+        let t = self.registers.get(instruction.t_register());
+        let s = self.registers.get(instruction.s_register());
+        let result = if s < t { 1 } else { 0 };
+        self.registers.set(instruction.d_register(), result);
+        self.advance_program_counter();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::error::ExecutionErrorCause;
+    use crate::processor::Processor;
+    use crate::test_util::{execute, processor, r_type};
+    use mips_types::constants::*;
+
+    /// Execute the function with $a1 and $a2 as its operands, and get $a0
+    fn result(function: u8, s: u32, t: u32) -> Result<u32, ExecutionErrorCause> {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let instruction = r_type(function, REG_A0, REG_A1, REG_A2);
+        execute(&mut processor, &[(REG_A1, s), (REG_A2, t)], instruction)?;
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 4);
+
+        Ok(processor.registers.get(REG_A0))
+    }
+
+    /// Execute the function with $a1 and $a2 as its operands, and get HI and LO
+    fn hi_lo(function: u8, s: u32, t: u32) -> Result<(u32, u32), ExecutionErrorCause> {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let instruction = r_type(function, 0, REG_A1, REG_A2);
+        execute(&mut processor, &[(REG_A1, s), (REG_A2, t)], instruction)?;
+
+        Ok((
+            processor.registers.hi_register,
+            processor.registers.lo_register,
+        ))
+    }
+
+    /// Shift $a2 by the shift amount, and get $a0
+    fn shift(function: u8, value: u32, shift_amount: u32) -> u32 {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let instruction = r_type(function, REG_A0, 0, REG_A2) | shift_amount << 6;
+        execute(&mut processor, &[(REG_A2, value)], instruction).unwrap();
+
+        processor.registers.get(REG_A0)
+    }
+
+    #[test]
+    fn add_and_subtract() {
+        assert_eq!(
+            result(FUNCTION_ADD, 5, (-7i32) as u32).unwrap(),
+            (-2i32) as u32
+        );
+        assert!(matches!(
+            result(FUNCTION_ADD, 0x7fff_ffff, 1),
+            Err(ExecutionErrorCause::IntegerOverflow)
+        ));
+        assert_eq!(result(FUNCTION_ADDU, 0x7fff_ffff, 1).unwrap(), 0x8000_0000);
+        assert_eq!(result(FUNCTION_ADDU, 0xffff_ffff, 2).unwrap(), 1);
+
+        assert_eq!(result(FUNCTION_SUB, 5, 7).unwrap(), (-2i32) as u32);
+        assert!(matches!(
+            result(FUNCTION_SUB, 0x8000_0000, 1),
+            Err(ExecutionErrorCause::IntegerOverflow)
+        ));
+        assert_eq!(result(FUNCTION_SUBU, 0x8000_0000, 1).unwrap(), 0x7fff_ffff);
+    }
+
+    #[test]
+    fn logic_and_comparisons() {
+        assert_eq!(result(FUNCTION_AND, 0b1100, 0b1010).unwrap(), 0b1000);
+        assert_eq!(result(FUNCTION_OR, 0b1100, 0b1010).unwrap(), 0b1110);
+        assert_eq!(result(FUNCTION_XOR, 0b1100, 0b1010).unwrap(), 0b0110);
+        assert_eq!(result(FUNCTION_NOR, 0b1100, 0b1010).unwrap(), !0b1110);
+
+        assert_eq!(result(FUNCTION_SLT, 0xffff_ffff, 1).unwrap(), 1);
+        assert_eq!(result(FUNCTION_SLT, 1, 1).unwrap(), 0);
+        assert_eq!(result(FUNCTION_SLTU, 0xffff_ffff, 1).unwrap(), 0);
+        assert_eq!(result(FUNCTION_SLTU, 1, 0xffff_ffff).unwrap(), 1);
+    }
+
+    #[test]
+    fn shifts() {
+        assert_eq!(shift(FUNCTION_SLL, 0x8000_0001, 4), 0x10);
+        assert_eq!(shift(FUNCTION_SRL, 0x8000_0000, 4), 0x0800_0000);
+        assert_eq!(shift(FUNCTION_SRA, 0x8000_0000, 4), 0xf800_0000);
+        assert_eq!(shift(FUNCTION_SRA, 0x4000_0000, 4), 0x0400_0000);
+
+        // The variable shifts use the low 5 bits of $rs
+        assert_eq!(result(FUNCTION_SLLV, 33, 1).unwrap(), 2);
+        assert_eq!(result(FUNCTION_SRLV, 31, 0x8000_0000).unwrap(), 1);
+        assert_eq!(result(FUNCTION_SRAV, 31, 0x8000_0000).unwrap(), 0xffff_ffff);
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        assert_eq!(
+            hi_lo(FUNCTION_MULT, (-2i32) as u32, 3).unwrap(),
+            (0xffff_ffff, (-6i32) as u32)
+        );
+        assert_eq!(
+            hi_lo(FUNCTION_MULTU, 0xffff_ffff, 2).unwrap(),
+            (1, 0xffff_fffe)
+        );
+
+        assert_eq!(
+            hi_lo(FUNCTION_DIV, (-7i32) as u32, 2).unwrap(),
+            ((-1i32) as u32, (-3i32) as u32)
+        );
+        assert_eq!(
+            hi_lo(FUNCTION_DIV, 0x8000_0000, 0xffff_ffff).unwrap(),
+            (0, 0x8000_0000)
+        );
+        assert_eq!(
+            hi_lo(FUNCTION_DIVU, 0xffff_fff9, 2).unwrap(),
+            (1, 0x7fff_fffc)
+        );
+        assert!(matches!(
+            hi_lo(FUNCTION_DIV, 1, 0),
+            Err(ExecutionErrorCause::DivideByZero)
+        ));
+        assert!(matches!(
+            hi_lo(FUNCTION_DIVU, 1, 0),
+            Err(ExecutionErrorCause::DivideByZero)
+        ));
+    }
+
+    #[test]
+    fn move_to_and_from_hi_and_lo() {
+        let (mut processor, _) = processor(Config::default(), "", &[0; 4], &[]);
+        let registers = [(REG_A0, 1), (REG_A1, 2)];
+        execute(
+            &mut processor,
+            &registers,
+            r_type(FUNCTION_MTHI, 0, REG_A0, 0),
+        )
+        .unwrap();
+        execute(&mut processor, &[], r_type(FUNCTION_MTLO, 0, REG_A1, 0)).unwrap();
+        execute(&mut processor, &[], r_type(FUNCTION_MFHI, REG_A2, 0, 0)).unwrap();
+        execute(&mut processor, &[], r_type(FUNCTION_MFLO, REG_V0, 0, 0)).unwrap();
+
+        assert_eq!(processor.registers.get(REG_A2), 1);
+        assert_eq!(processor.registers.get(REG_V0), 2);
+    }
+
+    #[test]
+    fn writes_to_zero_are_ignored() {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let instruction = r_type(FUNCTION_ADDU, 0, REG_A1, REG_A1);
+        execute(&mut processor, &[(REG_A1, 1)], instruction).unwrap();
+
+        assert_eq!(processor.registers.get(0), 0);
+    }
+
+    /// Jump to $a1 with jr or jalr (linking to $a0)
+    fn jump(function: u8, enable_delay_slots: bool) -> Processor {
+        let config = Config {
+            enable_delay_slots,
+            ..Config::default()
+        };
+        let (mut processor, _) = processor(config, "", &[0; 4], &[]);
+        let instruction = r_type(function, REG_A0, REG_A1, 0);
+        execute(&mut processor, &[(REG_A1, TEXT_OFFSET + 12)], instruction).unwrap();
+
+        processor
+    }
+
+    #[test]
+    fn jump_register() {
+        let processor = jump(FUNCTION_JR, false);
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 12);
+        assert_eq!(processor.registers.get(REG_A0), 0);
+
+        let processor = jump(FUNCTION_JALR, false);
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 12);
+        assert_eq!(processor.registers.get(REG_A0), TEXT_OFFSET + 4);
+
+        // The delay slot is executed before the jump, and skipped on return
+        let processor = jump(FUNCTION_JALR, true);
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 4);
+        assert_eq!(processor.next_program_counter, TEXT_OFFSET + 12);
+        assert_eq!(processor.registers.get(REG_A0), TEXT_OFFSET + 8);
+    }
 }
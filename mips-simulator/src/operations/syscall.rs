@@ -1,95 +1,164 @@
+use crate::error::ExecutionErrorCause;
+use crate::memory_map::Access;
+use crate::random::Random;
 use crate::Processor;
 use mips_types::constants::*;
+use std::ffi::CString;
+use std::fmt::{Display, LowerExp};
 use std::io;
 use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The most bytes copied between memory and a file at once, so a large read
+/// or write doesn't need a large buffer
+const FILE_CHUNK_SIZE: u32 = 0x10000;
 
 impl Processor {
     /// Handle a syscall operation
-    pub(crate) fn op_syscall(&mut self) {
+    pub(crate) fn op_syscall(&mut self) -> Result<(), ExecutionErrorCause> {
         match self.registers.get(REG_V0) {
-            SYSCALL_PRINT_INT => self.syscall_print_int(),
-            SYSCALL_PRINT_STR => self.syscall_print_str(),
-            SYSCALL_READ_INT => self.syscall_read_int(),
-            SYSCALL_READ_STRING => self.syscall_read_str(),
+            SYSCALL_PRINT_INT => self.syscall_print_int()?,
+            SYSCALL_PRINT_FLOAT => self.syscall_print_float()?,
+            SYSCALL_PRINT_DOUBLE => self.syscall_print_double()?,
+            SYSCALL_PRINT_STR => self.syscall_print_str()?,
+            SYSCALL_READ_INT => self.syscall_read_int()?,
+            SYSCALL_READ_FLOAT => self.syscall_read_float()?,
+            SYSCALL_READ_DOUBLE => self.syscall_read_double()?,
+            SYSCALL_READ_STRING => self.syscall_read_str()?,
+            SYSCALL_SBRK => self.syscall_sbrk(),
             SYSCALL_EXIT => self.syscall_exit(),
+            SYSCALL_PRINT_CHAR => self.syscall_print_char()?,
+            SYSCALL_READ_CHAR => self.syscall_read_char()?,
+            SYSCALL_OPEN => self.syscall_open()?,
+            SYSCALL_READ => self.syscall_read()?,
+            SYSCALL_WRITE => self.syscall_write()?,
+            SYSCALL_CLOSE => self.syscall_close(),
             SYSCALL_EXIT2 => self.syscall_exit2(),
-            operation => panic!("Unknown syscall operation {}", operation),
+            SYSCALL_TIME => self.syscall_time(),
+            SYSCALL_SLEEP => self.syscall_sleep(),
+            SYSCALL_PRINT_HEX => self.syscall_print_hex()?,
+            SYSCALL_PRINT_BINARY => self.syscall_print_binary()?,
+            SYSCALL_PRINT_UNSIGNED => self.syscall_print_unsigned()?,
+            SYSCALL_SET_SEED => self.syscall_set_seed(),
+            SYSCALL_RANDOM_INT => self.syscall_random_int(),
+            SYSCALL_RANDOM_INT_RANGE => self.syscall_random_int_range()?,
+            operation => return Err(ExecutionErrorCause::UnknownSyscall(operation)),
         }
 
         self.advance_program_counter();
+        Ok(())
     }
 
     /// Print an integer
-    fn syscall_print_int(&mut self) {
+    fn syscall_print_int(&mut self) -> Result<(), ExecutionErrorCause> {
         trace!("PRINT_INT");
         let value = self.registers.get(REG_A0) as i32;
-        Self::print(value.to_string().as_bytes());
+        self.print(value.to_string().as_bytes())
+    }
+
+    /// Print a single precision float from $f12
+    fn syscall_print_float(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_FLOAT");
+        let value = self.cp1.get_single(REG_F12);
+        self.print(float_string(value).as_bytes())
+    }
+
+    /// Print a double precision float from $f12 (and $f13)
+    fn syscall_print_double(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_DOUBLE");
+        let value = self.cp1.get_double(REG_F12);
+        self.print(float_string(value).as_bytes())
     }
 
     /// Print a string
-    fn syscall_print_str(&mut self) {
+    fn syscall_print_str(&mut self) -> Result<(), ExecutionErrorCause> {
         trace!("PRINT_STR");
         let str_address = self.registers.get(REG_A0);
-        let input_str = self.memory.get_str(str_address);
+        let input_str = self.read_str(str_address)?;
 
-        Self::print(input_str.as_bytes());
+        self.print(input_str.as_bytes())
     }
 
-    /// Read an integer from stdin
-    fn syscall_read_int(&mut self) {
+    /// Read an integer from the input stream
+    fn syscall_read_int(&mut self) -> Result<(), ExecutionErrorCause> {
         trace!("READ_INT");
-        let mut buffer = String::new();
-        io::stdin()
-            .read_line(&mut buffer)
-            .expect("Reading from stdin failed");
+        let buffer = self.streams.read_line()?;
 
-        let value = buffer
-            .trim()
+        let input = buffer.trim();
+        let value = input
             .parse::<i32>()
-            .expect("Input was not an integer");
+            .map_err(|_| ExecutionErrorCause::InvalidIntegerInput(input.to_string()))?;
         self.registers.set(REG_V0, value as u32);
+        Ok(())
+    }
+
+    /// Read a single precision float from the input stream into $f0
+    fn syscall_read_float(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("READ_FLOAT");
+        let value = self.read_float_line()?;
+        self.cp1.set_single(REG_F0, value as f32);
+        Ok(())
+    }
+
+    /// Read a double precision float from the input stream into $f0 (and $f1)
+    fn syscall_read_double(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("READ_DOUBLE");
+        let value = self.read_float_line()?;
+        self.cp1.set_double(REG_F0, value);
+        Ok(())
     }
 
-    /// Read a string from stdin
-    fn syscall_read_str(&mut self) {
+    /// Read a line from the input stream and parse it as a float
+    fn read_float_line(&mut self) -> Result<f64, ExecutionErrorCause> {
+        let buffer = self.streams.read_line()?;
+
+        let input = buffer.trim();
+        input
+            .parse::<f64>()
+            .map_err(|_| ExecutionErrorCause::InvalidFloatInput(input.to_string()))
+    }
+
+    /// Read a string from the input stream
+    fn syscall_read_str(&mut self) -> Result<(), ExecutionErrorCause> {
         trace!("READ_STR");
         let output_address = self.registers.get(REG_A0);
         let max_length = self.registers.get(REG_A1);
 
         if max_length == 0 {
-            return;
+            return Ok(());
         }
 
-        // Set up the byte stream
-        let stdin = io::stdin();
-        let stdin = stdin.lock();
-        let mut found_newline = false;
-        let bytes = stdin
-            .bytes()
-            // Reserve the last byte for the null byte
-            .take(max_length as usize - 1)
-            // Stop if we encounter a newline
-            .take_while(|b| {
-                found_newline = b.as_ref().map(|b| *b == b'\n').unwrap_or(false);
-                !found_newline
-            });
-
-        // Write the bytes to memory
+        self.check_access(output_address, max_length, Access::Write)?;
+
+        // Read until a newline, reserving the last byte for the null byte. The
+        // input is read one byte at a time so nothing past the line is
+        // consumed.
         let mut length = 0;
-        for (i, byte) in bytes.enumerate() {
-            let byte = byte.expect("Failed to read from stdin");
-            self.memory.set(output_address + i as u32, byte);
+        let mut byte = [0];
+        while length < max_length - 1 && self.streams.input.read(&mut byte)? != 0 {
+            self.memory
+                .set(output_address.wrapping_add(length), byte[0]);
             length += 1;
-        }
 
-        // Add the newline if we found one
-        if found_newline {
-            self.memory.set(output_address + length, b'\n');
-            length += 1;
+            if byte[0] == b'\n' {
+                break;
+            }
         }
 
         // Add the null byte
-        self.memory.set(output_address + length, 0);
+        self.memory.set(output_address.wrapping_add(length), 0);
+        Ok(())
+    }
+
+    /// Allocate heap memory by moving the program break. The address of the
+    /// allocated memory is returned, or -1 if the heap is exhausted.
+    fn syscall_sbrk(&mut self) {
+        trace!("SBRK");
+        // Keep the break word aligned
+        let increment = (self.registers.get(REG_A0) as i32).saturating_add(3) & !3;
+        let address = self.move_program_break(increment).unwrap_or(u32::MAX);
+        self.registers.set(REG_V0, address);
     }
 
     /// Exit
@@ -100,6 +169,160 @@ impl Processor {
         trace!("Exit with code 0");
     }
 
+    /// Print a character
+    fn syscall_print_char(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_CHAR");
+        let value = self.registers.get(REG_A0) as u8;
+        self.print(&[value])
+    }
+
+    /// Read a character from the input stream. At the end of the input, -1 is
+    /// returned.
+    fn syscall_read_char(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("READ_CHAR");
+        let mut buffer = [0];
+        let value = match self.streams.input.read(&mut buffer)? {
+            0 => u32::MAX,
+            _ => buffer[0] as u32,
+        };
+
+        self.registers.set(REG_V0, value);
+        Ok(())
+    }
+
+    /// Open a file in the sandbox. The file descriptor is returned, or -1 if
+    /// the file could not be opened.
+    fn syscall_open(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("OPEN");
+        let path_address = self.registers.get(REG_A0);
+        let flags = self.registers.get(REG_A1);
+        let path = self.read_str(path_address)?;
+
+        let result = self.files.open(&path.to_string_lossy(), flags);
+        self.set_file_result(result);
+        Ok(())
+    }
+
+    /// Read from a file into a buffer. The number of bytes read is returned,
+    /// or -1 if there was an error.
+    fn syscall_read(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("READ");
+        let fd = self.registers.get(REG_A0);
+        let buffer_address = self.registers.get(REG_A1);
+        let length = self.registers.get(REG_A2);
+        self.check_access(buffer_address, length, Access::Write)?;
+
+        // Read until the buffer is full or a read comes up short (ex. the end
+        // of the file, or a line from stdin)
+        let mut buffer = vec![0; length.min(FILE_CHUNK_SIZE) as usize];
+        let mut total = 0;
+        let result = loop {
+            let chunk_size = (length - total).min(FILE_CHUNK_SIZE) as usize;
+            if chunk_size == 0 {
+                break Ok(total);
+            }
+
+            match self
+                .files
+                .read(fd, &mut buffer[..chunk_size], &mut self.streams)
+            {
+                Ok(count) => {
+                    self.memory
+                        .load_into_memory(&buffer[..count], buffer_address.wrapping_add(total));
+                    total += count as u32;
+
+                    if count < chunk_size {
+                        break Ok(total);
+                    }
+                }
+                Err(e) if total == 0 => break Err(e),
+                Err(_) => break Ok(total),
+            }
+        };
+        self.set_file_result(result);
+        Ok(())
+    }
+
+    /// Write a buffer to a file. The number of bytes written is returned, or
+    /// -1 if there was an error.
+    fn syscall_write(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("WRITE");
+        let fd = self.registers.get(REG_A0);
+        let buffer_address = self.registers.get(REG_A1);
+        let length = self.registers.get(REG_A2);
+        self.check_access(buffer_address, length, Access::Read)?;
+
+        let mut total = 0;
+        let result = loop {
+            let chunk_size = (length - total).min(FILE_CHUNK_SIZE);
+            if chunk_size == 0 {
+                break Ok(total);
+            }
+
+            let chunk_address = buffer_address.wrapping_add(total);
+            let buffer: Vec<u8> = (0..chunk_size)
+                .map(|i| self.memory.get(chunk_address.wrapping_add(i)))
+                .collect();
+            match self.files.write(fd, &buffer, &mut self.streams) {
+                Ok(count) => {
+                    total += count as u32;
+
+                    if count < buffer.len() {
+                        break Ok(total);
+                    }
+                }
+                Err(e) if total == 0 => break Err(e),
+                Err(_) => break Ok(total),
+            }
+        };
+        self.set_file_result(result);
+        Ok(())
+    }
+
+    /// Read the null-terminated string at the address. Each byte's access is
+    /// checked before it is read, so nothing past the readable memory is read
+    /// (ex. a mapped device's registers).
+    fn read_str(&mut self, address: u32) -> Result<CString, ExecutionErrorCause> {
+        let mut bytes = Vec::new();
+
+        loop {
+            let byte_address = address.wrapping_add(bytes.len() as u32);
+            self.check_permissions(byte_address, 1, Access::Read)?;
+
+            match self.memory.get(byte_address) {
+                0 => break,
+                byte => bytes.push(byte),
+            }
+        }
+
+        // Include the null byte
+        if self.memory_accesses.is_some() {
+            self.record_memory_access(address, bytes.len() as u32 + 1, Access::Read);
+        }
+
+        Ok(CString::new(bytes).unwrap())
+    }
+
+    /// Close a file
+    fn syscall_close(&mut self) {
+        trace!("CLOSE");
+        let fd = self.registers.get(REG_A0);
+
+        if let Err(e) = self.files.close(fd) {
+            debug!("Failed to close file descriptor {}: {}", fd, e);
+        }
+    }
+
+    /// Return the result of a file syscall, or -1 if it failed
+    fn set_file_result(&mut self, result: io::Result<u32>) {
+        let value = result.unwrap_or_else(|e| {
+            debug!("File syscall failed: {}", e);
+            u32::MAX
+        });
+
+        self.registers.set(REG_V0, value);
+    }
+
     /// Exit with a code
     fn syscall_exit2(&mut self) {
         trace!("EXIT2");
@@ -108,11 +331,367 @@ impl Processor {
         trace!("Exit with code {}", self.return_code);
     }
 
-    /// Print a value to stdout
-    fn print(value: &[u8]) {
-        let stdout = io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(value).expect("Writing to stdout failed");
-        handle.flush().expect("Flushing stdout failed");
+    /// Get the system time in milliseconds since the Unix epoch. The low bits
+    /// are returned in $a0 and the high bits in $a1.
+    fn syscall_time(&mut self) {
+        trace!("TIME");
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        self.registers.set(REG_A0, time as u32);
+        self.registers.set(REG_A1, (time >> 32) as u32);
+    }
+
+    /// Sleep for some milliseconds. The sleep is cut short by the timeout, if
+    /// there is one.
+    fn syscall_sleep(&mut self) {
+        trace!("SLEEP");
+        let milliseconds = self.registers.get(REG_A0);
+        let mut duration = Duration::from_millis(milliseconds as u64);
+
+        if let Some(remaining_time) = self.remaining_time() {
+            if duration >= remaining_time {
+                duration = remaining_time;
+                self.timed_out = true;
+            }
+        }
+
+        thread::sleep(duration);
+    }
+
+    /// Print an integer in hexadecimal
+    fn syscall_print_hex(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_HEX");
+        let value = self.registers.get(REG_A0);
+        self.print(format!("0x{:08x}", value).as_bytes())
+    }
+
+    /// Print an integer in binary
+    fn syscall_print_binary(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_BINARY");
+        let value = self.registers.get(REG_A0);
+        self.print(format!("{:032b}", value).as_bytes())
+    }
+
+    /// Print an unsigned integer
+    fn syscall_print_unsigned(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("PRINT_UNSIGNED");
+        let value = self.registers.get(REG_A0);
+        self.print(value.to_string().as_bytes())
+    }
+
+    /// Seed a random number generator
+    fn syscall_set_seed(&mut self) {
+        trace!("SET_SEED");
+        let id = self.registers.get(REG_A0);
+        // The seed is sign extended, like in MARS
+        let seed = self.registers.get(REG_A1) as i32 as u64;
+        self.random_generators.insert(id, Random::new(seed));
+    }
+
+    /// Generate a random integer
+    fn syscall_random_int(&mut self) {
+        trace!("RANDOM_INT");
+        let id = self.registers.get(REG_A0);
+        let value = self.random_generator(id).next_int();
+        self.registers.set(REG_A0, value as u32);
+    }
+
+    /// Generate a random integer in the range `[0, $a1)`
+    fn syscall_random_int_range(&mut self) -> Result<(), ExecutionErrorCause> {
+        trace!("RANDOM_INT_RANGE");
+        let id = self.registers.get(REG_A0);
+        let bound = self.registers.get(REG_A1) as i32;
+
+        if bound <= 0 {
+            return Err(ExecutionErrorCause::InvalidRandomBound(bound));
+        }
+
+        let value = self.random_generator(id).next_int_bounded(bound);
+        self.registers.set(REG_A0, value as u32);
+        Ok(())
+    }
+
+    /// Print a value to the output stream
+    fn print(&mut self, value: &[u8]) -> Result<(), ExecutionErrorCause> {
+        self.streams.output.write_all(value)?;
+        self.streams.output.flush()?;
+        Ok(())
+    }
+}
+
+/// Format a float the way MARS prints it (Java's `Float.toString` and
+/// `Double.toString`): the shortest digits which read back as the same value,
+/// with at least one digit after the decimal point. Values below 10^-3 or at
+/// least 10^7 use scientific notation, ex. `1.0E20`.
+fn float_string<T: Copy + Display + LowerExp + Into<f64>>(value: T) -> String {
+    let magnitude = value.into().abs();
+    if magnitude.is_nan() {
+        return "NaN".to_string();
+    }
+    if magnitude.is_infinite() {
+        let sign = if value.into() < 0.0 { "-" } else { "" };
+        return format!("{}Infinity", sign);
+    }
+
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        let mut string = value.to_string();
+        if !string.contains('.') {
+            string.push_str(".0");
+        }
+        return string;
+    }
+
+    let string = format!("{:e}", value);
+    let (mantissa, exponent) = string.split_at(string.find('e').unwrap());
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    format!("{}{}E{}", mantissa, point, &exponent[1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::float_string;
+    use crate::config::Config;
+    use crate::error::ExecutionErrorCause;
+    use crate::memory_map::Access;
+    use crate::processor::StepOutcome;
+    use crate::test_util::{exit, li, processor, r_type, run, SYSCALL};
+    use mips_types::constants::*;
+
+    /// The register which the syscall's result is copied to
+    const RESULT: u8 = 16;
+
+    /// Call the syscall with the arguments in $a0-$a2, copy the result to
+    /// `RESULT`, then exit
+    fn syscall(operation: u32, arguments: &[u32]) -> Vec<u32> {
+        let mut text = Vec::new();
+        for (register, value) in [REG_A0, REG_A1, REG_A2].iter().zip(arguments) {
+            text.extend(li(*register, *value));
+        }
+        text.extend(li(REG_V0, operation));
+        text.push(SYSCALL);
+        text.push(r_type(FUNCTION_OR, RESULT, REG_V0, 0));
+        text.extend(exit());
+        text
+    }
+
+    #[test]
+    fn print_str() {
+        let text = syscall(SYSCALL_PRINT_STR, &[DATA_OFFSET]);
+        let (mut processor, output) = processor(Config::default(), "", &text, b"hello\0");
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(output.contents(), b"hello");
+    }
+
+    #[test]
+    fn print_integers() {
+        for (operation, value, expected) in [
+            (SYSCALL_PRINT_INT, (-5i32) as u32, "-5"),
+            (SYSCALL_PRINT_UNSIGNED, (-5i32) as u32, "4294967291"),
+            (SYSCALL_PRINT_HEX, 0xab, "0x000000ab"),
+            (SYSCALL_PRINT_BINARY, 5, "00000000000000000000000000000101"),
+            (SYSCALL_PRINT_CHAR, 0x141, "A"),
+        ] {
+            let text = syscall(operation, &[value]);
+            let (mut processor, output) = processor(Config::default(), "", &text, &[]);
+
+            assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+            assert_eq!(output.contents(), expected.as_bytes(), "{}", operation);
+        }
+    }
+
+    #[test]
+    fn format_floats() {
+        for (value, expected) in [
+            (1.0, "1.0"),
+            (-1.5, "-1.5"),
+            (0.1, "0.1"),
+            (100.0, "100.0"),
+            (0.001, "0.001"),
+            (1234567.0, "1234567.0"),
+            (1e7, "1.0E7"),
+            (1e20, "1.0E20"),
+            (-2.5e-4, "-2.5E-4"),
+            (0.0, "0.0"),
+            (-0.0, "-0.0"),
+            (f64::INFINITY, "Infinity"),
+            (f64::NEG_INFINITY, "-Infinity"),
+            (f64::NAN, "NaN"),
+        ] {
+            assert_eq!(float_string(value), expected);
+        }
+
+        // Singles use the shortest digits for single precision
+        assert_eq!(float_string(0.1f32), "0.1");
+        assert_eq!(float_string(3.4028235e38f32), "3.4028235E38");
+    }
+
+    #[test]
+    fn print_floats() {
+        for operation in [SYSCALL_PRINT_FLOAT, SYSCALL_PRINT_DOUBLE] {
+            let text = syscall(operation, &[]);
+            let (mut processor, output) = processor(Config::default(), "", &text, &[]);
+            if operation == SYSCALL_PRINT_FLOAT {
+                processor.cp1.set_single(REG_F12, 1e20);
+            } else {
+                processor.cp1.set_double(REG_F12, 1e20);
+            }
+
+            assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+            assert_eq!(output.contents(), b"1.0E20");
+        }
+    }
+
+    #[test]
+    fn read_int_only_reads_one_line() {
+        let mut text = syscall(SYSCALL_READ_INT, &[]);
+        // Read the next line, without copying the result over the integer
+        let mut read_str = syscall(SYSCALL_READ_STRING, &[DATA_OFFSET, 8]);
+        read_str.remove(read_str.len() - 4);
+        text.splice(text.len() - 3.., read_str);
+        let (mut processor, _) = processor(Config::default(), " -42 \nabc\n", &text, &[0; 8]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), (-42i32) as u32);
+        assert_eq!(processor.read_memory(DATA_OFFSET, 5), b"abc\n\0");
+    }
+
+    #[test]
+    fn invalid_integer_input() {
+        let text = syscall(SYSCALL_READ_INT, &[]);
+        let (mut processor, _) = processor(Config::default(), "12a\n", &text, &[]);
+
+        let error = run(&mut processor).unwrap_err();
+        assert!(matches!(
+            error.cause,
+            ExecutionErrorCause::InvalidIntegerInput(input) if input == "12a"
+        ));
+    }
+
+    #[test]
+    fn read_floats() {
+        for operation in [SYSCALL_READ_FLOAT, SYSCALL_READ_DOUBLE] {
+            let text = syscall(operation, &[]);
+            let (mut processor, _) = processor(Config::default(), "0.1\n", &text, &[]);
+
+            assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+            if operation == SYSCALL_READ_FLOAT {
+                assert_eq!(processor.cp1.get_single(REG_F0), 0.1);
+            } else {
+                assert_eq!(processor.cp1.get_double(REG_F0), 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn read_char_at_the_end_of_the_input() {
+        for (input, expected) in [("xy", b'y' as u32), ("x", u32::MAX)] {
+            let mut text = syscall(SYSCALL_READ_CHAR, &[]);
+            text.splice(text.len() - 3.., syscall(SYSCALL_READ_CHAR, &[]));
+            let (mut processor, _) = processor(Config::default(), input, &text, &[]);
+
+            assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+            assert_eq!(processor.registers.get(RESULT), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn print_str_stops_at_unmapped_memory() {
+        let text = syscall(SYSCALL_PRINT_STR, &[DATA_OFFSET]);
+        let (mut processor, output) = processor(Config::default(), "", &text, b"abc");
+
+        let error = run(&mut processor).unwrap_err();
+        assert!(matches!(
+            error.cause,
+            ExecutionErrorCause::UnmappedAddress { address, .. } if address == DATA_OFFSET + 3
+        ));
+        assert!(output.contents().is_empty());
+    }
+
+    #[test]
+    fn read_str_wraps_around_memory() {
+        let config = Config {
+            disable_memory_protection: true,
+            ..Config::default()
+        };
+        let text = syscall(SYSCALL_READ_STRING, &[0xffff_fffe, 8]);
+        let (mut processor, _) = processor(config, "abcdefgh\n", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.read_memory(0xffff_fffe, 8), b"abcdefg\0");
+    }
+
+    #[test]
+    fn read_with_a_huge_length() {
+        let config = Config {
+            disable_memory_protection: true,
+            ..Config::default()
+        };
+        let text = syscall(SYSCALL_READ, &[0, DATA_OFFSET, u32::MAX]);
+        let (mut processor, _) = processor(config, "hello\n", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), 6);
+        assert_eq!(processor.read_memory(DATA_OFFSET, 6), b"hello\n");
+    }
+
+    #[test]
+    fn files_are_disabled_without_a_sandbox() {
+        let text = syscall(SYSCALL_OPEN, &[DATA_OFFSET, 1]);
+        let (mut processor, _) = processor(Config::default(), "", &text, b"file.txt\0");
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), u32::MAX);
+    }
+
+    #[test]
+    fn sbrk() {
+        let text = syscall(SYSCALL_SBRK, &[5]);
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 4]);
+        let heap_start = DATA_OFFSET + 8;
+        assert!(processor
+            .check_permissions(heap_start, 1, Access::Write)
+            .is_err());
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), heap_start);
+        // The break is kept word aligned
+        assert_eq!(processor.program_break, heap_start + 8);
+        assert!(processor
+            .check_permissions(heap_start, 8, Access::Write)
+            .is_ok());
+        assert!(processor
+            .check_permissions(heap_start + 8, 1, Access::Write)
+            .is_err());
+    }
+
+    #[test]
+    fn sbrk_can_not_shrink_past_the_start() {
+        let text = syscall(SYSCALL_SBRK, &[(-4i32) as u32]);
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 4]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), u32::MAX);
+        assert_eq!(processor.program_break, DATA_OFFSET + 8);
+    }
+
+    #[test]
+    fn sbrk_heap_limit() {
+        let config = Config {
+            heap_limit: Some(16),
+            ..Config::default()
+        };
+        let mut text = syscall(SYSCALL_SBRK, &[16]);
+        // Grow the heap again before exiting
+        let grow_again = syscall(SYSCALL_SBRK, &[4]);
+        text.splice(text.len() - 3.., grow_again);
+        let (mut processor, _) = processor(config, "", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(RESULT), u32::MAX);
+        assert_eq!(processor.program_break, DATA_OFFSET + 16);
     }
 }
@@ -0,0 +1,665 @@
+//! A timing model of the classic five-stage pipeline (IF, ID, EX, MEM, WB).
+//!
+//! Instructions are still executed one at a time, and the model works out
+//! when each executed instruction would have been in each stage:
+//! - Instructions are fetched sequentially, so taken branches and jumps flush
+//!   the instructions fetched after them (or after their delay slot) until
+//!   they are resolved. Jumps are resolved in ID, and branches in the
+//!   configured stage.
+//! - An instruction waits in ID until its operands can be read from the
+//!   register file (written in the first half of WB, read in the second half
+//!   of ID) or forwarded from the EX/MEM or MEM/WB pipeline registers. Loads
+//!   produce their value at the end of MEM, and other instructions at the end
+//!   of EX.
+//! - Branches resolved in ID and `jr`/`jalr` need their operands in ID. Other
+//!   instructions need them in EX.
+
+use crate::instruction::{Instruction, Operands, OPERAND_REGISTER_COUNT};
+use crate::processor::Processor;
+use crate::stats::InstructionClass;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The stage where branches are resolved
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BranchStage {
+    Decode,
+    Execute,
+    Memory,
+}
+
+impl FromStr for BranchStage {
+    type Err = String;
+
+    fn from_str(stage: &str) -> Result<Self, Self::Err> {
+        match stage.to_lowercase().as_str() {
+            "id" => Ok(BranchStage::Decode),
+            "ex" => Ok(BranchStage::Execute),
+            "mem" => Ok(BranchStage::Memory),
+            _ => Err(format!("Unknown stage {} (expected id, ex, or mem)", stage)),
+        }
+    }
+}
+
+impl Display for BranchStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BranchStage::Decode => "ID",
+            BranchStage::Execute => "EX",
+            BranchStage::Memory => "MEM",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// The config of the pipeline timing model
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PipelineConfig {
+    /// Forward results from the EX/MEM pipeline register (ex. to an ALU
+    /// instruction which uses the previous instruction's result)
+    pub forward_ex_mem: bool,
+    /// Forward results from the MEM/WB pipeline register (ex. loaded values)
+    pub forward_mem_wb: bool,
+    /// The stage where conditional branches are resolved
+    pub branch_stage: BranchStage,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            forward_ex_mem: true,
+            forward_mem_wb: true,
+            branch_stage: BranchStage::Decode,
+        }
+    }
+}
+
+/// The cycles an instruction entered IF, ID, and EX. It is in MEM and WB in
+/// the two cycles after EX.
+#[derive(Copy, Clone, Debug)]
+struct Timing {
+    fetch: u64,
+    decode: u64,
+    execute: u64,
+}
+
+impl Timing {
+    fn memory(&self) -> u64 {
+        self.execute + 1
+    }
+
+    fn write_back(&self) -> u64 {
+        self.execute + 2
+    }
+}
+
+/// The last instruction which wrote a register
+#[derive(Copy, Clone, Debug)]
+struct Producer {
+    timing: Timing,
+    /// Loads produce their value in MEM instead of EX
+    load: bool,
+}
+
+/// The reason instructions were flushed after a control transfer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum ControlStall {
+    Branch,
+    Jump,
+    Exception,
+}
+
+/// A control transfer which the following instructions must wait for
+#[derive(Copy, Clone, Debug)]
+struct PendingControl {
+    /// The first cycle the target can be fetched in
+    fetch: u64,
+    cause: ControlStall,
+    /// The number of instructions (delay slots) to execute before the target
+    delay_slots: u8,
+}
+
+/// The state of the pipeline timing model
+pub(crate) struct Pipeline {
+    config: PipelineConfig,
+    /// The timing of the last instruction
+    last: Timing,
+    registers: [Option<Producer>; OPERAND_REGISTER_COUNT],
+    control: Option<PendingControl>,
+    report: PipelineReport,
+    diagram: Option<Diagram>,
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig, delay_slots: bool) -> Self {
+        Self {
+            config,
+            // The first instruction is fetched in cycle 1
+            last: Timing {
+                fetch: 0,
+                decode: 1,
+                execute: 2,
+            },
+            registers: [None; OPERAND_REGISTER_COUNT],
+            control: None,
+            report: PipelineReport {
+                config,
+                delay_slots,
+                ..PipelineReport::default()
+            },
+            diagram: None,
+        }
+    }
+
+    /// Add an executed instruction to the pipeline. `transferred` is true if
+    /// it was a taken branch or a jump, and `faulted` is true if it raised an
+    /// exception.
+    fn add(
+        &mut self,
+        program_counter: u32,
+        instruction: Instruction,
+        transferred: bool,
+        faulted: bool,
+    ) {
+        let class = InstructionClass::of(instruction);
+        let operands = instruction.operands();
+        let last = self.last;
+
+        // Fetch after the previous instruction moves to ID, and after any
+        // control transfer is resolved
+        let mut fetch = (last.fetch + 1).max(last.decode);
+        let mut control_cause = None;
+        if let Some(control) = &mut self.control {
+            if control.delay_slots > 0 {
+                control.delay_slots -= 1;
+            } else {
+                fetch = fetch.max(control.fetch);
+                control_cause = Some(control.cause);
+                self.control = None;
+            }
+        }
+
+        let decode = (fetch + 1).max(last.execute);
+        let earliest_execute = (decode + 1).max(last.execute + 1);
+        if let Some(cause) = control_cause {
+            let stalls = earliest_execute - (last.execute + 1);
+            match cause {
+                ControlStall::Branch => self.report.branch_stalls += stalls,
+                ControlStall::Jump => self.report.jump_stalls += stalls,
+                ControlStall::Exception => self.report.exception_stalls += stalls,
+            }
+        }
+
+        // Wait in ID for the operands
+        let decode_use = match class {
+            InstructionClass::Jump => true,
+            InstructionClass::Branch => self.config.branch_stage == BranchStage::Decode,
+            _ => false,
+        };
+        // Forwarding is only possible in certain cycles, so waiting for one
+        // operand can make another operand wait longer
+        let producers: Vec<Producer> = Operands::registers(operands.sources)
+            .filter_map(|register| self.registers[register as usize])
+            .collect();
+        let mut execute = earliest_execute;
+        let mut load_use = false;
+        loop {
+            let mut next_execute = execute;
+            for producer in &producers {
+                let ready = self.operand_ready(*producer, execute, decode_use);
+                if ready > next_execute {
+                    next_execute = ready;
+                    load_use = producer.load;
+                }
+            }
+
+            if next_execute == execute {
+                break;
+            }
+            execute = next_execute;
+        }
+
+        let stalls = execute - earliest_execute;
+        if load_use {
+            self.report.load_use_stalls += stalls;
+        } else {
+            self.report.data_stalls += stalls;
+        }
+
+        let timing = Timing {
+            fetch,
+            decode,
+            execute,
+        };
+        let producer = Producer {
+            timing,
+            load: class == InstructionClass::Load,
+        };
+        for register in Operands::registers(operands.destinations) {
+            self.registers[register as usize] = Some(producer);
+        }
+
+        // Taken branches and jumps flush the instructions fetched until they
+        // are resolved (except for their delay slot). Exceptions are taken in
+        // MEM.
+        let control = if faulted {
+            Some((timing.memory() + 1, ControlStall::Exception))
+        } else if transferred && class == InstructionClass::Jump {
+            Some((timing.execute, ControlStall::Jump))
+        } else if transferred && class == InstructionClass::Branch {
+            let resolved = match self.config.branch_stage {
+                BranchStage::Decode => timing.execute - 1,
+                BranchStage::Execute => timing.execute,
+                BranchStage::Memory => timing.memory(),
+            };
+            Some((resolved + 1, ControlStall::Branch))
+        } else {
+            None
+        };
+        if let Some((fetch, cause)) = control {
+            let delay_slots = match cause {
+                ControlStall::Exception => 0,
+                _ if self.report.delay_slots => 1,
+                _ => 0,
+            };
+            self.control = Some(PendingControl {
+                fetch,
+                cause,
+                delay_slots,
+            });
+        }
+
+        self.last = timing;
+        self.report.instructions += 1;
+        self.report.cycles = timing.write_back();
+
+        if let Some(diagram) = &mut self.diagram {
+            diagram.add(program_counter, timing);
+        }
+    }
+
+    /// Get the first cycle (starting from `earliest_execute`) an instruction
+    /// could enter EX in with the producer's value. If the value is used in
+    /// ID, it must be available in the instruction's last ID cycle.
+    fn operand_ready(&self, producer: Producer, earliest_execute: u64, decode_use: bool) -> u64 {
+        let timing = producer.timing;
+        // The cycle the value is needed in
+        let needed = if decode_use {
+            earliest_execute - 1
+        } else {
+            earliest_execute
+        };
+
+        // The cycles the value can be forwarded in, and the first cycle it
+        // can be read from the register file
+        let ex_mem = (self.config.forward_ex_mem && !producer.load).then(|| timing.memory());
+        let mem_wb = self.config.forward_mem_wb.then(|| timing.write_back());
+        let register_file = if decode_use {
+            timing.write_back()
+        } else {
+            timing.write_back() + 1
+        };
+
+        let ready = if needed >= register_file || ex_mem == Some(needed) || mem_wb == Some(needed) {
+            needed
+        } else {
+            [ex_mem, mem_wb, Some(register_file)]
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|cycle| *cycle >= needed)
+                .min()
+                .unwrap_or(register_file)
+        };
+
+        if decode_use {
+            ready + 1
+        } else {
+            ready
+        }
+    }
+}
+
+impl Debug for Pipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("config", &self.config)
+            .field("last", &self.last)
+            .field("control", &self.control)
+            .field("report", &self.report)
+            .finish()
+    }
+}
+
+/// A per-cycle diagram of the instructions in each stage
+struct Diagram {
+    output: Box<dyn Write>,
+    /// The next cycle to write
+    cycle: u64,
+    /// The instructions which may still be in the pipeline
+    instructions: VecDeque<(u32, Timing)>,
+}
+
+impl Diagram {
+    fn new(mut output: Box<dyn Write>) -> io::Result<Self> {
+        writeln!(
+            output,
+            "{:>8}  {:<10}  {:<10}  {:<10}  {:<10}  {:<10}",
+            "cycle", "IF", "ID", "EX", "MEM", "WB"
+        )?;
+
+        Ok(Self {
+            output,
+            cycle: 1,
+            instructions: VecDeque::new(),
+        })
+    }
+
+    /// Add an instruction, and write the cycles which can't change anymore
+    /// (later instructions are always fetched after this one)
+    fn add(&mut self, program_counter: u32, timing: Timing) {
+        self.instructions.push_back((program_counter, timing));
+
+        if let Err(e) = self.write_until(timing.fetch) {
+            warn!("Could not write the pipeline diagram: {}", e);
+        }
+    }
+
+    /// Write the cycles up to and including the last cycle
+    fn write_until(&mut self, last_cycle: u64) -> io::Result<()> {
+        while self.cycle <= last_cycle {
+            let cycle = self.cycle;
+            let stage = |in_stage: fn(&Timing, u64) -> bool| {
+                self.instructions
+                    .iter()
+                    .find(|(_, timing)| in_stage(timing, cycle))
+                    .map(|(address, _)| format!("0x{:08x}", address))
+                    .unwrap_or_else(|| "-".to_string())
+            };
+
+            writeln!(
+                self.output,
+                "{:>8}  {:<10}  {:<10}  {:<10}  {:<10}  {:<10}",
+                cycle,
+                stage(|timing, cycle| timing.fetch <= cycle && cycle < timing.decode),
+                stage(|timing, cycle| timing.decode <= cycle && cycle < timing.execute),
+                stage(|timing, cycle| timing.execute == cycle),
+                stage(|timing, cycle| timing.memory() == cycle),
+                stage(|timing, cycle| timing.write_back() == cycle),
+            )?;
+
+            self.cycle += 1;
+            while self
+                .instructions
+                .front()
+                .is_some_and(|(_, timing)| timing.write_back() < self.cycle)
+            {
+                self.instructions.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Processor {
+    /// Write a diagram of the instructions in each pipeline stage, one line
+    /// per cycle, to the output. Does nothing if the pipeline timing model is
+    /// disabled.
+    pub fn set_pipeline_diagram(&mut self, output: Box<dyn Write>) -> io::Result<()> {
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.diagram = Some(Diagram::new(output)?);
+        }
+
+        Ok(())
+    }
+
+    /// Write the rest of the pipeline diagram (the cycles until the last
+    /// instruction leaves the pipeline)
+    pub fn finish_pipeline_diagram(&mut self) -> io::Result<()> {
+        if let Some(pipeline) = &mut self.pipeline {
+            let cycles = pipeline.report.cycles;
+            if let Some(diagram) = &mut pipeline.diagram {
+                diagram.write_until(cycles)?;
+                diagram.output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the last executed instruction to the pipeline timing model
+    pub(crate) fn record_pipeline(
+        &mut self,
+        program_counter: u32,
+        instruction: Instruction,
+        executed: bool,
+    ) {
+        let transferred = executed && self.control_transfer(program_counter).is_some();
+
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.add(program_counter, instruction, transferred, !executed);
+        }
+    }
+
+    /// Get the pipeline timing report, if the pipeline timing model is enabled
+    pub fn pipeline_report(&self) -> Option<PipelineReport> {
+        self.pipeline
+            .as_ref()
+            .map(|pipeline| pipeline.report.clone())
+    }
+}
+
+/// The cycles taken by the executed instructions in the pipeline
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReport {
+    pub config: PipelineConfig,
+    /// If branches and jumps have delay slots
+    pub delay_slots: bool,
+    pub instructions: u64,
+    /// The cycle the last instruction left the pipeline in
+    pub cycles: u64,
+    /// Cycles stalled in ID waiting for an operand from a non-load
+    /// instruction
+    pub data_stalls: u64,
+    /// Cycles stalled in ID waiting for an operand from a load
+    pub load_use_stalls: u64,
+    /// Cycles lost to instructions flushed after a taken branch
+    pub branch_stalls: u64,
+    /// Cycles lost to instructions flushed after a jump
+    pub jump_stalls: u64,
+    /// Cycles lost to instructions flushed after an exception
+    pub exception_stalls: u64,
+}
+
+impl PipelineReport {
+    /// Get the average number of cycles per instruction
+    pub fn cycles_per_instruction(&self) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            self.cycles as f64 / self.instructions as f64
+        }
+    }
+}
+
+impl Display for PipelineReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let forwarding = match (self.config.forward_ex_mem, self.config.forward_mem_wb) {
+            (true, true) => "EX/MEM and MEM/WB",
+            (true, false) => "EX/MEM",
+            (false, true) => "MEM/WB",
+            (false, false) => "none",
+        };
+
+        writeln!(
+            f,
+            "Pipeline: forwarding from {}, branches resolved in {}, {}",
+            forwarding,
+            self.config.branch_stage,
+            if self.delay_slots {
+                "delay slots"
+            } else {
+                "no delay slots"
+            }
+        )?;
+        writeln!(f, "Instructions: {}", self.instructions)?;
+        writeln!(f, "Cycles: {}", self.cycles)?;
+        writeln!(f, "CPI: {:.3}", self.cycles_per_instruction())?;
+        writeln!(f, "Stall cycles:")?;
+        writeln!(f, "  data hazards     {:>12}", self.data_stalls)?;
+        writeln!(f, "  load-use         {:>12}", self.load_use_stalls)?;
+        writeln!(f, "  taken branches   {:>12}", self.branch_stalls)?;
+        writeln!(f, "  jumps            {:>12}", self.jump_stalls)?;
+        writeln!(f, "  exceptions       {:>12}", self.exception_stalls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BranchStage, Pipeline, PipelineConfig, PipelineReport};
+    use crate::config::Config;
+    use crate::instruction::Instruction;
+    use crate::test_util::{exit, i_type, li, processor, r_type, run};
+    use mips_types::constants::*;
+
+    /// An instruction which uses $a1
+    fn addu() -> u32 {
+        r_type(FUNCTION_ADDU, REG_A0, REG_A1, REG_A1)
+    }
+
+    /// Run the instructions (and whether each transferred control) through
+    /// the pipeline
+    fn run_pipeline(
+        config: PipelineConfig,
+        delay_slots: bool,
+        instructions: &[(u32, bool)],
+    ) -> PipelineReport {
+        let mut pipeline = Pipeline::new(config, delay_slots);
+        for (i, (instruction, transferred)) in instructions.iter().enumerate() {
+            let program_counter = TEXT_OFFSET + 4 * i as u32;
+            pipeline.add(
+                program_counter,
+                Instruction(*instruction),
+                *transferred,
+                false,
+            );
+        }
+
+        pipeline.report
+    }
+
+    fn no_forwarding() -> PipelineConfig {
+        PipelineConfig {
+            forward_ex_mem: false,
+            forward_mem_wb: false,
+            ..PipelineConfig::default()
+        }
+    }
+
+    #[test]
+    fn independent_instructions() {
+        let instructions = [(i_type(OP_ORI, REG_A0, 0, 1), false); 4];
+        let report = run_pipeline(PipelineConfig::default(), false, &instructions);
+
+        assert_eq!(report.instructions, 4);
+        // The first instruction takes 5 cycles, then one finishes each cycle
+        assert_eq!(report.cycles, 8);
+    }
+
+    #[test]
+    fn data_hazards() {
+        let instructions = [(i_type(OP_ORI, REG_A1, 0, 1), false), (addu(), false)];
+
+        let report = run_pipeline(PipelineConfig::default(), false, &instructions);
+        assert_eq!(report.data_stalls, 0);
+        assert_eq!(report.cycles, 6);
+
+        // Without forwarding, the value is read from the register file after
+        // it is written in WB
+        let report = run_pipeline(no_forwarding(), false, &instructions);
+        assert_eq!(report.data_stalls, 2);
+        assert_eq!(report.cycles, 8);
+    }
+
+    #[test]
+    fn load_use_hazards() {
+        let instructions = [(i_type(OP_LW, REG_A1, REG_A0, 0), false), (addu(), false)];
+
+        let report = run_pipeline(PipelineConfig::default(), false, &instructions);
+        assert_eq!(report.load_use_stalls, 1);
+        assert_eq!(report.data_stalls, 0);
+
+        let report = run_pipeline(no_forwarding(), false, &instructions);
+        assert_eq!(report.load_use_stalls, 2);
+    }
+
+    #[test]
+    fn control_hazards() {
+        let instructions = [
+            (i_type(OP_BEQ, 0, 0, 4), true),
+            (i_type(OP_ORI, REG_A0, 0, 1), false),
+        ];
+        for (branch_stage, stalls) in [
+            (BranchStage::Decode, 1),
+            (BranchStage::Execute, 2),
+            (BranchStage::Memory, 3),
+        ] {
+            let config = PipelineConfig {
+                branch_stage,
+                ..PipelineConfig::default()
+            };
+            let report = run_pipeline(config, false, &instructions);
+            assert_eq!(report.branch_stalls, stalls, "{}", branch_stage);
+        }
+
+        // Branches which aren't taken don't stall
+        let not_taken = [(instructions[0].0, false), instructions[1]];
+        let report = run_pipeline(PipelineConfig::default(), false, &not_taken);
+        assert_eq!(report.branch_stalls, 0);
+
+        let jump = [
+            ((OP_J as u32) << 26, true),
+            (i_type(OP_ORI, REG_A0, 0, 1), false),
+        ];
+        let report = run_pipeline(PipelineConfig::default(), false, &jump);
+        assert_eq!(report.jump_stalls, 1);
+    }
+
+    #[test]
+    fn delay_slots_hide_control_hazards() {
+        let instructions = [
+            (i_type(OP_BEQ, 0, 0, 4), true),
+            (0, false),
+            (i_type(OP_ORI, REG_A0, 0, 1), false),
+        ];
+        let report = run_pipeline(PipelineConfig::default(), true, &instructions);
+
+        assert_eq!(report.branch_stalls, 0);
+        assert_eq!(report.cycles, 7);
+    }
+
+    #[test]
+    fn processor_pipeline() {
+        let config = Config {
+            pipeline: Some(PipelineConfig::default()),
+            ..Config::default()
+        };
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        text.push(addu());
+        text.extend(exit());
+        let (mut processor, _) = processor(config, "", &text, &[0; 4]);
+        run(&mut processor).unwrap();
+
+        let report = processor.pipeline_report().unwrap();
+        assert_eq!(report.instructions, text.len() as u64);
+        assert_eq!(report.load_use_stalls, 1);
+        assert_eq!(report.cycles, text.len() as u64 + 4 + 1);
+    }
+}
@@ -1,80 +1,787 @@
+use crate::branch_prediction::BranchPrediction;
+use crate::cache::Cache;
 use crate::config::Config;
+use crate::cp0::Coprocessor0;
+use crate::cp1::Coprocessor1;
+use crate::decode::{decode, DecodedInstruction};
+use crate::devices::{ConsoleDevice, Device, CONSOLE_ADDRESS, CONSOLE_SIZE};
+use crate::error::{ExecutionError, ExecutionErrorCause};
+use crate::files::FileTable;
 use crate::instruction::Instruction;
+use crate::load_delay::{LoadDelayHazard, PendingLoad};
 use crate::memory::Memory;
+use crate::memory_map::{Access, AccessViolation, MemoryAccess, MemoryMap, Permissions};
+use crate::pipeline::Pipeline;
+use crate::random::Random;
 use crate::registers::Registers;
+use crate::stats::Statistics;
+use crate::streams::Streams;
+use crate::symbols::SymbolTable;
+use crate::trace::Tracer;
 use mips_types::constants::*;
-use mips_types::module::R2KModule;
+use mips_types::module::{R2KModule, R2KSection};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// A MIPS processor
 #[derive(Debug)]
 pub struct Processor {
     pub registers: Registers,
+    pub cp0: Coprocessor0,
+    pub cp1: Coprocessor1,
     pub program_counter: u32,
     pub(crate) next_program_counter: u32,
+    /// The instruction at the program counter is in the delay slot of a
+    /// branch or jump (only with delay slots)
+    pub(crate) in_delay_slot: bool,
+    /// The condition of the last branch executed, which can be true even if
+    /// the branch's target is the next instruction
+    pub(crate) branch_taken: bool,
     pub(crate) memory: Memory,
+    pub(crate) memory_map: MemoryMap,
     pub(crate) config: Config,
     pub running: bool,
     pub return_code: i32,
+    /// The number of instructions executed
+    pub instruction_count: u64,
+    /// When the first instruction was executed (only tracked if there is a
+    /// timeout)
+    pub(crate) start_time: Option<Instant>,
+    /// If a sleep syscall used up the rest of the timeout
+    pub(crate) timed_out: bool,
+    /// If a kernel module is loaded, exceptions are handled by its exception
+    /// handler instead of stopping the processor.
+    pub(crate) kernel_loaded: bool,
+    /// The start of the heap
+    pub(crate) heap_start: u32,
+    /// The end of the heap, which is moved by sbrk
+    pub(crate) program_break: u32,
+    /// The standard streams used by the syscalls
+    pub(crate) streams: Streams,
+    /// The files opened by the program
+    pub(crate) files: FileTable,
+    /// The random number generators, indexed by ID
+    pub(crate) random_generators: HashMap<u32, Random>,
+    /// The symbols of the loaded modules
+    pub(crate) symbols: SymbolTable,
+    /// The memory accesses made by the last instruction, if they are being
+    /// recorded
+    pub(crate) memory_accesses: Option<Vec<MemoryAccess>>,
+    /// The statistics collected while executing, if enabled
+    pub(crate) statistics: Option<Box<Statistics>>,
+    /// The pipeline timing model, if enabled
+    pub(crate) pipeline: Option<Box<Pipeline>>,
+    /// The instruction cache model, if enabled
+    pub(crate) instruction_cache: Option<Box<Cache>>,
+    /// The data cache model, if enabled
+    pub(crate) data_cache: Option<Box<Cache>>,
+    /// The writer of the execution trace, if enabled
+    pub(crate) tracer: Option<Box<Tracer>>,
+    /// The branch predictors, if enabled
+    pub(crate) branch_prediction: Option<Box<BranchPrediction>>,
+    /// The load executed by the last instruction, whose delay slot is the
+    /// next instruction
+    pub(crate) pending_load: Option<PendingLoad>,
+    /// The load delay slot hazard caused by the last instruction
+    pub(crate) load_delay_hazard: Option<LoadDelayHazard>,
+    /// The number of times each load delay slot hazard happened
+    pub(crate) load_delay_hazards: HashMap<LoadDelayHazard, u64>,
+    /// If anything is recording the executed instructions (memory accesses,
+    /// statistics, the trace, the pipeline timing model, the caches, or the
+    /// branch predictors), or load delay slots are enabled or checked
+    pub(crate) recording: bool,
+}
+
+/// The name of the heap's segment in the memory map
+const HEAP_SEGMENT: &str = "heap";
+/// How many instructions to execute between checks of the timeout, since
+/// getting the time is slow
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// The result of successfully executing an instruction
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StepOutcome {
+    /// The program is still running
+    Running,
+    /// The program exited with the return code
+    Exited(i32),
+    /// The program stopped on a break instruction
+    Break,
+    /// The program was stopped before the next instruction because it reached
+    /// a limit in the config
+    LimitReached(Limit),
+}
+
+/// A limit on how long a program can run
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Limit {
+    /// The maximum number of instructions were executed
+    Instructions,
+    /// The program ran for longer than the timeout
+    Timeout,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Limit::Instructions => "Instruction limit reached",
+            Limit::Timeout => "Timed out",
+        };
+
+        f.write_str(description)
+    }
 }
 
 impl Processor {
+    /// Create a processor which uses stdin, stdout, and stderr for I/O
     pub fn new(config: Config) -> Self {
+        Self::with_streams(config, Streams::stdio())
+    }
+
+    /// Create a processor which uses the streams for I/O
+    pub fn with_streams(config: Config, mut streams: Streams) -> Self {
+        // The console device uses the same streams as the syscalls
+        let console = if config.enable_mapped_io {
+            let (input, output) = streams.share();
+            Some(ConsoleDevice::new(input, output))
+        } else {
+            None
+        };
         let mut processor = Processor {
             registers: Registers::new(),
+            cp0: Coprocessor0::new(),
+            cp1: Coprocessor1::new(),
             memory: Memory::new(),
+            memory_map: MemoryMap::new(),
             program_counter: 0,
             next_program_counter: 4,
-            config,
+            in_delay_slot: false,
+            branch_taken: false,
             running: true,
             return_code: 0,
+            instruction_count: 0,
+            start_time: None,
+            timed_out: false,
+            kernel_loaded: false,
+            heap_start: 0,
+            program_break: 0,
+            streams,
+            files: FileTable::new(config.file_sandbox.clone()),
+            random_generators: HashMap::new(),
+            symbols: SymbolTable::new(),
+            memory_accesses: None,
+            statistics: None,
+            pipeline: None,
+            instruction_cache: None,
+            data_cache: None,
+            tracer: None,
+            branch_prediction: None,
+            pending_load: None,
+            load_delay_hazard: None,
+            load_delay_hazards: HashMap::new(),
+            recording: false,
+            config,
         };
+        processor.create_models();
         processor.registers.set(REG_SP, STACK_BOTTOM);
+        processor.memory_map.add_segment(
+            "stack",
+            STACK_LIMIT,
+            KTEXT_OFFSET - STACK_LIMIT,
+            Permissions::READ_WRITE,
+        );
+
+        if let Some(console) = console {
+            processor.map_device(CONSOLE_ADDRESS, CONSOLE_SIZE, Box::new(console));
+        }
+
         processor
     }
 
+    /// Create the pipeline timing, cache, and branch prediction models from
+    /// the config, replacing any existing models, and start recording what
+    /// they (and load delay slots) need
+    pub(crate) fn create_models(&mut self) {
+        let config = &self.config;
+        self.pipeline = config
+            .pipeline
+            .map(|pipeline| Box::new(Pipeline::new(pipeline, config.enable_delay_slots)));
+        self.instruction_cache = config
+            .instruction_cache
+            .map(|cache| Box::new(Cache::new(cache)));
+        self.data_cache = config.data_cache.map(|cache| Box::new(Cache::new(cache)));
+        self.branch_prediction = config
+            .branch_prediction
+            .clone()
+            .map(|config| Box::new(BranchPrediction::new(config)));
+        self.recording |= config.pipeline.is_some()
+            || config.instruction_cache.is_some()
+            || config.branch_prediction.is_some()
+            || config.enable_load_delay_slots
+            || config.warn_load_delay_slots;
+
+        if self.data_cache.is_some() {
+            self.record_memory_accesses();
+        }
+    }
+
+    /// Get the config
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Map a memory-mapped I/O device to an address range. Loads and stores in
+    /// the range are handled by the device instead of memory.
+    pub fn map_device(&mut self, address: u32, size: u32, device: Box<dyn Device>) {
+        self.memory.map_device(address, size, device);
+        self.memory_map
+            .add_segment("mmio", address, size, Permissions::READ_WRITE);
+    }
+
     /// Load an RSIM executable module into memory and prepare for execution
     pub fn load_rsim_module(&mut self, module: &R2KModule) {
-        self.memory
-            .load_into_memory(&module.text_section, TEXT_OFFSET);
+        let data_end = self.load_sections(module);
+        self.symbols.add_module(module);
+        self.set_program_counter(module.header.entry);
+
+        // The heap starts after the last data segment (double word aligned),
+        // and is empty until the program moves the break with sbrk.
+        self.heap_start = (data_end + 7) & !7;
+        self.program_break = self.heap_start;
+        self.memory_map
+            .add_segment(HEAP_SEGMENT, self.heap_start, 0, Permissions::READ_WRITE);
+    }
+
+    /// Move the program break (the end of the heap) by the increment. The old
+    /// break is returned, or None if the heap would shrink past its start or
+    /// grow past its limit (or into the stack).
+    pub(crate) fn move_program_break(&mut self, increment: i32) -> Option<u32> {
+        let old_break = self.program_break;
+        let new_break = old_break as i64 + increment as i64;
+        let limit = match self.config.heap_limit {
+            Some(heap_limit) => self.heap_start.saturating_add(heap_limit).min(STACK_LIMIT),
+            None => STACK_LIMIT,
+        };
+
+        if new_break < self.heap_start as i64 || new_break > limit as i64 {
+            return None;
+        }
+
+        self.set_program_break(new_break as u32);
+        Some(old_break)
+    }
+
+    /// Move the program break, and resize the heap to match
+    pub(crate) fn set_program_break(&mut self, program_break: u32) {
+        self.program_break = program_break;
+        self.memory_map
+            .set_segment_size(HEAP_SEGMENT, self.program_break - self.heap_start);
+    }
+
+    /// Copy the program's arguments and environment strings ("NAME=VALUE") to
+    /// the stack, in the layout expected by r2k_startup:
+    ///
+    /// ```text
+    /// $sp ->  argc
+    ///         argv ptr
+    ///         env ptr
+    ///         argv[0]
+    ///          ...
+    ///         argv[argc-1]
+    ///         0
+    ///         env[0]
+    ///          ...
+    ///         env[n-1]
+    ///         0
+    ///         (strings)
+    /// ```
+    pub fn load_arguments(&mut self, arguments: &[String], environment: &[String]) {
+        // Copy the strings to the top of the stack
+        let mut string_address = STACK_BOTTOM;
+        let mut push_string = |memory: &mut Memory, string: &String| {
+            string_address -= string.len() as u32 + 1;
+            memory.load_into_memory(string.as_bytes(), string_address);
+            memory.set(string_address + string.len() as u32, 0);
+            string_address
+        };
+        let argument_pointers: Vec<u32> = arguments
+            .iter()
+            .map(|argument| push_string(&mut self.memory, argument))
+            .collect();
+        let environment_pointers: Vec<u32> = environment
+            .iter()
+            .map(|variable| push_string(&mut self.memory, variable))
+            .collect();
+
+        // Put the header and pointer arrays below the strings. The stack
+        // pointer is kept double word aligned.
+        let word_count = 3 + (argument_pointers.len() + 1) + (environment_pointers.len() + 1);
+        let stack_pointer = (string_address - 4 * word_count as u32) & !7;
+        let argv_address = stack_pointer + 12;
+        let envp_address = argv_address + 4 * (argument_pointers.len() as u32 + 1);
 
-        let mut data_offset = DATA_OFFSET;
-        self.memory
-            .load_into_memory(&module.rdata_section, DATA_OFFSET);
-        data_offset += module.rdata_section.len() as u32;
-        self.memory
-            .load_into_memory(&module.data_section, data_offset);
-        data_offset += module.data_section.len() as u32;
         self.memory
-            .load_into_memory(&module.sdata_section, data_offset);
+            .set_word(stack_pointer, argument_pointers.len() as u32);
+        self.memory.set_word(stack_pointer + 4, argv_address);
+        self.memory.set_word(stack_pointer + 8, envp_address);
 
-        self.set_program_counter(module.header.entry);
+        let pointers = argument_pointers
+            .iter()
+            .chain(&[0])
+            .chain(&environment_pointers)
+            .chain(&[0]);
+        for (i, pointer) in pointers.enumerate() {
+            self.memory.set_word(argv_address + 4 * i as u32, *pointer);
+        }
+
+        self.registers.set(REG_SP, stack_pointer);
+    }
+
+    /// Load a kernel module (.ktext/.kdata) into memory. Its code at
+    /// `EXCEPTION_VECTOR` will handle any exceptions raised by the program.
+    pub fn load_kernel_module(&mut self, module: &R2KModule) {
+        self.load_sections(module);
+        self.symbols.add_module(module);
+        self.kernel_loaded = true;
+    }
+
+    /// Load a program to run: its executable module, its arguments (the first
+    /// is the program's path) and environment, and optionally the kernel
+    /// module at `kernel` to handle its exceptions. An environment variable
+    /// can be given as NAME=VALUE, or as NAME to copy it from the simulator's
+    /// environment (it is skipped with a warning if it isn't set).
+    pub fn load_program(
+        &mut self,
+        module: &R2KModule,
+        arguments: &[String],
+        environment: &[String],
+        kernel: Option<&Path>,
+    ) -> io::Result<()> {
+        self.load_rsim_module(module);
+
+        let environment: Vec<String> = environment
+            .iter()
+            .filter_map(|variable| {
+                if variable.contains('=') {
+                    return Some(variable.clone());
+                }
+
+                match env::var(variable) {
+                    Ok(value) => Some(format!("{}={}", variable, value)),
+                    Err(_) => {
+                        warn!("Environment variable {} is not set", variable);
+                        None
+                    }
+                }
+            })
+            .collect();
+        self.load_arguments(arguments, &environment);
+
+        // Load the exception handler
+        if let Some(kernel_path) = kernel {
+            let file_data = fs::read(kernel_path)?;
+            let kernel = R2KModule::parse(&mut Cursor::new(file_data))?;
+
+            if !kernel.is_kernel_module() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not a kernel module", kernel_path.display()),
+                ));
+            }
+
+            self.load_kernel_module(&kernel);
+            info!("Loaded kernel module with header: {:?}", kernel.header);
+        }
+
+        Ok(())
+    }
+
+    /// Load the module's sections into memory at their section offsets, and
+    /// add them to the memory map. The uninitialized data sections (sbss and
+    /// bss) are zeroed. The end address of the module's data is returned.
+    fn load_sections(&mut self, module: &R2KModule) -> u32 {
+        let kernel = module.is_kernel_module();
+        let sbss_section = vec![0; module.sbss_size as usize];
+        let bss_section = vec![0; module.bss_size as usize];
+        let sections = [
+            (
+                R2KSection::Text,
+                &module.text_section,
+                ".text",
+                ".ktext",
+                Permissions::READ_EXECUTE,
+            ),
+            (
+                R2KSection::RData,
+                &module.rdata_section,
+                ".rdata",
+                ".kdata",
+                Permissions::READ_ONLY,
+            ),
+            (
+                R2KSection::Data,
+                &module.data_section,
+                ".data",
+                ".kdata",
+                Permissions::READ_WRITE,
+            ),
+            (
+                R2KSection::SData,
+                &module.sdata_section,
+                ".sdata",
+                ".kdata",
+                Permissions::READ_WRITE,
+            ),
+            (
+                R2KSection::SBss,
+                &sbss_section,
+                ".sbss",
+                ".kbss",
+                Permissions::READ_WRITE,
+            ),
+            (
+                R2KSection::Bss,
+                &bss_section,
+                ".bss",
+                ".kbss",
+                Permissions::READ_WRITE,
+            ),
+        ];
+
+        for (section, data, name, kernel_name, permissions) in sections.iter() {
+            let offset = module.get_section_offset(*section).unwrap();
+            let name = if kernel { kernel_name } else { name };
+            self.memory.load_into_memory(data, offset);
+            self.memory_map
+                .add_segment(name, offset, data.len() as u32, *permissions);
+        }
+
+        module.get_section_offset(R2KSection::Bss).unwrap() + module.bss_size
     }
 
     fn set_program_counter(&mut self, address: u32) {
         self.program_counter = address;
-        self.next_program_counter = address + 4;
+        self.next_program_counter = address.wrapping_add(4);
+        self.in_delay_slot = false;
     }
 
-    /// Execute the next instruction
+    /// Execute the next instruction. If the instruction faults and there is no
+    /// exception handler, the processor state is left as it was before the
+    /// instruction. If a limit has been reached, nothing is executed.
     #[inline(always)]
-    pub fn step(&mut self) {
-        let instruction = self.load_next_instruction();
+    pub fn step(&mut self) -> Result<StepOutcome, ExecutionError> {
+        if let Some(limit) = self.check_limits() {
+            return Ok(StepOutcome::LimitReached(limit));
+        }
+
+        // Recording is rare, so it gets its own copy of the step to keep the
+        // normal step fast
+        if self.recording {
+            self.step_recording()
+        } else {
+            self.step_instruction::<false>()
+        }
+    }
+
+    /// Execute the next instruction, record its memory accesses, statistics,
+    /// trace, pipeline timing, cache accesses, and branch predictions, and
+    /// handle its load delay slot
+    #[inline(never)]
+    fn step_recording(&mut self) -> Result<StepOutcome, ExecutionError> {
+        self.step_instruction::<true>()
+    }
+
+    /// Execute the next instruction. If `RECORDING`, the memory accesses,
+    /// statistics, trace, pipeline timing, cache accesses, and branch
+    /// predictions are recorded, and load delay slots are handled.
+    #[inline(always)]
+    fn step_instruction<const RECORDING: bool>(&mut self) -> Result<StepOutcome, ExecutionError> {
+        if RECORDING {
+            if let Some(memory_accesses) = &mut self.memory_accesses {
+                memory_accesses.clear();
+            }
+        }
+
+        let program_counter = self.program_counter;
+        let cached = self.memory.get_decoded(program_counter);
+        let decoded =
+            cached.unwrap_or_else(|| DecodedInstruction::new(self.load_next_instruction()));
+        let instruction = decoded.instruction;
         trace!("{:08x?}", instruction);
-        debug!("{}", instruction.stringify(self.program_counter));
-        self.execute(instruction);
+        debug!("{}", instruction.stringify(program_counter));
+
+        // Cached instructions have already passed the fetch checks. The
+        // executable segments never change, so they would pass again.
+        let fetch_result = match cached {
+            Some(_) => Ok(()),
+            None => self
+                .check_alignment(program_counter, 4, false)
+                .and_then(|_| self.check_access(program_counter, 4, Access::Execute))
+                .map(|_| self.memory.set_decoded(program_counter, decoded)),
+        };
+        let old_registers = if RECORDING && self.tracer.is_some() {
+            Some(self.trace_registers())
+        } else {
+            None
+        };
+        let result = fetch_result.and_then(|_| {
+            if RECORDING
+                && (self.config.enable_load_delay_slots || self.config.warn_load_delay_slots)
+            {
+                self.execute_with_load_delay(program_counter, decoded)
+            } else {
+                (decoded.operation)(self, instruction)
+            }
+        });
+        let executed = result.is_ok();
+
+        if let Err(cause) = result {
+            match cause.exception_code() {
+                Some(exception_code) if self.kernel_loaded => {
+                    debug!("Raising exception: {}", cause);
+                    if let Some(address) = cause.bad_address() {
+                        self.cp0.bad_vaddr = address;
+                    }
+                    self.raise_exception(exception_code);
+                }
+                _ => {
+                    return Err(ExecutionError {
+                        program_counter,
+                        instruction: instruction.0,
+                        cause,
+                    })
+                }
+            }
+        }
+        self.instruction_count += 1;
+        if let (true, Some(old_registers)) = (RECORDING, &old_registers) {
+            let index = self.instruction_count - 1;
+            self.record_trace(index, program_counter, instruction, old_registers);
+        }
+        if RECORDING && self.statistics.is_some() {
+            self.record_statistics(program_counter, instruction, executed);
+        }
+        if RECORDING && self.pipeline.is_some() {
+            self.record_pipeline(program_counter, instruction, executed);
+        }
+        if RECORDING && (self.instruction_cache.is_some() || self.data_cache.is_some()) {
+            self.record_cache_accesses(program_counter, instruction);
+        }
+        if RECORDING && self.branch_prediction.is_some() {
+            self.record_branch_prediction(program_counter, instruction, executed);
+        }
         trace!("{:#08x?}", self);
+
+        Ok(if self.running {
+            StepOutcome::Running
+        } else if instruction.is_break() {
+            StepOutcome::Break
+        } else {
+            StepOutcome::Exited(self.return_code)
+        })
+    }
+
+    /// Check if the program has used up its instruction budget or time
+    fn check_limits(&mut self) -> Option<Limit> {
+        if self
+            .config
+            .max_instructions
+            .is_some_and(|max_instructions| self.instruction_count >= max_instructions)
+        {
+            return Some(Limit::Instructions);
+        }
+
+        if let Some(timeout) = self.config.timeout {
+            let start_time = *self.start_time.get_or_insert_with(Instant::now);
+
+            if self.timed_out
+                || self
+                    .instruction_count
+                    .is_multiple_of(TIMEOUT_CHECK_INTERVAL)
+                    && start_time.elapsed() >= timeout
+            {
+                return Some(Limit::Timeout);
+            }
+        }
+
+        None
+    }
+
+    /// Get the time left before the timeout, if there is one
+    pub(crate) fn remaining_time(&mut self) -> Option<Duration> {
+        let timeout = self.config.timeout?;
+        let start_time = *self.start_time.get_or_insert_with(Instant::now);
+
+        Some(timeout.saturating_sub(start_time.elapsed()))
     }
 
     pub fn load_next_instruction(&self) -> Instruction {
         trace!("Loading instruction at 0x{:x}", self.program_counter);
-        Instruction(self.memory.get_word(self.program_counter))
+        Instruction(self.memory.fetch_word(self.program_counter))
+    }
+
+    /// Get the random number generator with the ID, creating it if needed
+    pub(crate) fn random_generator(&mut self, id: u32) -> &mut Random {
+        let seed = self.config.random_seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64
+        });
+
+        self.random_generators
+            .entry(id)
+            .or_insert_with(|| Random::new(seed))
+    }
+
+    /// Check that an access of `size` bytes is aligned to its size. Unaligned
+    /// accesses raise an address error unless they are allowed by the config.
+    pub(crate) fn check_alignment(
+        &self,
+        address: u32,
+        size: u32,
+        store: bool,
+    ) -> Result<(), ExecutionErrorCause> {
+        if self.config.allow_unaligned_access || address.is_multiple_of(size) {
+            Ok(())
+        } else if store {
+            Err(ExecutionErrorCause::AddressErrorStore(address))
+        } else {
+            Err(ExecutionErrorCause::AddressErrorLoad(address))
+        }
+    }
+
+    /// Check that the memory map allows the access to all `size` bytes at the
+    /// address. Any access is allowed if memory protection is disabled.
+    /// Allowed reads and writes are recorded if memory accesses are being
+    /// recorded.
+    pub(crate) fn check_access(
+        &mut self,
+        address: u32,
+        size: u32,
+        access: Access,
+    ) -> Result<(), ExecutionErrorCause> {
+        self.check_permissions(address, size, access)?;
+
+        if self.memory_accesses.is_some() && access != Access::Execute {
+            self.record_memory_access(address, size, access);
+        }
+
+        Ok(())
+    }
+
+    /// Check that the memory map allows the access to all `size` bytes at the
+    /// address, without recording it
+    pub(crate) fn check_permissions(
+        &self,
+        address: u32,
+        size: u32,
+        access: Access,
+    ) -> Result<(), ExecutionErrorCause> {
+        if !self.config.disable_memory_protection {
+            self.memory_map
+                .check(address, size, access)
+                .map_err(|violation| match violation {
+                    AccessViolation::Unmapped => {
+                        ExecutionErrorCause::UnmappedAddress { address, access }
+                    }
+                    AccessViolation::NotPermitted(segment) => {
+                        ExecutionErrorCause::ProtectedAddress {
+                            address,
+                            access,
+                            segment,
+                        }
+                    }
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Add an access to the recorded memory accesses
+    #[cold]
+    pub(crate) fn record_memory_access(&mut self, address: u32, size: u32, access: Access) {
+        if let Some(memory_accesses) = &mut self.memory_accesses {
+            memory_accesses.push(MemoryAccess {
+                address,
+                size,
+                access,
+            });
+        }
+    }
+
+    /// Start recording the memory accesses made by each instruction
+    pub fn record_memory_accesses(&mut self) {
+        self.memory_accesses.get_or_insert_with(Vec::new);
+        self.recording = true;
+    }
+
+    /// Get the memory accesses made by the last instruction. This is empty
+    /// unless `record_memory_accesses` was called.
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        self.memory_accesses.as_deref().unwrap_or(&[])
+    }
+
+    /// Read `size` bytes of memory at the address, without executing an
+    /// instruction (ex. to show a value in a debugger). Mapped devices are not
+    /// accessed, so their registers read as 0.
+    pub fn read_memory(&self, address: u32, size: u32) -> Vec<u8> {
+        (0..size)
+            .map(|offset| self.memory.peek(address.wrapping_add(offset)))
+            .collect()
+    }
+
+    /// Get the symbols of the loaded modules
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Trap to the exception handler. The current instruction is the one which
+    /// raised the exception.
+    fn raise_exception(&mut self, exception_code: u8) {
+        // In a delay slot, the exception should return to the branch instead
+        let in_delay_slot = self.in_delay_slot;
+        let epc = if in_delay_slot {
+            self.program_counter - 4
+        } else {
+            self.program_counter
+        };
+
+        self.complete_pending_load();
+        self.cp0.enter_exception(exception_code, epc, in_delay_slot);
+        self.set_program_counter(EXCEPTION_VECTOR);
+    }
+
+    /// Get the address a branch or jump at the program counter (which was
+    /// just executed) transferred control to, if it was taken. With delay
+    /// slots, control is transferred after the delay slot.
+    pub(crate) fn control_transfer(&self, program_counter: u32) -> Option<u32> {
+        let (destination, sequential) = if self.config.enable_delay_slots {
+            (self.next_program_counter, program_counter.wrapping_add(8))
+        } else {
+            (self.program_counter, program_counter.wrapping_add(4))
+        };
+
+        if destination != sequential {
+            Some(destination)
+        } else {
+            None
+        }
     }
 
     /// Update the program counter with the queued address, and advance the
     /// queued address by 4 bytes.
     pub(crate) fn advance_program_counter(&mut self) {
         self.program_counter = self.next_program_counter;
-        self.next_program_counter += 4;
+        self.next_program_counter = self.next_program_counter.wrapping_add(4);
+        self.in_delay_slot = false;
     }
 
     /// Jump to an address. If delay slots are enabled, the next instruction
@@ -85,9 +792,10 @@ impl Processor {
         if self.config.enable_delay_slots {
             self.program_counter = self.next_program_counter;
             self.next_program_counter = address;
-            self.program_counter + 4
+            self.in_delay_slot = true;
+            self.program_counter.wrapping_add(4)
         } else {
-            let return_address = self.program_counter + 4;
+            let return_address = self.program_counter.wrapping_add(4);
             self.next_program_counter = address;
             self.advance_program_counter();
             return_address
@@ -96,41 +804,535 @@ impl Processor {
 
     /// Execute an instruction
     #[inline(always)]
-    pub fn execute(&mut self, instruction: Instruction) {
-        match instruction.op_code() {
-            OP_R_TYPE => match instruction.function() {
-                FUNCTION_SLL => self.op_sll(instruction),
-                FUNCTION_JR => self.op_jr(instruction),
-                FUNCTION_JALR => self.op_jalr(instruction),
-                FUNCTION_SYSCALL => self.op_syscall(),
-                FUNCTION_BREAK => self.op_break(),
-                FUNCTION_MFHI => self.op_mfhi(instruction),
-                FUNCTION_MFLO => self.op_mflo(instruction),
-                FUNCTION_MULT => self.op_mult(instruction),
-                FUNCTION_DIV => self.op_div(instruction),
-                FUNCTION_ADD => self.op_add(instruction),
-                FUNCTION_ADDU => self.op_addu(instruction),
-                FUNCTION_SUB => self.op_sub(instruction),
-                FUNCTION_OR => self.op_or(instruction),
-                FUNCTION_XOR => self.op_xor(instruction),
-                FUNCTION_SLT => self.op_slt(instruction),
-                function => panic!("Unknown R-type function 0x{:02x}", function),
-            },
-            OP_J => self.op_j(instruction),
-            OP_JAL => self.op_jal(instruction),
-            OP_BEQ => self.op_beq(instruction),
-            OP_BNE => self.op_bne(instruction),
-            OP_ADDI => self.op_addi(instruction),
-            OP_ADDIU => self.op_addiu(instruction),
-            OP_SLTI => self.op_slti(instruction),
-            OP_ORI => self.op_ori(instruction),
-            OP_LUI => self.op_lui(instruction),
-            OP_LB => self.op_lb(instruction),
-            OP_LW => self.op_lw(instruction),
-            OP_LBU => self.op_lbu(instruction),
-            OP_SB => self.op_sb(instruction),
-            OP_SW => self.op_sw(instruction),
-            op_code => panic!("Unknown op code 0x{:02x}", op_code),
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), ExecutionErrorCause> {
+        decode(instruction)(self, instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limit, Processor, StepOutcome};
+    use crate::config::Config;
+    use crate::devices::CONSOLE_ADDRESS;
+    use crate::error::ExecutionErrorCause;
+    use crate::memory_map::Access;
+    use crate::test_util::{exit, i_type, li, module, processor, r_type, run, SYSCALL};
+    use mips_types::constants::*;
+    use std::time::{Duration, Instant};
+
+    /// Load 0x7fffffff and 1, then add them (which overflows)
+    fn overflow() -> Vec<u32> {
+        let mut text = Vec::new();
+        text.extend(li(REG_A0, 0x7fff_ffff));
+        text.extend(li(REG_A1, 1));
+        text.push(r_type(FUNCTION_ADD, REG_A2, REG_A0, REG_A1));
+        text.extend(exit());
+        text
+    }
+
+    #[test]
+    fn faults_leave_the_state_unchanged() {
+        let (mut processor, _) = processor(Config::default(), "", &overflow(), &[]);
+
+        let error = run(&mut processor).unwrap_err();
+        assert!(matches!(error.cause, ExecutionErrorCause::IntegerOverflow));
+        assert_eq!(error.program_counter, TEXT_OFFSET + 16);
+        assert_eq!(
+            error.instruction,
+            r_type(FUNCTION_ADD, REG_A2, REG_A0, REG_A1)
+        );
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 16);
+        assert_eq!(processor.instruction_count, 4);
+        assert_eq!(processor.registers.get(REG_A2), 0);
+        assert!(processor.running);
+
+        // The instruction faults again
+        let error = processor.step().unwrap_err();
+        assert!(matches!(error.cause, ExecutionErrorCause::IntegerOverflow));
+    }
+
+    #[test]
+    fn reserved_instruction() {
+        let text = [0xfc00_0000];
+        let (mut processor, _) = processor(Config::default(), "", &text, &[]);
+
+        let error = processor.step().unwrap_err();
+        assert!(matches!(
+            error.cause,
+            ExecutionErrorCause::ReservedInstruction
+        ));
+        assert_eq!(error.program_counter, TEXT_OFFSET);
+    }
+
+    #[test]
+    fn break_without_an_exception_handler() {
+        let text = [r_type(FUNCTION_BREAK, 0, 0, 0)];
+        let (mut processor, _) = processor(Config::default(), "", &text, &[]);
+
+        assert_eq!(processor.step().unwrap(), StepOutcome::Break);
+        assert!(!processor.running);
+    }
+
+    #[test]
+    fn exit_with_a_return_code() {
+        let mut text = li(REG_A0, 3).to_vec();
+        text.extend(li(REG_V0, SYSCALL_EXIT2));
+        text.push(SYSCALL);
+        let (mut processor, _) = processor(Config::default(), "", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(3));
+        assert!(!processor.running);
+    }
+
+    /// Load a kernel with an exception handler (of nops)
+    fn load_kernel(processor: &mut Processor) {
+        let handler_offset = (EXCEPTION_VECTOR - KTEXT_OFFSET) as usize / 4;
+        let mut kernel = module(&vec![0; handler_offset + 1], &[], &[]);
+        kernel.header.flags |= MODULE_KERNEL;
+        processor.load_kernel_module(&kernel);
+    }
+
+    /// Get the exception code in the Cause register
+    fn exception_code(processor: &Processor) -> u8 {
+        ((processor.cp0.cause >> 2) & 0x1f) as u8
+    }
+
+    #[test]
+    fn trap_to_the_exception_handler() {
+        let (mut processor, _) = processor(Config::default(), "", &overflow(), &[]);
+        load_kernel(&mut processor);
+        processor.cp0.status = 0x3;
+
+        for _ in 0..5 {
+            assert_eq!(processor.step().unwrap(), StepOutcome::Running);
+        }
+        assert_eq!(processor.program_counter, EXCEPTION_VECTOR);
+        assert_eq!(processor.cp0.epc, TEXT_OFFSET + 16);
+        assert_eq!(exception_code(&processor), EXC_OVERFLOW);
+        assert_eq!(processor.cp0.cause & 0x8000_0000, 0);
+        // Kernel mode with interrupts disabled, and the old mode pushed
+        assert_eq!(processor.cp0.status & 0x3f, 0xc);
+        assert_eq!(processor.registers.get(REG_A2), 0);
+        assert_eq!(processor.instruction_count, 5);
+    }
+
+    #[test]
+    fn address_errors_set_bad_vaddr() {
+        let mut text = li(REG_A0, DATA_OFFSET + 2).to_vec();
+        text.push(i_type(OP_SW, REG_A1, REG_A0, 0));
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 8]);
+        load_kernel(&mut processor);
+
+        for _ in 0..3 {
+            processor.step().unwrap();
+        }
+        assert_eq!(processor.program_counter, EXCEPTION_VECTOR);
+        assert_eq!(exception_code(&processor), EXC_ADDRESS_STORE);
+        assert_eq!(processor.cp0.bad_vaddr, DATA_OFFSET + 2);
+        assert_eq!(processor.cp0.epc, TEXT_OFFSET + 8);
+    }
+
+    #[test]
+    fn exceptions_in_delay_slots_return_to_the_branch() {
+        // Taken and not-taken branches both have a delay slot
+        for branch in [OP_BEQ, OP_BNE] {
+            let mut text = li(REG_A0, DATA_OFFSET + 1).to_vec();
+            text.push(i_type(branch, 0, 0, 2));
+            text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+            text.extend(exit());
+            let config = Config {
+                enable_delay_slots: true,
+                ..Config::default()
+            };
+            let (mut processor, _) = processor(config, "", &text, &[0; 8]);
+            load_kernel(&mut processor);
+
+            for _ in 0..4 {
+                processor.step().unwrap();
+            }
+            assert_eq!(processor.program_counter, EXCEPTION_VECTOR);
+            assert_eq!(exception_code(&processor), EXC_ADDRESS_LOAD);
+            assert_eq!(processor.cp0.epc, TEXT_OFFSET + 8);
+            assert_ne!(processor.cp0.cause & 0x8000_0000, 0);
+        }
+    }
+
+    /// Access memory at the address with the instruction
+    fn access(op_code: u8, address: u32, config: Config) -> Processor {
+        let mut text = li(REG_A0, address).to_vec();
+        text.push(i_type(op_code, REG_A1, REG_A0, 0));
+        text.extend(exit());
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+        processor(config, "", &text, &data).0
+    }
+
+    #[test]
+    fn unaligned_accesses_are_address_errors() {
+        for (op_code, address) in [(OP_LW, 2), (OP_LW, 1), (OP_LH, 1), (OP_LHU, 3)] {
+            let mut processor = access(op_code, DATA_OFFSET + address, Config::default());
+            assert!(matches!(
+                run(&mut processor).unwrap_err().cause,
+                ExecutionErrorCause::AddressErrorLoad(bad_address)
+                    if bad_address == DATA_OFFSET + address
+            ));
+        }
+
+        for (op_code, address) in [(OP_SW, 2), (OP_SH, 1)] {
+            let mut processor = access(op_code, DATA_OFFSET + address, Config::default());
+            assert!(matches!(
+                run(&mut processor).unwrap_err().cause,
+                ExecutionErrorCause::AddressErrorStore(bad_address)
+                    if bad_address == DATA_OFFSET + address
+            ));
+            assert_eq!(processor.read_memory(DATA_OFFSET, 4), [1, 2, 3, 4]);
+        }
+
+        // Bytes are always aligned
+        let mut processor = access(OP_LB, DATA_OFFSET + 3, Config::default());
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(REG_A1), 4);
+    }
+
+    #[test]
+    fn allow_unaligned_accesses() {
+        let config = Config {
+            allow_unaligned_access: true,
+            ..Config::default()
+        };
+        let mut processor = access(OP_LW, DATA_OFFSET + 2, config.clone());
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(REG_A1), 0x0304_0506);
+
+        let mut processor = access(OP_LH, DATA_OFFSET + 3, config);
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(REG_A1), 0x0405);
+    }
+
+    #[test]
+    fn unaligned_instruction_fetch() {
+        let mut text = li(REG_A0, TEXT_OFFSET + 2).to_vec();
+        text.push(r_type(FUNCTION_JR, 0, REG_A0, 0));
+        let (mut processor, _) = processor(Config::default(), "", &text, &[]);
+
+        let error = run(&mut processor).unwrap_err();
+        assert!(matches!(
+            error.cause,
+            ExecutionErrorCause::AddressErrorLoad(address) if address == TEXT_OFFSET + 2
+        ));
+        assert_eq!(error.program_counter, TEXT_OFFSET + 2);
+    }
+
+    #[test]
+    fn jump_to_the_end_of_memory() {
+        for enable_delay_slots in [false, true] {
+            let config = Config {
+                enable_delay_slots,
+                ..Config::default()
+            };
+            let mut text = li(REG_A0, 0xffff_fffc).to_vec();
+            text.push(r_type(FUNCTION_JR, 0, REG_A0, 0));
+            text.push(0);
+            let (mut processor, _) = processor(config, "", &text, &[]);
+
+            let error = run(&mut processor).unwrap_err();
+            assert!(matches!(
+                error.cause,
+                ExecutionErrorCause::UnmappedAddress { address, access: Access::Execute }
+                    if address == 0xffff_fffc
+            ));
+            assert_eq!(error.program_counter, 0xffff_fffc);
+        }
+    }
+
+    #[test]
+    fn protection_faults() {
+        // Store to the text section
+        let mut processor = access(OP_SW, TEXT_OFFSET, Config::default());
+        assert!(matches!(
+            run(&mut processor).unwrap_err().cause,
+            ExecutionErrorCause::ProtectedAddress {
+                address: TEXT_OFFSET,
+                access: Access::Write,
+                segment: ".text",
+            }
+        ));
+
+        // Load from unmapped memory
+        let mut processor = access(OP_LW, 0, Config::default());
+        assert!(matches!(
+            run(&mut processor).unwrap_err().cause,
+            ExecutionErrorCause::UnmappedAddress {
+                address: 0,
+                access: Access::Read,
+            }
+        ));
+
+        // Load past the end of the data section
+        let mut processor = access(OP_LW, DATA_OFFSET + 8, Config::default());
+        assert!(matches!(
+            run(&mut processor).unwrap_err().cause,
+            ExecutionErrorCause::UnmappedAddress { .. }
+        ));
+    }
+
+    #[test]
+    fn execute_from_the_data_section() {
+        let mut text = li(REG_A0, DATA_OFFSET).to_vec();
+        text.push(r_type(FUNCTION_JR, 0, REG_A0, 0));
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 4]);
+
+        let error = run(&mut processor).unwrap_err();
+        assert!(matches!(
+            error.cause,
+            ExecutionErrorCause::ProtectedAddress {
+                access: Access::Execute,
+                segment: ".data",
+                ..
+            }
+        ));
+        assert_eq!(error.program_counter, DATA_OFFSET);
+    }
+
+    #[test]
+    fn disable_memory_protection() {
+        let config = Config {
+            disable_memory_protection: true,
+            ..Config::default()
+        };
+        let mut processor = access(OP_SW, TEXT_OFFSET, config);
+        processor.registers.set(REG_A1, 0x1234_5678);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(
+            processor.read_memory(TEXT_OFFSET, 4),
+            [0x12, 0x34, 0x56, 0x78]
+        );
+    }
+
+    #[test]
+    fn mapped_io_uses_the_streams() {
+        let config = Config {
+            enable_mapped_io: true,
+            timeout: Some(Duration::from_secs(5)),
+            ..Config::default()
+        };
+        let mut text = li(REG_A2, CONSOLE_ADDRESS).to_vec();
+        // Write "h" to the console, then "i" with a syscall
+        text.extend(li(REG_A0, b'h' as u32));
+        text.push(i_type(OP_SW, REG_A0, REG_A2, 12));
+        text.extend(li(REG_A0, b'i' as u32));
+        text.extend(li(REG_V0, SYSCALL_PRINT_CHAR));
+        text.push(SYSCALL);
+        // Read "a" with a syscall, then echo "b" from the console
+        text.extend(li(REG_V0, SYSCALL_READ_CHAR));
+        text.push(SYSCALL);
+        text.push(r_type(FUNCTION_OR, REG_A1, REG_V0, 0));
+        // Poll until the console has received "b"
+        text.push(i_type(OP_LW, REG_A0, REG_A2, 0));
+        text.push(i_type(OP_ANDI, REG_A0, REG_A0, 1));
+        text.push(i_type(OP_BEQ, 0, REG_A0, (-3i16) as u16));
+        text.push(i_type(OP_LW, REG_A0, REG_A2, 4));
+        text.push(i_type(OP_SW, REG_A0, REG_A2, 12));
+        text.extend(exit());
+        let (mut processor, output) = processor(config, "ab", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(REG_A1), b'a' as u32);
+        assert_eq!(output.contents(), b"hib");
+    }
+
+    #[test]
+    fn uninitialized_data_is_zeroed_and_mapped() {
+        let mut processor = Processor::new(Config::default());
+        for offset in 0..32 {
+            processor.memory.set(DATA_OFFSET + offset, 0xff);
         }
+        let mut program = module(&[0], &[1, 2, 3], &[]);
+        program.sbss_size = 4;
+        program.bss_size = 12;
+        processor.load_rsim_module(&program);
+
+        assert_eq!(processor.read_memory(DATA_OFFSET, 4), [1, 2, 3, 0]);
+        assert!(processor
+            .read_memory(DATA_OFFSET + 3, 16)
+            .iter()
+            .all(|byte| *byte == 0));
+        let segments: Vec<_> = processor
+            .memory_map
+            .segments()
+            .iter()
+            .filter(|segment| segment.size > 0)
+            .map(|segment| (segment.name, segment.start, segment.size))
+            .collect();
+        assert!(segments.contains(&(".sbss", DATA_OFFSET + 3, 4)));
+        assert!(segments.contains(&(".bss", DATA_OFFSET + 7, 12)));
+        assert!(processor
+            .check_permissions(DATA_OFFSET + 3, 4, Access::Write)
+            .is_ok());
+        assert!(processor
+            .check_permissions(DATA_OFFSET + 7, 12, Access::Write)
+            .is_ok());
+
+        // The heap starts after the bss (double word aligned), and is empty
+        assert_eq!(processor.program_break, DATA_OFFSET + 24);
+        assert!(processor
+            .check_permissions(DATA_OFFSET + 19, 1, Access::Read)
+            .is_err());
+    }
+
+    #[test]
+    fn arguments_on_the_stack() {
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let arguments = ["prog".to_string(), "-v".to_string()];
+        processor.load_arguments(&arguments, &["HOME=/".to_string()]);
+
+        let stack_pointer = processor.registers.get(REG_SP);
+        assert_eq!(stack_pointer % 8, 0);
+        let word = |address| processor.memory.get_word(address);
+        let string = |address: u32| {
+            let bytes = processor.read_memory(address, 16);
+            let end = bytes.iter().position(|byte| *byte == 0).unwrap();
+            String::from_utf8(bytes[..end].to_vec()).unwrap()
+        };
+
+        assert_eq!(word(stack_pointer), 2);
+        let argv = word(stack_pointer + 4);
+        let envp = word(stack_pointer + 8);
+        assert_eq!(argv, stack_pointer + 12);
+        assert_eq!(string(word(argv)), "prog");
+        assert_eq!(string(word(argv + 4)), "-v");
+        assert_eq!(word(argv + 8), 0);
+        assert_eq!(envp, argv + 12);
+        assert_eq!(string(word(envp)), "HOME=/");
+        assert_eq!(word(envp + 4), 0);
+        // The strings are at the top of the stack
+        assert!(word(argv) > envp + 4);
+        assert!(word(envp) + 7 <= STACK_BOTTOM);
+    }
+
+    #[test]
+    fn program_environment() {
+        std::env::set_var("MIPS_SIMULATOR_TEST_SET", "yes");
+        std::env::remove_var("MIPS_SIMULATOR_TEST_UNSET");
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let environment = [
+            "HOME=/".to_string(),
+            "MIPS_SIMULATOR_TEST_SET".to_string(),
+            "MIPS_SIMULATOR_TEST_UNSET".to_string(),
+        ];
+        processor
+            .load_program(
+                &module(&[0], &[], &[]),
+                &["prog".to_string()],
+                &environment,
+                None,
+            )
+            .unwrap();
+
+        // Variables without a value are copied from the simulator's
+        // environment, and skipped if they aren't set there
+        let stack_pointer = processor.registers.get(REG_SP);
+        let envp = processor.memory.get_word(stack_pointer + 8);
+        let variables: Vec<String> = (0..3)
+            .map(|index| processor.memory.get_word(envp + 4 * index))
+            .take_while(|address| *address != 0)
+            .map(|address| {
+                let bytes = processor.read_memory(address, 32);
+                let end = bytes.iter().position(|byte| *byte == 0).unwrap();
+                String::from_utf8(bytes[..end].to_vec()).unwrap()
+            })
+            .collect();
+        assert_eq!(variables, ["HOME=/", "MIPS_SIMULATOR_TEST_SET=yes"]);
+    }
+
+    #[test]
+    fn program_kernel_must_be_a_kernel_module() {
+        let path = std::env::temp_dir().join("mips-simulator-test-not-a-kernel.rsim");
+        let mut file_data = Vec::new();
+        module(&[0], &[], &[]).write(&mut file_data).unwrap();
+        std::fs::write(&path, file_data).unwrap();
+
+        let (mut processor, _) = processor(Config::default(), "", &[0], &[]);
+        let result = processor.load_program(&module(&[0], &[], &[]), &[], &[], Some(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        let error = result.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!processor.kernel_loaded);
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        let config = Config {
+            disable_memory_protection: true,
+            ..Config::default()
+        };
+        let mut text = Vec::new();
+        text.extend(li(REG_A0, i_type(OP_ADDIU, REG_A1, REG_A1, 10)));
+        text.extend(li(REG_A2, TEXT_OFFSET + 16));
+        // Add 1 the first time, then overwrite it to add 10 the second time
+        text.push(i_type(OP_ADDIU, REG_A1, REG_A1, 1));
+        text.push(i_type(OP_SW, REG_A0, REG_A2, 0));
+        text.push(i_type(OP_SLTI, REG_V0, REG_A1, 2));
+        text.push(i_type(OP_BNE, 0, REG_V0, (-4i16) as u16));
+        text.extend(exit());
+        let (mut processor, _) = processor(config, "", &text, &[]);
+
+        assert_eq!(run(&mut processor).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(processor.registers.get(REG_A1), 11);
+    }
+
+    #[test]
+    fn instruction_limit() {
+        let config = Config {
+            max_instructions: Some(2),
+            ..Config::default()
+        };
+        let (mut processor, _) = processor(config, "", &overflow(), &[]);
+
+        assert_eq!(
+            run(&mut processor).unwrap(),
+            StepOutcome::LimitReached(Limit::Instructions)
+        );
+        assert_eq!(processor.instruction_count, 2);
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 8);
+    }
+
+    #[test]
+    fn timeout() {
+        let config = Config {
+            timeout: Some(Duration::from_millis(10)),
+            ..Config::default()
+        };
+        let infinite_loop = i_type(OP_BEQ, 0, 0, (-1i16) as u16);
+        let (mut processor, _) = processor(config, "", &[infinite_loop], &[]);
+
+        assert_eq!(
+            run(&mut processor).unwrap(),
+            StepOutcome::LimitReached(Limit::Timeout)
+        );
+        assert_eq!(processor.program_counter, TEXT_OFFSET);
+    }
+
+    #[test]
+    fn sleep_is_cut_short_by_the_timeout() {
+        let config = Config {
+            timeout: Some(Duration::from_millis(10)),
+            ..Config::default()
+        };
+        let mut text = li(REG_A0, u32::MAX).to_vec();
+        text.extend(li(REG_V0, SYSCALL_SLEEP));
+        text.push(SYSCALL);
+        text.extend(exit());
+        let (mut processor, _) = processor(config, "", &text, &[]);
+
+        let start_time = Instant::now();
+        assert_eq!(
+            run(&mut processor).unwrap(),
+            StepOutcome::LimitReached(Limit::Timeout)
+        );
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+        // The sleep finished, but nothing after it ran
+        assert_eq!(processor.program_counter, TEXT_OFFSET + 20);
     }
 }
@@ -0,0 +1,98 @@
+//! A random number generator for the random syscalls. It uses the same
+//! algorithm as `java.util.Random`, so seeded programs produce the same numbers
+//! as they do in MARS.
+
+const MULTIPLIER: u64 = 0x5_DEEC_E66D;
+const ADDEND: u64 = 0xB;
+const MASK: u64 = (1 << 48) - 1;
+
+#[derive(Debug)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: (seed ^ MULTIPLIER) & MASK,
+        }
+    }
+
+    /// Create a generator which continues from the state of another generator
+    pub fn from_state(state: u64) -> Self {
+        Self {
+            state: state & MASK,
+        }
+    }
+
+    /// Get the generator's state
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Generate the next `bits` random bits
+    fn next(&mut self, bits: u32) -> i32 {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(ADDEND) & MASK;
+
+        (self.state >> (48 - bits)) as i32
+    }
+
+    /// Generate a random integer
+    pub fn next_int(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    /// Generate a random integer in the range `[0, bound)`. The bound must be
+    /// positive.
+    pub fn next_int_bounded(&mut self, bound: i32) -> i32 {
+        assert!(bound > 0, "The bound must be positive");
+
+        if (bound & -bound) == bound {
+            // The bound is a power of two
+            return ((bound as i64 * self.next(31) as i64) >> 31) as i32;
+        }
+
+        // Reject values from the last partial range, so each value is equally
+        // likely
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+
+            if bits.wrapping_sub(value).wrapping_add(bound - 1) >= 0 {
+                return value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    // The expected values are from java.util.Random
+
+    #[test]
+    fn next_int() {
+        let mut random = Random::new(42);
+        assert_eq!(random.next_int(), -1170105035);
+        assert_eq!(random.next_int(), 234785527);
+    }
+
+    #[test]
+    fn next_int_bounded() {
+        let mut random = Random::new((-1i64) as u64);
+        assert_eq!(random.next_int_bounded(10), 3);
+        assert_eq!(random.next_int_bounded(16), 7);
+        assert_eq!(random.next_int_bounded(1_000_000_007), 26349579);
+        assert_eq!(random.next_int_bounded(i32::MAX), 1176895439);
+    }
+
+    #[test]
+    fn continue_from_the_state() {
+        let mut random = Random::new(42);
+        random.next_int();
+        let mut copy = Random::from_state(random.state());
+
+        assert_eq!(copy.next_int(), 234785527);
+    }
+}
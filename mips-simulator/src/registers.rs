@@ -1,5 +1,5 @@
 /// Holds the processor's registers
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Registers {
     registers: [u32; 32],
     pub lo_register: u32,
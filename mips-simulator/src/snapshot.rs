@@ -0,0 +1,625 @@
+//! Snapshots of the processor's state, which can be saved to a file and
+//! restored later (ex. to resume a long run, or to start a program which is
+//! already paused at an interesting point).
+//!
+//! Snapshot files are big endian, and hold (in order):
+//! - The magic number (`SNAPSHOT_MAGIC`) and version (`SNAPSHOT_VERSION`)
+//! - The config, except for the file sandbox (which is a host path) and the
+//!   pipeline timing, cache, and branch prediction models (which are chosen
+//!   each run). Parsed snapshots don't have any models.
+//! - The program counters, delay slot flag, pending load, run state, instruction count, and heap bounds
+//! - The registers (general purpose, HI/LO, CP0, and CP1)
+//! - The random number generators
+//! - The memory map's segments
+//! - The symbols of the loaded modules
+//! - The memory pages which aren't all zeros
+//!
+//! Open files and mapped devices are not saved.
+
+use crate::config::Config;
+use crate::cp0::Coprocessor0;
+use crate::cp1::Coprocessor1;
+use crate::files::FileTable;
+use crate::load_delay::{LoadRegister, PendingLoad};
+use crate::memory_map::{MemoryMap, Permissions, Segment};
+use crate::processor::Processor;
+use crate::random::Random;
+use crate::registers::Registers;
+use crate::symbols::{Symbol, SymbolTable};
+use std::convert::TryInto;
+use std::io;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// "MSNP"
+pub const SNAPSHOT_MAGIC: u32 = 0x4D53_4E50;
+pub const SNAPSHOT_VERSION: u16 = 4;
+
+// Config flags
+const CONFIG_DELAY_SLOTS: u32 = 0x1;
+const CONFIG_UNALIGNED_ACCESS: u32 = 0x2;
+const CONFIG_MAPPED_IO: u32 = 0x4;
+const CONFIG_NO_MEMORY_PROTECTION: u32 = 0x8;
+const CONFIG_LOAD_DELAY_SLOTS: u32 = 0x10;
+const CONFIG_WARN_LOAD_DELAY_SLOTS: u32 = 0x20;
+
+// Pending load register kinds
+const LOAD_REGISTER_GENERAL: u8 = 0;
+const LOAD_REGISTER_FLOAT: u8 = 1;
+
+// Segment permission flags
+const PERMISSION_READ: u8 = 0x1;
+const PERMISSION_WRITE: u8 = 0x2;
+const PERMISSION_EXECUTE: u8 = 0x4;
+
+/// The names of the segments the processor creates. Segment names are static,
+/// so restored segments use these names.
+const SEGMENT_NAMES: &[&str] = &[
+    "stack", "heap", "mmio", ".text", ".rdata", ".data", ".sdata", ".sbss", ".bss", ".ktext",
+    ".kdata", ".kbss",
+];
+
+/// The state of a processor
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// The config of the processor. It can be changed before restoring the
+    /// snapshot (ex. to raise the instruction limit). The file sandbox is not
+    /// saved, so it is only set if the snapshot was not parsed from a file.
+    pub config: Config,
+    registers: Registers,
+    cp0: Coprocessor0,
+    cp1: Coprocessor1,
+    program_counter: u32,
+    next_program_counter: u32,
+    /// If the next instruction is in a delay slot
+    in_delay_slot: bool,
+    /// The load whose delay slot is the next instruction
+    pending_load: Option<PendingLoad>,
+    running: bool,
+    return_code: i32,
+    instruction_count: u64,
+    kernel_loaded: bool,
+    heap_start: u32,
+    program_break: u32,
+    /// The state of each random number generator, by ID
+    random_generators: Vec<(u32, u64)>,
+    segments: Vec<Segment>,
+    symbols: Vec<Symbol>,
+    /// The start address and data of each page
+    pages: Vec<(u32, Vec<u8>)>,
+}
+
+impl Processor {
+    /// Save the processor's state
+    pub fn snapshot(&self) -> Snapshot {
+        let mut random_generators: Vec<(u32, u64)> = self
+            .random_generators
+            .iter()
+            .map(|(id, random)| (*id, random.state()))
+            .collect();
+        random_generators.sort_unstable();
+
+        Snapshot {
+            config: self.config.clone(),
+            registers: self.registers.clone(),
+            cp0: self.cp0.clone(),
+            cp1: self.cp1.clone(),
+            program_counter: self.program_counter,
+            next_program_counter: self.next_program_counter,
+            in_delay_slot: self.in_delay_slot,
+            pending_load: self.pending_load,
+            running: self.running,
+            return_code: self.return_code,
+            instruction_count: self.instruction_count,
+            kernel_loaded: self.kernel_loaded,
+            heap_start: self.heap_start,
+            program_break: self.program_break,
+            random_generators,
+            segments: self.memory_map.segments().to_vec(),
+            symbols: self.symbols.symbols().to_vec(),
+            pages: self
+                .memory
+                .pages()
+                .filter(|(_, data)| data.iter().any(|byte| *byte != 0))
+                .map(|(address, data)| (address, data.to_vec()))
+                .collect(),
+        }
+    }
+
+    /// Replace the processor's state and config with the snapshot. Open files
+    /// are closed, and mapped devices are kept. The pipeline timing, cache,
+    /// and branch prediction models are recreated from the snapshot's config,
+    /// so they start over (or are removed, for a parsed snapshot, unless its
+    /// config is given models first).
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.config = snapshot.config.clone();
+        self.create_models();
+        self.registers = snapshot.registers.clone();
+        self.cp0 = snapshot.cp0.clone();
+        self.cp1 = snapshot.cp1.clone();
+        self.program_counter = snapshot.program_counter;
+        self.next_program_counter = snapshot.next_program_counter;
+        self.in_delay_slot = snapshot.in_delay_slot;
+        self.pending_load = snapshot.pending_load;
+        self.load_delay_hazard = None;
+        self.running = snapshot.running;
+        self.return_code = snapshot.return_code;
+        self.instruction_count = snapshot.instruction_count;
+        self.start_time = None;
+        self.timed_out = false;
+        self.kernel_loaded = snapshot.kernel_loaded;
+        self.heap_start = snapshot.heap_start;
+        self.program_break = snapshot.program_break;
+        self.files = FileTable::new(self.config.file_sandbox.clone());
+        self.random_generators = snapshot
+            .random_generators
+            .iter()
+            .map(|(id, state)| (*id, Random::from_state(*state)))
+            .collect();
+
+        self.memory_map = MemoryMap::new();
+        for segment in &snapshot.segments {
+            self.memory_map.add_segment(
+                segment.name,
+                segment.start,
+                segment.size,
+                segment.permissions,
+            );
+        }
+        self.symbols = SymbolTable::from_symbols(snapshot.symbols.clone());
+
+        self.memory.clear();
+        for (address, data) in &snapshot.pages {
+            self.memory.load_into_memory(data, *address);
+        }
+    }
+}
+
+impl Snapshot {
+    /// Parse the input as a snapshot
+    pub fn parse<R: Read>(input: &mut R) -> io::Result<Self> {
+        if read_u32(input)? != SNAPSHOT_MAGIC {
+            return Err(invalid_data("Invalid magic number"));
+        }
+
+        if read_u16(input)? != SNAPSHOT_VERSION {
+            return Err(invalid_data("Unknown version number"));
+        }
+
+        let config_flags = read_u32(input)?;
+        let config = Config {
+            enable_delay_slots: config_flags & CONFIG_DELAY_SLOTS != 0,
+            enable_load_delay_slots: config_flags & CONFIG_LOAD_DELAY_SLOTS != 0,
+            warn_load_delay_slots: config_flags & CONFIG_WARN_LOAD_DELAY_SLOTS != 0,
+            allow_unaligned_access: config_flags & CONFIG_UNALIGNED_ACCESS != 0,
+            enable_mapped_io: config_flags & CONFIG_MAPPED_IO != 0,
+            disable_memory_protection: config_flags & CONFIG_NO_MEMORY_PROTECTION != 0,
+            heap_limit: read_option(input, read_u32)?,
+            file_sandbox: None,
+            random_seed: read_option(input, read_u64)?,
+            max_instructions: read_option(input, read_u64)?,
+            timeout: read_option(input, read_u64)?.map(Duration::from_nanos),
+            pipeline: None,
+            instruction_cache: None,
+            data_cache: None,
+            branch_prediction: None,
+        };
+
+        let program_counter = read_u32(input)?;
+        let next_program_counter = read_u32(input)?;
+        let in_delay_slot = read_bool(input)?;
+        let pending_load = read_option(input, read_pending_load)?;
+        let running = read_bool(input)?;
+        let return_code = read_u32(input)? as i32;
+        let instruction_count = read_u64(input)?;
+        let kernel_loaded = read_bool(input)?;
+        let heap_start = read_u32(input)?;
+        let program_break = read_u32(input)?;
+
+        let mut registers = Registers::new();
+        for register in 0..32 {
+            registers.set(register, read_u32(input)?);
+        }
+        registers.hi_register = read_u32(input)?;
+        registers.lo_register = read_u32(input)?;
+
+        let cp0 = Coprocessor0 {
+            status: read_u32(input)?,
+            cause: read_u32(input)?,
+            epc: read_u32(input)?,
+            bad_vaddr: read_u32(input)?,
+        };
+
+        let mut cp1 = Coprocessor1::new();
+        for register in 0..32 {
+            cp1.set(register, read_u32(input)?);
+        }
+        cp1.condition = read_bool(input)?;
+
+        let random_generators = (0..read_u32(input)?)
+            .map(|_| Ok((read_u32(input)?, read_u64(input)?)))
+            .collect::<io::Result<_>>()?;
+
+        let segments = (0..read_u32(input)?)
+            .map(|_| read_segment(input))
+            .collect::<io::Result<_>>()?;
+
+        let symbols = (0..read_u32(input)?)
+            .map(|_| read_symbol(input))
+            .collect::<io::Result<_>>()?;
+
+        let pages = (0..read_u32(input)?)
+            .map(|_| {
+                let address = read_u32(input)?;
+                let data = read_bytes(input)?;
+                Ok((address, data))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            config,
+            registers,
+            cp0,
+            cp1,
+            program_counter,
+            next_program_counter,
+            in_delay_slot,
+            pending_load,
+            running,
+            return_code,
+            instruction_count,
+            kernel_loaded,
+            heap_start,
+            program_break,
+            random_generators,
+            segments,
+            symbols,
+            pages,
+        })
+    }
+
+    /// Write the snapshot
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        output.write_all(&SNAPSHOT_MAGIC.to_be_bytes())?;
+        output.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+
+        let config = &self.config;
+        let mut config_flags = 0;
+        if config.enable_delay_slots {
+            config_flags |= CONFIG_DELAY_SLOTS;
+        }
+        if config.allow_unaligned_access {
+            config_flags |= CONFIG_UNALIGNED_ACCESS;
+        }
+        if config.enable_mapped_io {
+            config_flags |= CONFIG_MAPPED_IO;
+        }
+        if config.disable_memory_protection {
+            config_flags |= CONFIG_NO_MEMORY_PROTECTION;
+        }
+        if config.enable_load_delay_slots {
+            config_flags |= CONFIG_LOAD_DELAY_SLOTS;
+        }
+        if config.warn_load_delay_slots {
+            config_flags |= CONFIG_WARN_LOAD_DELAY_SLOTS;
+        }
+        output.write_all(&config_flags.to_be_bytes())?;
+        write_option(output, config.heap_limit, |output, limit| {
+            output.write_all(&limit.to_be_bytes())
+        })?;
+        for value in [
+            config.random_seed,
+            config.max_instructions,
+            config.timeout.map(|timeout| timeout.as_nanos() as u64),
+        ] {
+            write_option(output, value, |output, value| {
+                output.write_all(&value.to_be_bytes())
+            })?;
+        }
+
+        output.write_all(&self.program_counter.to_be_bytes())?;
+        output.write_all(&self.next_program_counter.to_be_bytes())?;
+        output.write_all(&[self.in_delay_slot as u8])?;
+        write_option(output, self.pending_load, write_pending_load)?;
+        output.write_all(&[self.running as u8])?;
+        output.write_all(&self.return_code.to_be_bytes())?;
+        output.write_all(&self.instruction_count.to_be_bytes())?;
+        output.write_all(&[self.kernel_loaded as u8])?;
+        output.write_all(&self.heap_start.to_be_bytes())?;
+        output.write_all(&self.program_break.to_be_bytes())?;
+
+        for register in 0..32 {
+            output.write_all(&self.registers.get(register).to_be_bytes())?;
+        }
+        output.write_all(&self.registers.hi_register.to_be_bytes())?;
+        output.write_all(&self.registers.lo_register.to_be_bytes())?;
+
+        for value in [
+            self.cp0.status,
+            self.cp0.cause,
+            self.cp0.epc,
+            self.cp0.bad_vaddr,
+        ] {
+            output.write_all(&value.to_be_bytes())?;
+        }
+
+        for register in 0..32 {
+            output.write_all(&self.cp1.get(register).to_be_bytes())?;
+        }
+        output.write_all(&[self.cp1.condition as u8])?;
+
+        output.write_all(&(self.random_generators.len() as u32).to_be_bytes())?;
+        for (id, state) in &self.random_generators {
+            output.write_all(&id.to_be_bytes())?;
+            output.write_all(&state.to_be_bytes())?;
+        }
+
+        output.write_all(&(self.segments.len() as u32).to_be_bytes())?;
+        for segment in &self.segments {
+            write_segment(output, segment)?;
+        }
+
+        output.write_all(&(self.symbols.len() as u32).to_be_bytes())?;
+        for symbol in &self.symbols {
+            write_symbol(output, symbol)?;
+        }
+
+        output.write_all(&(self.pages.len() as u32).to_be_bytes())?;
+        for (address, data) in &self.pages {
+            output.write_all(&address.to_be_bytes())?;
+            write_bytes(output, data)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_segment<R: Read>(input: &mut R) -> io::Result<Segment> {
+    let name = read_string(input)?;
+    let name = SEGMENT_NAMES
+        .iter()
+        .find(|known_name| **known_name == name)
+        .ok_or_else(|| invalid_data("Unknown segment name"))?;
+    let start = read_u32(input)?;
+    let size = read_u32(input)?;
+    let permissions = read_u8(input)?;
+
+    Ok(Segment {
+        name,
+        start,
+        size,
+        permissions: Permissions {
+            read: permissions & PERMISSION_READ != 0,
+            write: permissions & PERMISSION_WRITE != 0,
+            execute: permissions & PERMISSION_EXECUTE != 0,
+        },
+    })
+}
+
+fn write_segment<W: Write>(output: &mut W, segment: &Segment) -> io::Result<()> {
+    let permissions = &segment.permissions;
+    let permission_flags = (permissions.read as u8 * PERMISSION_READ)
+        | (permissions.write as u8 * PERMISSION_WRITE)
+        | (permissions.execute as u8 * PERMISSION_EXECUTE);
+
+    write_bytes(output, segment.name.as_bytes())?;
+    output.write_all(&segment.start.to_be_bytes())?;
+    output.write_all(&segment.size.to_be_bytes())?;
+    output.write_all(&[permission_flags])
+}
+
+fn read_symbol<R: Read>(input: &mut R) -> io::Result<Symbol> {
+    Ok(Symbol {
+        name: read_string(input)?,
+        address: read_u32(input)?,
+        global: read_bool(input)?,
+        section_end: read_u32(input)?,
+    })
+}
+
+fn write_symbol<W: Write>(output: &mut W, symbol: &Symbol) -> io::Result<()> {
+    write_bytes(output, symbol.name.as_bytes())?;
+    output.write_all(&symbol.address.to_be_bytes())?;
+    output.write_all(&[symbol.global as u8])?;
+    output.write_all(&symbol.section_end.to_be_bytes())
+}
+
+fn read_pending_load<R: Read>(input: &mut R) -> io::Result<PendingLoad> {
+    let program_counter = read_u32(input)?;
+    let register = match (read_u8(input)?, read_u8(input)?) {
+        (LOAD_REGISTER_GENERAL, register) if register < 32 => LoadRegister::General(register),
+        (LOAD_REGISTER_FLOAT, register) if register < 32 => LoadRegister::Float(register),
+        _ => return Err(invalid_data("Invalid pending load register")),
+    };
+    let value = read_u32(input)?;
+
+    Ok(PendingLoad {
+        program_counter,
+        register,
+        value,
+    })
+}
+
+fn write_pending_load<W: Write>(output: &mut W, pending_load: PendingLoad) -> io::Result<()> {
+    let (kind, register) = match pending_load.register {
+        LoadRegister::General(register) => (LOAD_REGISTER_GENERAL, register),
+        LoadRegister::Float(register) => (LOAD_REGISTER_FLOAT, register),
+    };
+
+    output.write_all(&pending_load.program_counter.to_be_bytes())?;
+    output.write_all(&[kind, register])?;
+    output.write_all(&pending_load.value.to_be_bytes())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    input.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut buffer = [0; 2];
+    input.read_exact(&mut buffer)?;
+    Ok(u16::from_be_bytes(buffer))
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    input.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    input.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+}
+
+fn read_bool<R: Read>(input: &mut R) -> io::Result<bool> {
+    Ok(read_u8(input)? != 0)
+}
+
+/// Read a length (u32) prefixed byte array
+fn read_bytes<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let length = read_u32(input)?;
+    let mut bytes = Vec::new();
+    input.take(length as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != length as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+/// Read a length (u32) prefixed UTF-8 string
+fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    String::from_utf8(read_bytes(input)?).map_err(|_| invalid_data("Invalid string"))
+}
+
+/// Read a value which is preceded by a byte that says if it is present
+fn read_option<R: Read, T>(
+    input: &mut R,
+    read: impl FnOnce(&mut R) -> io::Result<T>,
+) -> io::Result<Option<T>> {
+    if read_bool(input)? {
+        read(input).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Write a length (u32) prefixed byte array
+fn write_bytes<W: Write>(output: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let length: u32 = bytes
+        .len()
+        .try_into()
+        .map_err(|_| invalid_data("Data is too long"))?;
+    output.write_all(&length.to_be_bytes())?;
+    output.write_all(bytes)
+}
+
+/// Write a value preceded by a byte that says if it is present
+fn write_option<W: Write, T>(
+    output: &mut W,
+    value: Option<T>,
+    write: impl FnOnce(&mut W, T) -> io::Result<()>,
+) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            output.write_all(&[1])?;
+            write(output, value)
+        }
+        None => output.write_all(&[0]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::cache::CacheConfig;
+    use crate::config::Config;
+    use crate::processor::StepOutcome;
+    use crate::test_util::{exit, i_type, li, module, processor, run, SYSCALL};
+    use mips_types::constants::*;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    /// Load the word in the data section, print it, then exit
+    fn print_data_word() -> Vec<u32> {
+        let mut text = Vec::new();
+        text.extend(li(REG_A0, DATA_OFFSET));
+        text.push(i_type(OP_LW, REG_A0, REG_A0, 0));
+        text.extend(li(REG_V0, SYSCALL_PRINT_INT));
+        text.push(SYSCALL);
+        text.extend(exit());
+        text
+    }
+
+    fn write_and_parse(snapshot: &Snapshot) -> Snapshot {
+        let mut data = Vec::new();
+        snapshot.write(&mut data).unwrap();
+        Snapshot::parse(&mut Cursor::new(data)).unwrap()
+    }
+
+    #[test]
+    fn resume_from_a_parsed_snapshot() {
+        let text = print_data_word();
+        let (mut original, _) = processor(Config::default(), "", &text, &1234u32.to_be_bytes());
+        original
+            .symbols
+            .add_module(&module(&text, &[], &[("main", 0), ("print", 12)]));
+        for _ in 0..3 {
+            original.step().unwrap();
+        }
+        let snapshot = write_and_parse(&original.snapshot());
+
+        let (mut restored, output) = processor(Config::default(), "", &[], &[]);
+        restored.restore(&snapshot);
+        assert_eq!(restored.program_counter, TEXT_OFFSET + 12);
+        assert_eq!(restored.symbols().symbols(), original.symbols().symbols());
+        assert_eq!(restored.symbols().address("print"), Some(TEXT_OFFSET + 12));
+
+        assert_eq!(run(&mut restored).unwrap(), StepOutcome::Exited(0));
+        assert_eq!(output.contents(), b"1234");
+    }
+
+    #[test]
+    fn file_sandbox_is_not_saved() {
+        let sandbox = PathBuf::from("/host/path/to/sandbox");
+        let config = Config {
+            file_sandbox: Some(sandbox.clone()),
+            ..Config::default()
+        };
+        let (processor, _) = processor(config, "", &print_data_word(), &[]);
+        let snapshot = processor.snapshot();
+        assert_eq!(snapshot.config.file_sandbox, Some(sandbox));
+
+        let mut data = Vec::new();
+        snapshot.write(&mut data).unwrap();
+        let path = b"/host/path/to/sandbox";
+        assert!(!data.windows(path.len()).any(|window| window == path));
+        assert_eq!(write_and_parse(&snapshot).config.file_sandbox, None);
+    }
+
+    #[test]
+    fn restore_creates_models_from_the_config() {
+        let text = print_data_word();
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 4]);
+        let mut snapshot = processor.snapshot();
+        snapshot.config.data_cache = Some(CacheConfig::new(64, 16, 1));
+
+        processor.restore(&snapshot);
+        run(&mut processor).unwrap();
+        let report = processor.data_cache_report().unwrap();
+        assert_eq!(report.reads, 1);
+
+        snapshot.config.data_cache = None;
+        processor.restore(&snapshot);
+        assert!(processor.data_cache_report().is_none());
+    }
+}
@@ -0,0 +1,525 @@
+//! Execution statistics: how many times each instruction was executed, the
+//! kinds of instructions, branches, memory traffic, and time spent in each
+//! function.
+
+use crate::instruction::Instruction;
+use crate::memory_map::Access;
+use crate::processor::Processor;
+use mips_types::constants::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fmt::{Display, Formatter, Write};
+
+/// A kind of instruction
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub enum InstructionClass {
+    /// Arithmetic, logic, shifts, and moves between registers
+    Alu,
+    Load,
+    Store,
+    /// Conditional branches
+    Branch,
+    /// Unconditional jumps
+    Jump,
+    Syscall,
+    /// Floating point operations (except for loads, stores, and branches)
+    Float,
+    /// Breaks, coprocessor 0 instructions, and unknown instructions
+    Other,
+}
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 8] = [
+        InstructionClass::Alu,
+        InstructionClass::Load,
+        InstructionClass::Store,
+        InstructionClass::Branch,
+        InstructionClass::Jump,
+        InstructionClass::Syscall,
+        InstructionClass::Float,
+        InstructionClass::Other,
+    ];
+
+    /// Get the class of the instruction
+    pub(crate) fn of(instruction: Instruction) -> Self {
+        match instruction.op_code() {
+            OP_R_TYPE => match instruction.function() {
+                FUNCTION_SYSCALL => InstructionClass::Syscall,
+                FUNCTION_BREAK => InstructionClass::Other,
+                FUNCTION_JR | FUNCTION_JALR => InstructionClass::Jump,
+                _ => InstructionClass::Alu,
+            },
+            OP_BCOND | OP_BEQ | OP_BNE | OP_BLEZ | OP_BGTZ => InstructionClass::Branch,
+            OP_J | OP_JAL => InstructionClass::Jump,
+            OP_ADDI..=OP_LUI => InstructionClass::Alu,
+            OP_COP1 if instruction.s_register() == COP1_RS_BC => InstructionClass::Branch,
+            OP_COP1 => InstructionClass::Float,
+            OP_LB | OP_LH | OP_LWL | OP_LW | OP_LBU | OP_LHU | OP_LWR | OP_LWC1 => {
+                InstructionClass::Load
+            }
+            OP_SB | OP_SH | OP_SWL | OP_SW | OP_SWR | OP_SWC1 => InstructionClass::Store,
+            _ => InstructionClass::Other,
+        }
+    }
+
+    /// Get the name of the class, as used in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionClass::Alu => "alu",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Branch => "branch",
+            InstructionClass::Jump => "jump",
+            InstructionClass::Syscall => "syscall",
+            InstructionClass::Float => "float",
+            InstructionClass::Other => "other",
+        }
+    }
+}
+
+/// The raw statistics collected while executing
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Statistics {
+    /// The number of times each instruction was executed, by address and
+    /// instruction word (the word is kept in case the code is overwritten)
+    executions: HashMap<(u32, u32), u64>,
+    /// The number of taken and not taken branches, by address
+    branches: HashMap<u32, (u64, u64)>,
+    /// The number of calls (jal/jalr) to each address
+    calls: HashMap<u32, u64>,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+impl Processor {
+    /// Start collecting execution statistics. This also records the memory
+    /// accesses made by each instruction (see `memory_accesses`).
+    pub fn enable_statistics(&mut self) {
+        self.record_memory_accesses();
+        self.statistics.get_or_insert_with(Default::default);
+    }
+
+    /// Record an executed instruction in the statistics. `executed` is false
+    /// if the instruction raised an exception instead.
+    pub(crate) fn record_statistics(
+        &mut self,
+        program_counter: u32,
+        instruction: Instruction,
+        executed: bool,
+    ) {
+        let control_transfer = self.control_transfer(program_counter);
+        let memory_accesses = self.memory_accesses.as_deref().unwrap_or(&[]);
+        let statistics = match &mut self.statistics {
+            Some(statistics) => statistics,
+            None => return,
+        };
+
+        *statistics
+            .executions
+            .entry((program_counter, instruction.0))
+            .or_insert(0) += 1;
+
+        for memory_access in memory_accesses {
+            match memory_access.access {
+                Access::Read => statistics.bytes_read += memory_access.size as u64,
+                Access::Write => statistics.bytes_written += memory_access.size as u64,
+                Access::Execute => {}
+            }
+        }
+
+        if !executed {
+            return;
+        }
+
+        match InstructionClass::of(instruction) {
+            InstructionClass::Branch => {
+                let counts = statistics.branches.entry(program_counter).or_insert((0, 0));
+                if self.branch_taken {
+                    counts.0 += 1;
+                } else {
+                    counts.1 += 1;
+                }
+            }
+            InstructionClass::Jump
+                if instruction.op_code() == OP_JAL
+                    || instruction.op_code() == OP_R_TYPE
+                        && instruction.function() == FUNCTION_JALR =>
+            {
+                if let Some(destination) = control_transfer {
+                    *statistics.calls.entry(destination).or_insert(0) += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Summarize the statistics collected so far, if they are enabled
+    pub fn statistics_report(&self) -> Option<StatisticsReport> {
+        let statistics = self.statistics.as_ref()?;
+        let mut report = StatisticsReport {
+            instructions: 0,
+            classes: InstructionClass::ALL
+                .iter()
+                .map(|class| (*class, 0))
+                .collect(),
+            mnemonics: Vec::new(),
+            branches_taken: 0,
+            branches_not_taken: 0,
+            bytes_read: statistics.bytes_read,
+            bytes_written: statistics.bytes_written,
+            functions: Vec::new(),
+        };
+
+        // Functions start at the global labels in executable segments, the
+        // targets of calls, and the exception handler
+        let mut function_starts: BTreeSet<u32> = self
+            .symbols
+            .symbols()
+            .iter()
+            .filter(|symbol| symbol.global && self.is_executable(symbol.address))
+            .map(|symbol| symbol.address)
+            .collect();
+        function_starts.extend(statistics.calls.keys());
+        if self.kernel_loaded {
+            function_starts.insert(EXCEPTION_VECTOR);
+        }
+
+        let mut mnemonics: HashMap<String, u64> = HashMap::new();
+        let mut functions: HashMap<Option<u32>, u64> = HashMap::new();
+        for (&(program_counter, word), &count) in &statistics.executions {
+            let instruction = Instruction(word);
+            let text = instruction.stringify(program_counter);
+            let mnemonic = text.split(' ').next().unwrap_or_default();
+            let function = function_starts.range(..=program_counter).next_back();
+
+            report.instructions += count;
+            report.classes[InstructionClass::of(instruction) as usize].1 += count;
+            *mnemonics.entry(mnemonic.to_string()).or_insert(0) += count;
+            *functions.entry(function.copied()).or_insert(0) += count;
+        }
+
+        for (taken, not_taken) in statistics.branches.values() {
+            report.branches_taken += taken;
+            report.branches_not_taken += not_taken;
+        }
+
+        report.mnemonics = mnemonics.into_iter().collect();
+        report
+            .mnemonics
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        report.functions = functions
+            .into_iter()
+            .map(|(address, instructions)| FunctionStatistics {
+                name: match address {
+                    Some(address) => match self.symbols.symbol_at(address) {
+                        Some(symbol) => symbol.name.clone(),
+                        None => format!("0x{:08x}", address),
+                    },
+                    None => "(unknown)".to_string(),
+                },
+                address,
+                instructions,
+                calls: address
+                    .and_then(|address| statistics.calls.get(&address))
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect();
+        report.functions.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then_with(|| a.address.cmp(&b.address))
+        });
+
+        Some(report)
+    }
+
+    /// Check if the address is in an executable segment
+    fn is_executable(&self, address: u32) -> bool {
+        self.memory_map
+            .find_segment(address)
+            .is_some_and(|segment| segment.permissions.execute)
+    }
+}
+
+/// A summary of the execution statistics
+#[derive(Clone, Debug)]
+pub struct StatisticsReport {
+    /// The number of instructions executed while statistics were enabled
+    pub instructions: u64,
+    /// The number of instructions executed in each class, in the order of
+    /// `InstructionClass::ALL`
+    pub classes: Vec<(InstructionClass, u64)>,
+    /// The number of times each mnemonic was executed, most frequent first
+    pub mnemonics: Vec<(String, u64)>,
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    /// The number of bytes read by loads and syscalls
+    pub bytes_read: u64,
+    /// The number of bytes written by stores and syscalls
+    pub bytes_written: u64,
+    /// The instructions executed in each function, most frequent first
+    pub functions: Vec<FunctionStatistics>,
+}
+
+/// The instructions executed in a function, which includes everything from
+/// its start address up to the next function
+#[derive(Clone, Debug)]
+pub struct FunctionStatistics {
+    /// The function's symbol, or its address if it has no symbol
+    pub name: String,
+    /// The function's start address, or None for instructions before the
+    /// first known function
+    pub address: Option<u32>,
+    pub instructions: u64,
+    /// The number of times the function was called (with jal or jalr)
+    pub calls: u64,
+}
+
+impl StatisticsReport {
+    /// Format the report as a JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"instructions\":{},\"classes\":{{",
+            self.instructions
+        )
+        .unwrap();
+        for (i, (class, count)) in self.classes.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(json, "{}\"{}\":{}", separator, class.name(), count).unwrap();
+        }
+
+        write!(
+            json,
+            "}},\"branches\":{{\"taken\":{},\"not_taken\":{}}},\
+             \"memory\":{{\"bytes_read\":{},\"bytes_written\":{}}},\"mnemonics\":{{",
+            self.branches_taken, self.branches_not_taken, self.bytes_read, self.bytes_written
+        )
+        .unwrap();
+        for (i, (mnemonic, count)) in self.mnemonics.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(json, "{}{}:{}", separator, json_string(mnemonic), count).unwrap();
+        }
+
+        json.push_str("},\"functions\":[");
+        for (i, function) in self.functions.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let address = match function.address {
+                Some(address) => address.to_string(),
+                None => "null".to_string(),
+            };
+            write!(
+                json,
+                "{}{{\"name\":{},\"address\":{},\"instructions\":{},\"calls\":{}}}",
+                separator,
+                json_string(&function.name),
+                address,
+                function.instructions,
+                function.calls
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+
+        json
+    }
+}
+
+impl Display for StatisticsReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let percent = |count: u64| {
+            if self.instructions == 0 {
+                0.0
+            } else {
+                100.0 * count as f64 / self.instructions as f64
+            }
+        };
+
+        writeln!(f, "Instructions executed: {}", self.instructions)?;
+        writeln!(f, "\nBy class:")?;
+        for (class, count) in &self.classes {
+            write!(
+                f,
+                "  {:<10} {:>12} {:>6.2}%",
+                class.name(),
+                count,
+                percent(*count)
+            )?;
+            if *class == InstructionClass::Branch {
+                write!(
+                    f,
+                    " (taken {}, not taken {})",
+                    self.branches_taken, self.branches_not_taken
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(
+            f,
+            "\nMemory: {} bytes read, {} bytes written",
+            self.bytes_read, self.bytes_written
+        )?;
+
+        writeln!(f, "\nBy mnemonic:")?;
+        for (mnemonic, count) in &self.mnemonics {
+            writeln!(
+                f,
+                "  {:<10} {:>12} {:>6.2}%",
+                mnemonic,
+                count,
+                percent(*count)
+            )?;
+        }
+
+        writeln!(f, "\nBy function:")?;
+        for function in &self.functions {
+            let address = match function.address {
+                Some(address) => format!("0x{:08x}", address),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<24} {:>10} {:>12} {:>6.2}% {:>8} calls",
+                function.name,
+                address,
+                function.instructions,
+                percent(function.instructions),
+                function.calls
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Quote and escape a string for JSON
+pub(crate) fn json_string(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_string, InstructionClass};
+    use crate::config::Config;
+    use crate::instruction::Instruction;
+    use crate::test_util::{exit, i_type, li, module, processor, r_type, run};
+    use mips_types::constants::*;
+
+    #[test]
+    fn classify_instructions() {
+        for (instruction, class) in [
+            (
+                r_type(FUNCTION_ADDU, REG_A0, REG_A1, REG_A1),
+                InstructionClass::Alu,
+            ),
+            (i_type(OP_LUI, REG_A0, 0, 1), InstructionClass::Alu),
+            (i_type(OP_LBU, REG_A0, REG_A1, 0), InstructionClass::Load),
+            (i_type(OP_SWR, REG_A0, REG_A1, 0), InstructionClass::Store),
+            (i_type(OP_BCOND, 0, REG_A1, 0), InstructionClass::Branch),
+            (
+                r_type(FUNCTION_JALR, REG_RA, REG_A0, 0),
+                InstructionClass::Jump,
+            ),
+            ((OP_J as u32) << 26, InstructionClass::Jump),
+            (r_type(FUNCTION_SYSCALL, 0, 0, 0), InstructionClass::Syscall),
+            (r_type(FUNCTION_BREAK, 0, 0, 0), InstructionClass::Other),
+        ] {
+            assert_eq!(InstructionClass::of(Instruction(instruction)), class);
+        }
+    }
+
+    #[test]
+    fn quote_json_strings() {
+        assert_eq!(json_string("main+0x4"), "\"main+0x4\"");
+        assert_eq!(json_string("\"\\\n\t"), "\"\\\"\\\\\\u000a\\u0009\"");
+    }
+
+    #[test]
+    fn statistics_report() {
+        let mut text = li(REG_A0, 2).to_vec();
+        // Count $a0 down to 0
+        text.push(i_type(OP_ADDIU, REG_A0, REG_A0, 0xffff));
+        text.push(i_type(OP_BNE, 0, REG_A0, (-2i16) as u16));
+        let call = text.len();
+        text.push(0);
+        text.extend(exit());
+        // A function which loads a word
+        let function = text.len() as u32 * 4;
+        text[call] = (OP_JAL as u32) << 26 | ((TEXT_OFFSET + function) & 0x0fff_ffff) >> 2;
+        text.extend(li(REG_A0, DATA_OFFSET));
+        text.push(i_type(OP_LW, REG_A1, REG_A0, 0));
+        text.push(i_type(OP_SH, REG_A1, REG_A0, 4));
+        text.push(r_type(FUNCTION_JR, 0, REG_RA, 0));
+
+        let (mut processor, _) = processor(Config::default(), "", &text, &[0; 8]);
+        processor
+            .symbols
+            .add_module(&module(&text, &[], &[("main", 0), ("load", function)]));
+        processor.enable_statistics();
+        run(&mut processor).unwrap();
+
+        let report = processor.statistics_report().unwrap();
+        assert_eq!(report.instructions, processor.instruction_count);
+        assert_eq!(report.instructions, 2 + 2 * 2 + 1 + 3 + 5);
+        let class_count = |class| report.classes[class as usize].1;
+        assert_eq!(class_count(InstructionClass::Alu), 2 + 2 + 2 + 2);
+        assert_eq!(class_count(InstructionClass::Branch), 2);
+        assert_eq!(class_count(InstructionClass::Jump), 2);
+        assert_eq!(class_count(InstructionClass::Load), 1);
+        assert_eq!(class_count(InstructionClass::Store), 1);
+        assert_eq!(class_count(InstructionClass::Syscall), 1);
+        assert_eq!(report.branches_taken, 1);
+        assert_eq!(report.branches_not_taken, 1);
+        assert_eq!(report.bytes_read, 4);
+        assert_eq!(report.bytes_written, 2);
+        // Ties are sorted by name
+        let mnemonics = &report.mnemonics;
+        assert_eq!(mnemonics[0], ("lui".to_string(), 3));
+        assert_eq!(mnemonics[1], ("ori".to_string(), 3));
+        assert_eq!(mnemonics[2], ("addiu".to_string(), 2));
+
+        let functions: Vec<_> = report
+            .functions
+            .iter()
+            .map(|function| {
+                (
+                    function.name.as_str(),
+                    function.instructions,
+                    function.calls,
+                )
+            })
+            .collect();
+        assert_eq!(functions, [("main", 10, 0), ("load", 5, 1)]);
+    }
+
+    #[test]
+    fn branch_to_the_fall_through_address() {
+        // Branches whose target is the instruction after them, one taken and
+        // one not taken
+        let mut text = vec![i_type(OP_BEQ, 0, 0, 0), i_type(OP_BNE, 0, 0, 0)];
+        text.extend(exit());
+        let (mut processor, _) = processor(Config::default(), "", &text, &[]);
+        processor.enable_statistics();
+        run(&mut processor).unwrap();
+
+        let report = processor.statistics_report().unwrap();
+        assert_eq!(report.branches_taken, 1);
+        assert_eq!(report.branches_not_taken, 1);
+    }
+}
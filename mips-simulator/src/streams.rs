@@ -0,0 +1,242 @@
+//! The input and output streams used by the syscalls
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+/// The program's standard input, output, and error streams
+pub struct Streams {
+    /// The input, which is sent to a background thread if it is shared with a
+    /// device
+    pub input: Box<dyn Read + Send>,
+    pub output: Box<dyn Write>,
+    pub error: Box<dyn Write>,
+}
+
+impl Streams {
+    pub fn new(input: Box<dyn Read + Send>, output: Box<dyn Write>, error: Box<dyn Write>) -> Self {
+        Self {
+            input,
+            output,
+            error,
+        }
+    }
+
+    /// Create streams connected to stdin, stdout, and stderr
+    pub fn stdio() -> Self {
+        Self::new(
+            Box::new(io::stdin()),
+            Box::new(io::stdout()),
+            Box::new(io::stderr()),
+        )
+    }
+
+    /// Share the input and output with something else which uses them (ex. a
+    /// memory-mapped console device). Reads and writes through the returned
+    /// handles are interleaved with the ones made through the streams. The
+    /// returned input can be polled without blocking.
+    pub fn share(&mut self) -> (PollingInput, Box<dyn Write>) {
+        let input = PollingInput::new(mem::replace(&mut self.input, Box::new(io::empty())));
+        let output = Shared::new(mem::replace(&mut self.output, Box::new(io::sink())));
+        self.input = Box::new(input.clone());
+        self.output = Box::new(output.clone());
+
+        (input, Box::new(output))
+    }
+
+    /// Read a line from the input, including the newline (if found)
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        let mut byte = [0];
+
+        while self.input.read(&mut byte)? != 0 {
+            line.push(byte[0]);
+
+            if byte[0] == b'\n' {
+                break;
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+impl Debug for Streams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Streams")
+    }
+}
+
+/// An in-memory output stream which can be read after it is given to the
+/// processor (ex. to check a program's output).
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer {
+    data: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a copy of the data written so far
+    pub fn contents(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream which is shared by several users
+struct Shared<T> {
+    stream: Rc<RefCell<T>>,
+}
+
+impl<T> Shared<T> {
+    fn new(stream: T) -> Self {
+        Self {
+            stream: Rc::new(RefCell::new(stream)),
+        }
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            stream: Rc::clone(&self.stream),
+        }
+    }
+}
+
+impl Write for Shared<Box<dyn Write>> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.borrow_mut().flush()
+    }
+}
+
+/// An input stream which can be checked for a byte without blocking. The
+/// source is read by a background thread, one byte at a time when a byte is
+/// requested, so nothing is read before the program asks for it. Clones share
+/// the same source.
+#[derive(Clone)]
+pub struct PollingInput {
+    state: Arc<Mutex<PollingState>>,
+}
+
+struct PollingState {
+    requests: Sender<()>,
+    bytes: Receiver<io::Result<Option<u8>>>,
+    /// If a byte has been requested but not received yet
+    pending: bool,
+    /// If the source has ended
+    ended: bool,
+}
+
+impl PollingInput {
+    pub fn new(mut source: Box<dyn Read + Send>) -> Self {
+        let (requests, request_receiver) = mpsc::channel();
+        let (byte_sender, bytes) = mpsc::channel();
+
+        // The thread stops when the input is dropped. It may be stuck reading
+        // until then, which doesn't keep the process from exiting.
+        thread::spawn(move || {
+            for () in request_receiver {
+                let mut byte = [0];
+                let result =
+                    source
+                        .read(&mut byte)
+                        .map(|count| if count == 0 { None } else { Some(byte[0]) });
+
+                if byte_sender.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            state: Arc::new(Mutex::new(PollingState {
+                requests,
+                bytes,
+                pending: false,
+                ended: false,
+            })),
+        }
+    }
+
+    /// Get the next byte if it has been received, without blocking. Returns
+    /// `None` if the byte hasn't arrived yet, or if the input has ended.
+    pub fn try_read_byte(&self) -> io::Result<Option<u8>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.request() {
+            return Ok(None);
+        }
+
+        match state.bytes.try_recv() {
+            Ok(result) => state.received(result),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => state.received(Ok(None)),
+        }
+    }
+}
+
+impl PollingState {
+    /// Request a byte from the thread, if one hasn't been requested already.
+    /// Returns false if the input has ended.
+    fn request(&mut self) -> bool {
+        if !self.ended && !self.pending {
+            self.pending = self.requests.send(()).is_ok();
+            self.ended = !self.pending;
+        }
+
+        !self.ended
+    }
+
+    /// Handle a byte received from the thread
+    fn received(&mut self, result: io::Result<Option<u8>>) -> io::Result<Option<u8>> {
+        self.pending = false;
+        if let Ok(None) = result {
+            self.ended = true;
+        }
+
+        result
+    }
+}
+
+impl Read for PollingInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if buf.is_empty() || !state.request() {
+            return Ok(0);
+        }
+
+        // Wait for the byte
+        let result = state.bytes.recv().unwrap_or(Ok(None));
+        match state.received(result)? {
+            Some(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+}
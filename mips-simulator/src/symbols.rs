@@ -0,0 +1,225 @@
+//! The labels defined by the loaded modules, used to show addresses as
+//! symbol+offset and to find addresses by name.
+
+use mips_types::module::{R2KModule, R2KSection};
+use std::str::from_utf8;
+
+/// A label and its address
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    /// If the label was declared with `.globl`
+    pub global: bool,
+    /// The end of the label's section. Addresses past it are not shown
+    /// relative to the label.
+    pub(crate) section_end: u32,
+}
+
+/// The symbols of the loaded modules, sorted by address
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the labels defined by the module. The module must already be
+    /// placed at its section offsets (ex. a load module).
+    pub fn add_module(&mut self, module: &R2KModule) {
+        for entry in &module.symbol_table {
+            if !entry.is_label() || !entry.has_definition() {
+                continue;
+            }
+
+            let section = entry.section();
+            let section_length = match section {
+                R2KSection::Text => module.text_section.len() as u32,
+                R2KSection::RData => module.rdata_section.len() as u32,
+                R2KSection::Data => module.data_section.len() as u32,
+                R2KSection::SData => module.sdata_section.len() as u32,
+                R2KSection::SBss => module.sbss_size,
+                R2KSection::Bss => module.bss_size,
+                _ => continue,
+            };
+            let section_start = match module.get_section_offset(section) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            let name = match get_str(&module.string_table, entry.str_idx) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            // Load modules linked by rlink hold addresses, while ours hold
+            // offsets into the sections. The sections are never big enough
+            // for an offset to reach the section's address.
+            let address = if entry.value >= section_start {
+                entry.value
+            } else {
+                section_start.wrapping_add(entry.value)
+            };
+
+            self.symbols.push(Symbol {
+                name: name.to_string(),
+                address,
+                global: entry.is_global(),
+                section_end: section_start.wrapping_add(section_length),
+            });
+        }
+
+        self.sort();
+    }
+
+    /// Create a symbol table with the symbols (ex. restored from a snapshot)
+    pub(crate) fn from_symbols(symbols: Vec<Symbol>) -> Self {
+        let mut symbol_table = Self { symbols };
+        symbol_table.sort();
+        symbol_table
+    }
+
+    /// Sort the symbols by address. Global symbols come first, so they are
+    /// preferred over local symbols at the same address.
+    fn sort(&mut self) {
+        self.symbols
+            .sort_by_key(|symbol| (symbol.address, !symbol.global));
+    }
+
+    /// Get the symbols, sorted by address
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Check if there are no symbols
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Get the address of the symbol with the name. Global symbols are
+    /// preferred over local symbols with the same name.
+    pub fn address(&self, name: &str) -> Option<u32> {
+        let mut matches = self.symbols.iter().filter(|symbol| symbol.name == name);
+        let first = matches.next()?;
+
+        Some(
+            std::iter::once(first)
+                .chain(matches)
+                .find(|symbol| symbol.global)
+                .unwrap_or(first)
+                .address,
+        )
+    }
+
+    /// Get the symbol which is exactly at the address
+    pub fn symbol_at(&self, address: u32) -> Option<&Symbol> {
+        let index = self
+            .symbols
+            .partition_point(|symbol| symbol.address < address);
+
+        self.symbols
+            .get(index)
+            .filter(|symbol| symbol.address == address)
+    }
+
+    /// Get the closest symbol at or before the address (in the same section),
+    /// and the address's offset from it
+    pub fn lookup(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let end = self
+            .symbols
+            .partition_point(|symbol| symbol.address <= address);
+        let closest = self.symbols[..end].last()?;
+        let symbol = self.symbol_at(closest.address)?;
+
+        if address < symbol.section_end {
+            Some((symbol, address - symbol.address))
+        } else {
+            None
+        }
+    }
+
+    /// Format the address as "symbol" or "symbol+offset" (with a hex offset),
+    /// if it has a symbol
+    pub fn describe(&self, address: u32) -> Option<String> {
+        self.lookup(address).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            _ => format!("{}+0x{:x}", symbol.name, offset),
+        })
+    }
+}
+
+/// Get the null-terminated string at the index of the string table
+fn get_str(string_table: &[u8], index: u32) -> Option<&str> {
+    let strings = string_table.get(index as usize..)?;
+    let length = strings.iter().position(|byte| *byte == 0)?;
+
+    from_utf8(&strings[..length]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Symbol, SymbolTable};
+    use crate::test_util::module;
+    use mips_types::constants::*;
+
+    fn symbol(name: &str, address: u32, global: bool) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            address,
+            global,
+            section_end: 0x100,
+        }
+    }
+
+    #[test]
+    fn add_module() {
+        let mut symbols = SymbolTable::new();
+        assert!(symbols.is_empty());
+        symbols.add_module(&module(&[0; 4], &[], &[("main", 0), ("loop", 8)]));
+
+        assert_eq!(symbols.address("main"), Some(TEXT_OFFSET));
+        assert_eq!(symbols.address("loop"), Some(TEXT_OFFSET + 8));
+        assert_eq!(symbols.address("missing"), None);
+        assert_eq!(symbols.symbols()[1].section_end, TEXT_OFFSET + 16);
+    }
+
+    #[test]
+    fn describe_addresses() {
+        let mut symbols = SymbolTable::new();
+        symbols.add_module(&module(&[0; 4], &[], &[("main", 0), ("loop", 8)]));
+
+        assert_eq!(symbols.describe(TEXT_OFFSET).as_deref(), Some("main"));
+        assert_eq!(
+            symbols.describe(TEXT_OFFSET + 4).as_deref(),
+            Some("main+0x4")
+        );
+        assert_eq!(
+            symbols.describe(TEXT_OFFSET + 12).as_deref(),
+            Some("loop+0x4")
+        );
+        // Addresses before the first symbol or past the section have none
+        assert_eq!(symbols.describe(TEXT_OFFSET - 4), None);
+        assert_eq!(symbols.describe(TEXT_OFFSET + 16), None);
+
+        assert_eq!(symbols.symbol_at(TEXT_OFFSET + 8).unwrap().name, "loop");
+        assert!(symbols.symbol_at(TEXT_OFFSET + 4).is_none());
+    }
+
+    #[test]
+    fn prefer_global_symbols() {
+        let symbols = SymbolTable::from_symbols(vec![
+            symbol("x", 0x30, true),
+            symbol("local", 0x10, false),
+            symbol("global", 0x10, true),
+            symbol("x", 0x20, false),
+        ]);
+
+        assert_eq!(symbols.describe(0x14).as_deref(), Some("global+0x4"));
+        assert_eq!(symbols.address("x"), Some(0x30));
+        assert_eq!(symbols.address("local"), Some(0x10));
+        let addresses: Vec<u32> = symbols.symbols().iter().map(|s| s.address).collect();
+        assert_eq!(addresses, [0x10, 0x10, 0x20, 0x30]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Helpers for running small programs in the unit tests
+
+use crate::config::Config;
+use crate::error::{ExecutionError, ExecutionErrorCause};
+use crate::instruction::Instruction;
+use crate::processor::{Processor, StepOutcome};
+use crate::streams::{SharedBuffer, Streams};
+use mips_types::constants::*;
+use mips_types::module::{
+    R2KModule, R2KModuleHeader, R2KSection, R2KSymbolEntry, DATA_INDEX, TEXT_INDEX,
+};
+use std::io;
+use std::io::Cursor;
+
+/// The syscall instruction
+pub const SYSCALL: u32 = FUNCTION_SYSCALL as u32;
+
+/// Encode an R-type instruction
+pub fn r_type(function: u8, rd: u8, rs: u8, rt: u8) -> u32 {
+    (rs as u32) << 21 | (rt as u32) << 16 | (rd as u32) << 11 | function as u32
+}
+
+/// Encode an I-type instruction
+pub fn i_type(op_code: u8, rt: u8, rs: u8, immediate: u16) -> u32 {
+    (op_code as u32) << 26 | (rs as u32) << 21 | (rt as u32) << 16 | immediate as u32
+}
+
+/// Load the value into the register (lui and ori)
+pub fn li(register: u8, value: u32) -> [u32; 2] {
+    [
+        i_type(OP_LUI, register, 0, (value >> 16) as u16),
+        i_type(OP_ORI, register, register, value as u16),
+    ]
+}
+
+/// Exit the program with code 0
+pub fn exit() -> [u32; 3] {
+    let [lui, ori] = li(REG_V0, SYSCALL_EXIT);
+    [lui, ori, SYSCALL]
+}
+
+/// Build a load module with the text and data. The labels are offsets into
+/// the text section.
+pub fn module(text: &[u32], data: &[u8], labels: &[(&str, u32)]) -> R2KModule {
+    let mut string_table = Vec::new();
+    let symbol_table = labels
+        .iter()
+        .map(|(name, offset)| {
+            let str_idx = string_table.len() as u32;
+            string_table.extend_from_slice(name.as_bytes());
+            string_table.push(0);
+
+            R2KSymbolEntry {
+                flags: SYM_DEF_SEEN | SYM_DEF_LABEL | SYM_GLOBAL | R2KSection::Text as u32,
+                value: *offset,
+                str_idx,
+            }
+        })
+        .collect();
+    let text_section: Vec<u8> = text.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+    let mut header = R2KModuleHeader {
+        entry: TEXT_OFFSET,
+        ..R2KModuleHeader::default()
+    };
+    header.section_sizes[TEXT_INDEX] = text_section.len() as u32;
+    header.section_sizes[DATA_INDEX] = data.len() as u32;
+
+    R2KModule {
+        header,
+        text_section,
+        rdata_section: Vec::new(),
+        data_section: data.to_vec(),
+        sdata_section: Vec::new(),
+        sbss_size: 0,
+        bss_size: 0,
+        relocation_section: Vec::new(),
+        reference_section: Vec::new(),
+        symbol_table,
+        string_table,
+    }
+}
+
+/// Create a processor running the text and data. It reads the input, and its
+/// output is written to the returned buffer.
+pub fn processor(
+    config: Config,
+    input: &str,
+    text: &[u32],
+    data: &[u8],
+) -> (Processor, SharedBuffer) {
+    let output = SharedBuffer::new();
+    let streams = Streams::new(
+        Box::new(Cursor::new(input.as_bytes().to_vec())),
+        Box::new(output.clone()),
+        Box::new(io::sink()),
+    );
+    let mut processor = Processor::with_streams(config, streams);
+    processor.load_rsim_module(&module(text, data, &[]));
+
+    (processor, output)
+}
+
+/// Run the processor until the program stops
+pub fn run(processor: &mut Processor) -> Result<StepOutcome, ExecutionError> {
+    loop {
+        match processor.step()? {
+            StepOutcome::Running => {}
+            outcome => return Ok(outcome),
+        }
+    }
+}
+
+/// Set the registers, then execute the instruction
+pub fn execute(
+    processor: &mut Processor,
+    registers: &[(u8, u32)],
+    instruction: u32,
+) -> Result<(), ExecutionErrorCause> {
+    for (register, value) in registers {
+        processor.registers.set(*register, *value);
+    }
+
+    processor.execute(Instruction(instruction))
+}
@@ -0,0 +1,752 @@
+//! Execution traces: one record per executed instruction, with the registers
+//! and memory it wrote. Traces are written as tab-separated text or as JSON
+//! lines, and can be parsed again to compare two runs.
+
+use crate::instruction::Instruction;
+use crate::memory_map::Access;
+use crate::processor::Processor;
+use crate::stats::json_string;
+use mips_types::constants::*;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter, Write as _};
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The format of a trace
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TraceFormat {
+    /// One line of tab-separated fields per record
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(TraceFormat::Text),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err("Expected text or json".to_string()),
+        }
+    }
+}
+
+/// A register written by an instruction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RegisterWrite {
+    /// The register's name (ex. "$t0", "$hi", "$f2", or "$epc")
+    pub register: String,
+    pub old_value: u32,
+    pub new_value: u32,
+}
+
+/// A range of memory written by an instruction
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryWrite {
+    pub address: u32,
+    pub size: u32,
+}
+
+/// An executed instruction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceRecord {
+    /// The number of instructions executed before this one
+    pub index: u64,
+    pub program_counter: u32,
+    /// The instruction word
+    pub instruction: u32,
+    pub disassembly: String,
+    /// The address as symbol+offset, if it has a symbol
+    pub symbol: Option<String>,
+    /// The registers whose values were changed
+    pub registers: Vec<RegisterWrite>,
+    pub memory: Vec<MemoryWrite>,
+}
+
+impl TraceRecord {
+    /// Parse a line of a trace, in either format
+    pub fn parse(line: &str) -> Result<Self, String> {
+        if line.trim_start().starts_with('{') {
+            Self::parse_json(line)
+        } else {
+            Self::parse_text(line)
+        }
+    }
+
+    /// Check if the record has the same effects as the other record: the same
+    /// instruction at the same address, writing the same registers and memory.
+    /// The index, disassembly, and symbol are not compared.
+    pub fn same_effects(&self, other: &TraceRecord) -> bool {
+        self.program_counter == other.program_counter
+            && self.instruction == other.instruction
+            && self.registers == other.registers
+            && self.memory == other.memory
+    }
+
+    /// Format the record as a JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"index\":{},\"pc\":{},\"instruction\":{},\"disassembly\":{},\"symbol\":{},\"registers\":[",
+            self.index,
+            self.program_counter,
+            self.instruction,
+            json_string(&self.disassembly),
+            match &self.symbol {
+                Some(symbol) => json_string(symbol),
+                None => "null".to_string(),
+            }
+        )
+        .unwrap();
+        for (i, write) in self.registers.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(
+                json,
+                "{}{{\"register\":{},\"old\":{},\"new\":{}}}",
+                separator,
+                json_string(&write.register),
+                write.old_value,
+                write.new_value
+            )
+            .unwrap();
+        }
+        json.push_str("],\"memory\":[");
+        for (i, write) in self.memory.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(
+                json,
+                "{}{{\"address\":{},\"size\":{}}}",
+                separator, write.address, write.size
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+
+        json
+    }
+
+    /// Parse a record written by `Display`
+    fn parse_text(line: &str) -> Result<Self, String> {
+        let mut fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+        // The trailing memory field is empty for most instructions, so it
+        // may have been trimmed
+        if fields.len() == 6 {
+            fields.push("");
+        }
+        let (index, program_counter, instruction, symbol, disassembly, registers, memory) =
+            match fields.as_slice() {
+                [index, program_counter, instruction, symbol, disassembly, registers, memory] => (
+                    *index,
+                    *program_counter,
+                    *instruction,
+                    *symbol,
+                    *disassembly,
+                    *registers,
+                    *memory,
+                ),
+                _ => return Err("Expected 7 tab-separated fields".to_string()),
+            };
+
+        Ok(Self {
+            index: index
+                .parse()
+                .map_err(|_| format!("Invalid index: {}", index))?,
+            program_counter: parse_hex(program_counter)?,
+            instruction: parse_hex(instruction)?,
+            disassembly: disassembly.to_string(),
+            symbol: match symbol {
+                "-" => None,
+                symbol => Some(symbol.to_string()),
+            },
+            registers: registers
+                .split_whitespace()
+                .map(|write| {
+                    let invalid = || format!("Invalid register write: {}", write);
+                    let (register, values) = write.split_once('=').ok_or_else(invalid)?;
+                    let (old_value, new_value) = values.split_once("->").ok_or_else(invalid)?;
+
+                    Ok(RegisterWrite {
+                        register: register.to_string(),
+                        old_value: parse_hex(old_value)?,
+                        new_value: parse_hex(new_value)?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            memory: memory
+                .split_whitespace()
+                .map(|write| {
+                    let (address, size) = write
+                        .split_once('/')
+                        .ok_or_else(|| format!("Invalid memory write: {}", write))?;
+
+                    Ok(MemoryWrite {
+                        address: parse_hex(address)?,
+                        size: size
+                            .parse()
+                            .map_err(|_| format!("Invalid memory write: {}", write))?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    /// Parse a record written by `to_json`
+    fn parse_json(line: &str) -> Result<Self, String> {
+        let mut parser = JsonParser {
+            input: line.as_bytes(),
+            position: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.input.len() {
+            return Err("Unexpected data after the record".to_string());
+        }
+
+        let number = |value: &JsonValue, name: &str| match value.get(name) {
+            Some(JsonValue::Number(number)) => Ok(*number),
+            _ => Err(format!("Expected a number for \"{}\"", name)),
+        };
+        let word = |value: &JsonValue, name: &str| {
+            number(value, name).and_then(|number| {
+                u32::try_from(number).map_err(|_| format!("\"{}\" is out of range", name))
+            })
+        };
+        let array = |name: &str| match value.get(name) {
+            Some(JsonValue::Array(values)) => Ok(values),
+            _ => Err(format!("Expected an array for \"{}\"", name)),
+        };
+
+        Ok(Self {
+            index: number(&value, "index")?,
+            program_counter: word(&value, "pc")?,
+            instruction: word(&value, "instruction")?,
+            disassembly: match value.get("disassembly") {
+                Some(JsonValue::String(disassembly)) => disassembly.clone(),
+                _ => return Err("Expected a string for \"disassembly\"".to_string()),
+            },
+            symbol: match value.get("symbol") {
+                Some(JsonValue::String(symbol)) => Some(symbol.clone()),
+                Some(JsonValue::Null) | None => None,
+                _ => return Err("Expected a string for \"symbol\"".to_string()),
+            },
+            registers: array("registers")?
+                .iter()
+                .map(|write| {
+                    Ok(RegisterWrite {
+                        register: match write.get("register") {
+                            Some(JsonValue::String(register)) => register.clone(),
+                            _ => return Err("Expected a string for \"register\"".to_string()),
+                        },
+                        old_value: word(write, "old")?,
+                        new_value: word(write, "new")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            memory: array("memory")?
+                .iter()
+                .map(|write| {
+                    Ok(MemoryWrite {
+                        address: word(write, "address")?,
+                        size: word(write, "size")?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        })
+    }
+}
+
+/// Format the record as tab-separated fields: the index, address, instruction
+/// word, symbol (or "-"), disassembly, register writes ("$t0=OLD->NEW"), and
+/// memory writes ("ADDRESS/SIZE")
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t0x{:08x}\t0x{:08x}\t{}\t{}\t",
+            self.index,
+            self.program_counter,
+            self.instruction,
+            self.symbol.as_deref().unwrap_or("-"),
+            self.disassembly
+        )?;
+        for (i, write) in self.registers.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(
+                f,
+                "{}{}=0x{:08x}->0x{:08x}",
+                separator, write.register, write.old_value, write.new_value
+            )?;
+        }
+        f.write_str("\t")?;
+        for (i, write) in self.memory.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{}0x{:08x}/{}", separator, write.address, write.size)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_hex(value: &str) -> Result<u32, String> {
+    value
+        .strip_prefix("0x")
+        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| format!("Invalid hex number: {}", value))
+}
+
+/// The CP0 registers in a trace
+const TRACE_CP0_REGISTERS: [(u8, &str); 4] = [
+    (CP0_STATUS, "$status"),
+    (CP0_CAUSE, "$cause"),
+    (CP0_EPC, "$epc"),
+    (CP0_BAD_VADDR, "$badvaddr"),
+];
+/// The number of registers in a trace: the general purpose registers, HI, LO,
+/// the floating point registers and condition flag, and the CP0 registers
+const TRACE_REGISTER_COUNT: usize = 32 + 2 + 32 + 1 + TRACE_CP0_REGISTERS.len();
+
+/// The values of the registers in a trace
+pub(crate) type TraceRegisters = [u32; TRACE_REGISTER_COUNT];
+
+/// Get the name of a register in `TraceRegisters`
+fn trace_register_name(index: usize) -> String {
+    match index {
+        0..=31 => REGISTER_NAMES[index].to_string(),
+        32 => "$hi".to_string(),
+        33 => "$lo".to_string(),
+        34..=65 => format!("$f{}", index - 34),
+        66 => "$fcc".to_string(),
+        _ => TRACE_CP0_REGISTERS[index - 67].1.to_string(),
+    }
+}
+
+/// Writes the trace records
+pub(crate) struct Tracer {
+    output: Box<dyn Write>,
+    format: TraceFormat,
+    /// The first error while writing. Nothing else is written after it.
+    error: Option<io::Error>,
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl Processor {
+    /// Write a trace record for each executed instruction to the output. This
+    /// also records the memory accesses made by each instruction (see
+    /// `memory_accesses`).
+    pub fn set_trace(&mut self, output: Box<dyn Write>, format: TraceFormat) {
+        self.record_memory_accesses();
+        self.tracer = Some(Box::new(Tracer {
+            output,
+            format,
+            error: None,
+        }));
+    }
+
+    /// Flush the trace, and get the first error while writing it
+    pub fn finish_trace(&mut self) -> io::Result<()> {
+        if let Some(tracer) = &mut self.tracer {
+            if let Some(e) = tracer.error.take() {
+                return Err(e);
+            }
+            tracer.output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the values of the registers in a trace
+    pub(crate) fn trace_registers(&self) -> TraceRegisters {
+        let mut values = [0; TRACE_REGISTER_COUNT];
+        for register in 0..32 {
+            values[register] = self.registers.get(register as u8);
+            values[34 + register] = self.cp1.get(register as u8);
+        }
+        values[32] = self.registers.hi_register;
+        values[33] = self.registers.lo_register;
+        values[66] = self.cp1.condition as u32;
+        for (i, (register, _)) in TRACE_CP0_REGISTERS.iter().enumerate() {
+            values[67 + i] = self.cp0.get(*register);
+        }
+
+        values
+    }
+
+    /// Write the trace record of the last executed instruction. `old_values`
+    /// are the registers before it was executed.
+    pub(crate) fn record_trace(
+        &mut self,
+        index: u64,
+        program_counter: u32,
+        instruction: Instruction,
+        old_values: &TraceRegisters,
+    ) {
+        let new_values = self.trace_registers();
+        let record = TraceRecord {
+            index,
+            program_counter,
+            instruction: instruction.0,
+            disassembly: instruction.stringify(program_counter),
+            symbol: self.symbols.describe(program_counter),
+            registers: old_values
+                .iter()
+                .zip(&new_values)
+                .enumerate()
+                .filter(|(_, (old_value, new_value))| old_value != new_value)
+                .map(|(i, (old_value, new_value))| RegisterWrite {
+                    register: trace_register_name(i),
+                    old_value: *old_value,
+                    new_value: *new_value,
+                })
+                .collect(),
+            memory: self
+                .memory_accesses()
+                .iter()
+                .filter(|memory_access| memory_access.access == Access::Write)
+                .map(|memory_access| MemoryWrite {
+                    address: memory_access.address,
+                    size: memory_access.size,
+                })
+                .collect(),
+        };
+
+        let tracer = match &mut self.tracer {
+            Some(tracer) if tracer.error.is_none() => tracer,
+            _ => return,
+        };
+        let result = match tracer.format {
+            TraceFormat::Text => writeln!(tracer.output, "{}", record),
+            TraceFormat::Json => writeln!(tracer.output, "{}", record.to_json()),
+        };
+        if let Err(e) = result {
+            tracer.error = Some(e);
+        }
+    }
+}
+
+/// A parsed JSON value
+#[derive(Clone, Debug, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    /// Only non-negative integers are used in traces
+    Number(u64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Get the value of an object's field
+    fn get(&self, name: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// A parser for the subset of JSON used by traces
+struct JsonParser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                if !self.consume(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let name = self.parse_string()?;
+                        self.expect(b':')?;
+                        fields.push((name, self.parse_value()?));
+                        if self.consume(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(JsonValue::Object(fields))
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                if !self.consume(b']') {
+                    loop {
+                        values.push(self.parse_value()?);
+                        if self.consume(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(JsonValue::Array(values))
+            }
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'0'..=b'9') => {
+                let start = self.position;
+                while let Some(b'0'..=b'9') = self.peek() {
+                    self.position += 1;
+                }
+                std::str::from_utf8(&self.input[start..self.position])
+                    .ok()
+                    .and_then(|digits| digits.parse().ok())
+                    .map(JsonValue::Number)
+                    .ok_or_else(|| "Invalid number".to_string())
+            }
+            _ => {
+                for (keyword, value) in [
+                    ("null", JsonValue::Null),
+                    ("true", JsonValue::Bool(true)),
+                    ("false", JsonValue::Bool(false)),
+                ] {
+                    if self.input[self.position..].starts_with(keyword.as_bytes()) {
+                        self.position += keyword.len();
+                        return Ok(value);
+                    }
+                }
+                Err(format!("Unexpected character at {}", self.position))
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut string = Vec::new();
+        loop {
+            let byte = self.next().ok_or("Unterminated string")?;
+            match byte {
+                b'"' => break,
+                b'\\' => match self.next().ok_or("Unterminated string")? {
+                    b'u' => {
+                        let digits = self
+                            .input
+                            .get(self.position..self.position + 4)
+                            .and_then(|digits| std::str::from_utf8(digits).ok())
+                            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                            .and_then(char::from_u32)
+                            .ok_or("Invalid escape")?;
+                        self.position += 4;
+                        string.extend(digits.to_string().bytes());
+                    }
+                    b'n' => string.push(b'\n'),
+                    b't' => string.push(b'\t'),
+                    b'r' => string.push(b'\r'),
+                    escaped @ (b'"' | b'\\' | b'/') => string.push(escaped),
+                    _ => return Err("Invalid escape".to_string()),
+                },
+                byte => string.push(byte),
+            }
+        }
+
+        String::from_utf8(string).map_err(|_| "Invalid string".to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    /// Skip whitespace, and consume the byte if it is next
+    fn consume(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.consume(byte) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' at {}", byte as char, self.position))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryWrite, RegisterWrite, TraceFormat, TraceRecord};
+    use crate::config::Config;
+    use crate::streams::SharedBuffer;
+    use crate::test_util::{exit, i_type, li, processor, run};
+    use mips_types::constants::*;
+
+    fn record() -> TraceRecord {
+        TraceRecord {
+            index: 41,
+            program_counter: 0x0040_0010,
+            instruction: 0xad09_0000,
+            disassembly: "sw $t1, 0($t0)".to_string(),
+            symbol: Some("main+0x10".to_string()),
+            registers: vec![
+                RegisterWrite {
+                    register: "$t0".to_string(),
+                    old_value: 0,
+                    new_value: 0xffff_ffff,
+                },
+                RegisterWrite {
+                    register: "$epc".to_string(),
+                    old_value: 0x0040_0000,
+                    new_value: 0x0040_0010,
+                },
+            ],
+            memory: vec![
+                MemoryWrite {
+                    address: 0x1000_0000,
+                    size: 4,
+                },
+                MemoryWrite {
+                    address: 0x1000_0006,
+                    size: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let record = record();
+        assert_eq!(TraceRecord::parse(&record.to_string()), Ok(record));
+
+        let empty = TraceRecord {
+            symbol: None,
+            registers: Vec::new(),
+            memory: Vec::new(),
+            ..self::record()
+        };
+        assert_eq!(TraceRecord::parse(&format!("{}\n", empty)), Ok(empty));
+
+        // The empty memory field may be trimmed
+        let no_memory = TraceRecord {
+            memory: Vec::new(),
+            ..self::record()
+        };
+        let line = no_memory.to_string();
+        assert_eq!(TraceRecord::parse(line.trim_end()), Ok(no_memory));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let record = record();
+        assert_eq!(TraceRecord::parse(&record.to_json()), Ok(record));
+
+        let escaped = TraceRecord {
+            disassembly: "\"quoted\"\t\\".to_string(),
+            symbol: None,
+            ..self::record()
+        };
+        assert_eq!(TraceRecord::parse(&escaped.to_json()), Ok(escaped));
+    }
+
+    #[test]
+    fn parse_invalid_records() {
+        for line in [
+            "",
+            "1\t0x00400000\t0x00000000\t-\tnop",
+            "x\t0x00400000\t0x00000000\t-\tnop\t\t",
+            "1\t0x00400000\t0x00000000\t-\tnop\t$t0=0x1\t",
+            "1\t0x00400000\t0x00000000\t-\tnop\t\t0x10000000",
+            "{\"index\":1}",
+            "{\"index\":1,\"pc\":4294967296,\"instruction\":0,\"disassembly\":\"nop\",\"symbol\":null,\"registers\":[],\"memory\":[]}",
+            "{\"index\":1,\"pc\":0,\"instruction\":0,\"disassembly\":\"nop\",\"symbol\":null,\"registers\":[],\"memory\":[]} x",
+        ] {
+            assert!(TraceRecord::parse(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn same_effects_ignores_the_index_and_names() {
+        let record = record();
+        let other = TraceRecord {
+            index: 0,
+            disassembly: String::new(),
+            symbol: None,
+            ..record.clone()
+        };
+        assert!(record.same_effects(&other));
+
+        let other = TraceRecord {
+            memory: Vec::new(),
+            ..record.clone()
+        };
+        assert!(!record.same_effects(&other));
+    }
+
+    #[test]
+    fn trace_a_store() {
+        for format in [TraceFormat::Text, TraceFormat::Json] {
+            let mut text = Vec::new();
+            text.extend(li(REG_A0, DATA_OFFSET));
+            text.extend(li(REG_A1, 7));
+            text.push(i_type(OP_SW, REG_A1, REG_A0, 4));
+            text.extend(exit());
+            let (mut processor, _) = processor(Config::default(), "", &text, &[0; 8]);
+            let trace = SharedBuffer::new();
+            processor.set_trace(Box::new(trace.clone()), format);
+
+            run(&mut processor).unwrap();
+            processor.finish_trace().unwrap();
+            let trace = String::from_utf8(trace.contents()).unwrap();
+            let records: Vec<TraceRecord> = trace
+                .lines()
+                .map(|line| TraceRecord::parse(line).unwrap())
+                .collect();
+
+            assert_eq!(records.len(), text.len());
+            let store = &records[4];
+            assert_eq!(store.index, 4);
+            assert_eq!(store.program_counter, TEXT_OFFSET + 16);
+            assert!(store.registers.is_empty());
+            assert_eq!(
+                store.memory,
+                [MemoryWrite {
+                    address: DATA_OFFSET + 4,
+                    size: 4
+                }]
+            );
+            assert_eq!(
+                records[3].registers,
+                [RegisterWrite {
+                    register: "$a1".to_string(),
+                    old_value: 0,
+                    new_value: 7,
+                }]
+            );
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Compare two execution traces (written by `mips-simulator --trace`), and
+//! report the first instruction where they diverge.
+
+use mips_simulator::trace::TraceRecord;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// The exit code used when the traces diverge
+const DIVERGED_EXIT_CODE: i32 = 1;
+/// The exit code used when a trace could not be read
+const ERROR_EXIT_CODE: i32 = 2;
+
+#[derive(StructOpt)]
+struct CliArgs {
+    /// The number of matching instructions to show before the divergence.
+    #[structopt(long, default_value = "3")]
+    context: usize,
+
+    /// The reference trace (ex. from the reference solution).
+    #[structopt(parse(from_os_str))]
+    expected: PathBuf,
+
+    /// The trace to check against the reference. Either trace can be text or
+    /// JSON lines.
+    #[structopt(parse(from_os_str))]
+    actual: PathBuf,
+}
+
+/// The records of a trace file
+struct TraceReader {
+    path: PathBuf,
+    lines: Lines<BufReader<File>>,
+    line_number: usize,
+}
+
+impl TraceReader {
+    fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines: BufReader::new(file).lines(),
+            line_number: 0,
+        })
+    }
+
+    /// Read the next record, skipping empty lines
+    fn next_record(&mut self) -> Result<Option<TraceRecord>, Box<dyn Error>> {
+        for line in &mut self.lines {
+            let line = line?;
+            self.line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            return TraceRecord::parse(&line).map(Some).map_err(|e| {
+                format!("{}:{}: {}", self.path.display(), self.line_number, e).into()
+            });
+        }
+
+        Ok(None)
+    }
+}
+
+/// Describe how the records differ
+fn describe_differences(expected: &TraceRecord, actual: &TraceRecord) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if expected.program_counter != actual.program_counter {
+        differences.push(format!(
+            "The address is 0x{:08x} instead of 0x{:08x}",
+            actual.program_counter, expected.program_counter
+        ));
+    }
+
+    if expected.instruction != actual.instruction {
+        differences.push(format!(
+            "The instruction is \"{}\" instead of \"{}\"",
+            actual.disassembly, expected.disassembly
+        ));
+    }
+
+    for write in &expected.registers {
+        match actual
+            .registers
+            .iter()
+            .find(|actual_write| actual_write.register == write.register)
+        {
+            Some(actual_write) if actual_write == write => {}
+            Some(actual_write) => differences.push(format!(
+                "{} is set to 0x{:08x} (from 0x{:08x}) instead of 0x{:08x} (from 0x{:08x})",
+                write.register,
+                actual_write.new_value,
+                actual_write.old_value,
+                write.new_value,
+                write.old_value
+            )),
+            None => differences.push(format!(
+                "{} is not set (expected 0x{:08x})",
+                write.register, write.new_value
+            )),
+        }
+    }
+
+    for write in &actual.registers {
+        if !expected
+            .registers
+            .iter()
+            .any(|expected_write| expected_write.register == write.register)
+        {
+            differences.push(format!(
+                "{} is set to 0x{:08x} (expected no change)",
+                write.register, write.new_value
+            ));
+        }
+    }
+
+    if expected.memory != actual.memory {
+        let describe = |record: &TraceRecord| {
+            if record.memory.is_empty() {
+                "nothing".to_string()
+            } else {
+                record
+                    .memory
+                    .iter()
+                    .map(|write| format!("{} bytes at 0x{:08x}", write.size, write.address))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        };
+
+        differences.push(format!(
+            "Memory written: {} instead of {}",
+            describe(actual),
+            describe(expected)
+        ));
+    }
+
+    differences
+}
+
+/// Compare the traces. Returns if they match.
+fn compare(args: &CliArgs) -> Result<bool, Box<dyn Error>> {
+    let mut expected = TraceReader::open(&args.expected)?;
+    let mut actual = TraceReader::open(&args.actual)?;
+    let mut context = VecDeque::with_capacity(args.context);
+    let mut count = 0;
+
+    // Find the first records which differ
+    let (expected_record, actual_record) = loop {
+        let expected_record = expected.next_record()?;
+        let actual_record = actual.next_record()?;
+
+        match (&expected_record, &actual_record) {
+            (None, None) => {
+                println!("The traces match ({} instructions)", count);
+                return Ok(true);
+            }
+            (Some(expected_record), Some(actual_record))
+                if expected_record.same_effects(actual_record) =>
+            {
+                count += 1;
+                if args.context > 0 {
+                    if context.len() == args.context {
+                        context.pop_front();
+                    }
+                    context.push_back(actual_record.clone());
+                }
+            }
+            _ => break (expected_record, actual_record),
+        }
+    };
+
+    println!("The traces diverge after {} matching instructions", count);
+    if !context.is_empty() {
+        println!("\nLast matching instructions:");
+        for record in &context {
+            println!("  {}", record);
+        }
+    }
+
+    let describe = |record: &Option<TraceRecord>| match record {
+        Some(record) => record.to_string(),
+        None => "(the trace ended)".to_string(),
+    };
+    println!("\nExpected:\n  {}", describe(&expected_record));
+    println!("Actual:\n  {}", describe(&actual_record));
+
+    if let (Some(expected_record), Some(actual_record)) = (&expected_record, &actual_record) {
+        println!();
+        for difference in describe_differences(expected_record, actual_record) {
+            println!("{}", difference);
+        }
+    }
+
+    Ok(false)
+}
+
+fn main() {
+    let args = CliArgs::from_args();
+
+    match compare(&args) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(DIVERGED_EXIT_CODE),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(ERROR_EXIT_CODE);
+        }
+    }
+}
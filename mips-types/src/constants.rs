@@ -15,6 +15,8 @@ pub const OP_ANDI: u8 = 0x0C;
 pub const OP_ORI: u8 = 0x0D;
 pub const OP_XORI: u8 = 0x0E;
 pub const OP_LUI: u8 = 0x0F;
+pub const OP_COP0: u8 = 0x10;
+pub const OP_COP1: u8 = 0x11;
 pub const OP_LB: u8 = 0x20;
 pub const OP_LH: u8 = 0x21;
 pub const OP_LWL: u8 = 0x22;
@@ -27,6 +29,8 @@ pub const OP_SH: u8 = 0x29;
 pub const OP_SWL: u8 = 0x2A;
 pub const OP_SW: u8 = 0x2B;
 pub const OP_SWR: u8 = 0x2E;
+pub const OP_LWC1: u8 = 0x31;
+pub const OP_SWC1: u8 = 0x39;
 
 // R-type function codes
 pub const FUNCTION_SLL: u8 = 0x00;
@@ -64,14 +68,78 @@ pub const BCOND_RT_BGEZ: u8 = 0x01;
 pub const BCOND_RT_BLTZAL: u8 = 0x10;
 pub const BCOND_RT_BGEZAL: u8 = 0x11;
 
+// OP_COP0 rs values
+pub const COP0_RS_MF: u8 = 0x00;
+pub const COP0_RS_MT: u8 = 0x04;
+/// Set for coprocessor operations (ex. rfe), which use the function field
+pub const COP0_RS_CO: u8 = 0x10;
+
+// OP_COP0 function codes (when rs is COP0_RS_CO)
+pub const COP0_FUNCTION_RFE: u8 = 0x10;
+
+// OP_COP1 rs values
+pub const COP1_RS_MF: u8 = 0x00;
+pub const COP1_RS_MT: u8 = 0x04;
+/// Branch on the floating point condition flag (bc1f/bc1t)
+pub const COP1_RS_BC: u8 = 0x08;
+/// Set for arithmetic operations on single precision values
+pub const COP1_FMT_S: u8 = 0x10;
+/// Set for arithmetic operations on double precision values
+pub const COP1_FMT_D: u8 = 0x11;
+/// Set for conversions from words (fixed point values)
+pub const COP1_FMT_W: u8 = 0x14;
+
+// OP_COP1 rt values (when rs is COP1_RS_BC)
+pub const COP1_BC_RT_BC1F: u8 = 0x00;
+pub const COP1_BC_RT_BC1T: u8 = 0x01;
+
+// OP_COP1 function codes (when rs is a format)
+pub const COP1_FUNCTION_ADD: u8 = 0x00;
+pub const COP1_FUNCTION_SUB: u8 = 0x01;
+pub const COP1_FUNCTION_MUL: u8 = 0x02;
+pub const COP1_FUNCTION_DIV: u8 = 0x03;
+pub const COP1_FUNCTION_ABS: u8 = 0x05;
+pub const COP1_FUNCTION_MOV: u8 = 0x06;
+pub const COP1_FUNCTION_NEG: u8 = 0x07;
+pub const COP1_FUNCTION_CVT_S: u8 = 0x20;
+pub const COP1_FUNCTION_CVT_D: u8 = 0x21;
+pub const COP1_FUNCTION_CVT_W: u8 = 0x24;
+pub const COP1_FUNCTION_C_EQ: u8 = 0x32;
+pub const COP1_FUNCTION_C_LT: u8 = 0x3C;
+pub const COP1_FUNCTION_C_LE: u8 = 0x3E;
+
+// Coprocessor 0 register numbers
+pub const CP0_BAD_VADDR: u8 = 8;
+pub const CP0_STATUS: u8 = 12;
+pub const CP0_CAUSE: u8 = 13;
+pub const CP0_EPC: u8 = 14;
+
+// Exception codes (stored in the Cause register)
+pub const EXC_TLB_MODIFIED: u8 = 1;
+pub const EXC_ADDRESS_LOAD: u8 = 4;
+pub const EXC_ADDRESS_STORE: u8 = 5;
+pub const EXC_BUS_ERROR_FETCH: u8 = 6;
+pub const EXC_BUS_ERROR_DATA: u8 = 7;
+pub const EXC_SYSCALL: u8 = 8;
+pub const EXC_BREAKPOINT: u8 = 9;
+pub const EXC_RESERVED_INSTRUCTION: u8 = 10;
+pub const EXC_OVERFLOW: u8 = 12;
+
 // Register numbers
 pub const REG_V0: u8 = 2;
 pub const REG_A0: u8 = 4;
 pub const REG_A1: u8 = 5;
+pub const REG_A2: u8 = 6;
 /// The stack pointer register
 pub const REG_SP: u8 = 29;
 pub const REG_RA: u8 = 31;
 
+// Floating point register numbers
+/// Floating point results are returned in $f0 (and $f1 for doubles)
+pub const REG_F0: u8 = 0;
+/// The floating point argument register
+pub const REG_F12: u8 = 12;
+
 pub static REGISTER_NAMES: [&str; 32] = [
     "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3", "$t0", "$t1", "$t2", "$t3", "$t4",
     "$t5", "$t6", "$t7", "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7", "$t8", "$t9",
@@ -80,17 +148,47 @@ pub static REGISTER_NAMES: [&str; 32] = [
 
 // Syscall codes
 pub const SYSCALL_PRINT_INT: u32 = 1;
+pub const SYSCALL_PRINT_FLOAT: u32 = 2;
+pub const SYSCALL_PRINT_DOUBLE: u32 = 3;
 pub const SYSCALL_PRINT_STR: u32 = 4;
 pub const SYSCALL_READ_INT: u32 = 5;
+pub const SYSCALL_READ_FLOAT: u32 = 6;
+pub const SYSCALL_READ_DOUBLE: u32 = 7;
 pub const SYSCALL_READ_STRING: u32 = 8;
+pub const SYSCALL_SBRK: u32 = 9;
 pub const SYSCALL_EXIT: u32 = 10;
+pub const SYSCALL_PRINT_CHAR: u32 = 11;
+pub const SYSCALL_READ_CHAR: u32 = 12;
+pub const SYSCALL_OPEN: u32 = 13;
+pub const SYSCALL_READ: u32 = 14;
+pub const SYSCALL_WRITE: u32 = 15;
+pub const SYSCALL_CLOSE: u32 = 16;
 pub const SYSCALL_EXIT2: u32 = 17;
+// MARS extensions
+pub const SYSCALL_TIME: u32 = 30;
+pub const SYSCALL_SLEEP: u32 = 32;
+pub const SYSCALL_PRINT_HEX: u32 = 34;
+pub const SYSCALL_PRINT_BINARY: u32 = 35;
+pub const SYSCALL_PRINT_UNSIGNED: u32 = 36;
+pub const SYSCALL_SET_SEED: u32 = 40;
+pub const SYSCALL_RANDOM_INT: u32 = 41;
+pub const SYSCALL_RANDOM_INT_RANGE: u32 = 42;
 
 // Memory offsets
 /// The bottom of the stack
-pub const STACK_BOTTOM: u32 = 0x7fffefff;
+pub const STACK_BOTTOM: u32 = 0x7fffeffc;
+/// The lowest address the stack can grow down to (8MB below the kernel)
+pub const STACK_LIMIT: u32 = 0x7f800000;
 pub const TEXT_OFFSET: u32 = 0x00400000;
 pub const DATA_OFFSET: u32 = 0x10000000;
+pub const KTEXT_OFFSET: u32 = 0x80000000;
+pub const KDATA_OFFSET: u32 = 0x90000000;
+/// Where the processor jumps to when an exception is raised
+pub const EXCEPTION_VECTOR: u32 = 0x80000080;
+
+// Module flags
+/// The module's text and data sections are kernel sections (.ktext/.kdata)
+pub const MODULE_KERNEL: u32 = 0x01;
 
 /// The entrypoint of R2K programs
 pub const R2K_ENTRYPOINT: &str = "__r2k__entry__";
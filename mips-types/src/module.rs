@@ -1,4 +1,7 @@
-use crate::constants::{DATA_OFFSET, SYM_DEF_LABEL, SYM_DEF_SEEN, SYM_MODE_MASK, TEXT_OFFSET};
+use crate::constants::{
+    DATA_OFFSET, KDATA_OFFSET, KTEXT_OFFSET, MODULE_KERNEL, SYM_DEF_LABEL, SYM_DEF_SEEN,
+    SYM_GLOBAL, SYM_MODE_MASK, TEXT_OFFSET,
+};
 use std::convert::{TryFrom, TryInto};
 use std::io;
 use std::io::{Read, Write};
@@ -131,6 +134,11 @@ impl R2KModule {
         self.header.entry != 0
     }
 
+    /// Check if this module holds kernel code and data (.ktext/.kdata)
+    pub fn is_kernel_module(&self) -> bool {
+        self.header.flags & MODULE_KERNEL != 0
+    }
+
     /// Get a mutable reference to the given section's data. If the section does
     /// not hold data (ex. undefined, bss, external) then None is returned.
     pub fn get_mut_section(&mut self, section: R2KSection) -> Option<&mut [u8]> {
@@ -147,20 +155,29 @@ impl R2KModule {
         }
     }
 
-    /// Get the offset of the given section, if it holds data.
+    /// Get the offset of the given section, if it is placed in memory. The
+    /// uninitialized data sections (sbss and bss) come after sdata. Kernel
+    /// modules are placed in the kernel segments.
     pub fn get_section_offset(&self, section: R2KSection) -> Option<u32> {
+        let (text_offset, data_offset) = if self.is_kernel_module() {
+            (KTEXT_OFFSET, KDATA_OFFSET)
+        } else {
+            (TEXT_OFFSET, DATA_OFFSET)
+        };
+        let rdata_offset = data_offset;
+        let data_offset = rdata_offset + self.rdata_section.len() as u32;
+        let sdata_offset = data_offset + self.data_section.len() as u32;
+        let sbss_offset = sdata_offset + self.sdata_section.len() as u32;
+        let bss_offset = sbss_offset + self.sbss_size;
+
         match section {
-            R2KSection::Text => Some(TEXT_OFFSET),
-            R2KSection::RData => Some(DATA_OFFSET),
-            R2KSection::Data => Some(DATA_OFFSET + self.rdata_section.len() as u32),
-            R2KSection::SData => {
-                Some(DATA_OFFSET + self.rdata_section.len() as u32 + self.data_section.len() as u32)
-            }
-            R2KSection::Undefined
-            | R2KSection::SBss
-            | R2KSection::Bss
-            | R2KSection::Absolute
-            | R2KSection::External => None,
+            R2KSection::Text => Some(text_offset),
+            R2KSection::RData => Some(rdata_offset),
+            R2KSection::Data => Some(data_offset),
+            R2KSection::SData => Some(sdata_offset),
+            R2KSection::SBss => Some(sbss_offset),
+            R2KSection::Bss => Some(bss_offset),
+            R2KSection::Undefined | R2KSection::Absolute | R2KSection::External => None,
         }
     }
 }
@@ -388,6 +405,11 @@ impl R2KSymbolEntry {
     pub fn has_definition(&self) -> bool {
         self.flags & SYM_DEF_SEEN != 0
     }
+
+    /// Check if this symbol was declared global
+    pub fn is_global(&self) -> bool {
+        self.flags & SYM_GLOBAL != 0
+    }
 }
 
 fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
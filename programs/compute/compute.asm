@@ -0,0 +1,80 @@
+# Runs a sieve of Eratosthenes several times and prints the number of primes
+# found. Used as a compute/memory heavy benchmark for the simulator.
+
+PRINT_INT = 1
+SIZE = 100000
+ROUNDS = 10
+
+    .globl main
+    .data
+
+sieve:
+    .space SIZE
+
+    .text
+
+main:
+    li $s2, ROUNDS
+    la $s1, sieve
+    li $t9, SIZE
+
+round:
+    # Mark every number as a possible prime
+    move $t0, $s1
+    li $t1, SIZE
+    li $t2, 1
+clear:
+    sb $t2, 0($t0)
+    addiu $t0, $t0, 1
+    addiu $t1, $t1, -1
+    bne $t1, $zero, clear
+    nop # In case delay slots are enabled
+
+    # Cross off the multiples of each prime
+    li $s0, 2
+outer:
+    mul $t0, $s0, $s0
+    slt $t1, $t0, $t9
+    beq $t1, $zero, count
+    nop
+    addu $t3, $s1, $s0
+    lb $t4, 0($t3)
+    beq $t4, $zero, next
+    nop
+inner:
+    addu $t3, $s1, $t0
+    sb $zero, 0($t3)
+    addu $t0, $t0, $s0
+    slt $t1, $t0, $t9
+    bne $t1, $zero, inner
+    nop
+next:
+    addiu $s0, $s0, 1
+    j outer
+    nop
+
+    # Count the primes
+count:
+    li $v1, 0
+    li $t0, 2
+count_loop:
+    addu $t3, $s1, $t0
+    lb $t4, 0($t3)
+    addu $v1, $v1, $t4
+    addiu $t0, $t0, 1
+    slt $t1, $t0, $t9
+    bne $t1, $zero, count_loop
+    nop
+
+    addiu $s2, $s2, -1
+    bne $s2, $zero, round
+    nop
+
+    # Print the number of primes
+    move $a0, $v1
+    li $v0, PRINT_INT
+    syscall
+
+    li $v0, 0
+    jr $ra
+    nop